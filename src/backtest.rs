@@ -0,0 +1,340 @@
+use crate::clock::Clock;
+use crate::error::Result;
+use crate::exchange_info::SymbolInfo;
+use crate::executor::Executor;
+use crate::fill::{FillModel, Timing};
+use crate::sizing::Sizing;
+use crate::strategy::Strategy;
+use crate::types::{Candle, CandleLine, Event, Execution, Journal, Market, Order, OrderResponse};
+
+#[derive(Debug, Clone)]
+pub struct BacktestResult {
+    journal: Journal,
+    start: Market,
+    end: Market,
+}
+
+impl BacktestResult {
+    pub fn get_journal(&self) -> &Journal {
+        &self.journal
+    }
+    pub fn get_start_market(&self) -> Market {
+        self.start.clone()
+    }
+    pub fn get_end_market(&self) -> Market {
+        self.end.clone()
+    }
+    pub fn get_executions(&self) -> Vec<Execution> {
+        self.journal.get_executions()
+    }
+    pub fn get_return(&self) -> f64 {
+        // Change of equity from the first to the last candle, in B
+        let start = self.start.equity();
+        if start == 0.0 {
+            0.0
+        } else {
+            (self.end.equity() - start) / start
+        }
+    }
+}
+
+pub struct Backtester {
+    strategy: Box<dyn Strategy>,
+    market: Market,
+    sizing: Sizing,
+    info: Option<SymbolInfo>,
+    fill_model: FillModel,
+}
+
+impl Backtester {
+    pub fn new(strategy: Box<dyn Strategy>, market: Market) -> Backtester {
+        Backtester {
+            strategy,
+            market,
+            sizing: Sizing::default(),
+            info: None,
+            fill_model: FillModel::new(),
+        }
+    }
+    pub fn with_sizing(mut self, sizing: Sizing) -> Backtester {
+        self.sizing = sizing;
+        self
+    }
+    pub fn with_fill_model(mut self, fill_model: FillModel) -> Backtester {
+        self.fill_model = fill_model;
+        self
+    }
+    pub fn with_symbol_info(mut self, info: SymbolInfo) -> Backtester {
+        // Orders are rounded to and checked against its filters, without it they are not
+        self.info = Some(info);
+        self
+    }
+
+    fn symbol_info(&self) -> SymbolInfo {
+        self.info.clone().unwrap_or_else(|| SymbolInfo {
+            symbol: "BACKTEST".to_string(),
+            status: "TRADING".to_string(),
+            base_asset: "A".to_string(),
+            base_asset_precision: 8,
+            quote_asset: "B".to_string(),
+            quote_asset_precision: 8,
+            order_types: vec![],
+            iceberg_allowed: true,
+            oco_allowed: true,
+            is_spot_trading_allowed: true,
+            filters: vec![],
+        })
+    }
+
+    pub fn run(&mut self, candles: &CandleLine) -> Result<BacktestResult> {
+        // Every run starts from the same market and a fresh strategy
+        self.strategy.reset();
+        let mut start = self.market.clone();
+        if !candles.is_empty() {
+            start.update_ratio(candles.first().open());
+        }
+        let mut run = Run {
+            executor: Executor::new(self.symbol_info()).with_sizing(self.sizing),
+            market: start.clone(),
+            journal: Journal::new().with_start_equity(start.equity()),
+            fill_model: self.fill_model,
+            due: vec![],
+            clock: Clock::new(),
+        };
+        for candle in candles.all().iter() {
+            run.candle(self.strategy.as_mut(), candle)?;
+        }
+        Ok(BacktestResult {
+            journal: run.journal,
+            start,
+            end: run.market,
+        })
+    }
+}
+
+struct Run {
+    executor: Executor,
+    market: Market,
+    journal: Journal,
+    fill_model: FillModel,
+    // Orders waiting for the open of the next candle to be placed
+    due: Vec<Order>,
+    clock: Clock,
+}
+
+impl Run {
+    fn report(&mut self, response: &OrderResponse) {
+        if let OrderResponse::Full(full) = response {
+            let timestamp = full.result.transact_time;
+            self.executor.report(response, timestamp);
+            for fill in full.fills.iter() {
+                self.journal
+                    .put_execution(Execution::from_fill(timestamp, full.result.side, fill));
+            }
+        }
+    }
+
+    fn place(&mut self, order: Order, mid: f64, candle: &Candle) {
+        self.market.update_ratio(mid);
+        let response = self.market.place(order, &self.fill_model, candle);
+        self.report(&response);
+    }
+
+    fn exits(&mut self, candle: &Candle) -> Result<()> {
+        if let Some((price, order)) = self
+            .executor
+            .check_exit(candle, &self.market, &self.clock)?
+        {
+            // Stop or target turns into a market order once its price is reached
+            self.place(order, price, candle);
+        }
+        Ok(())
+    }
+
+    fn candle(&mut self, strategy: &mut dyn Strategy, candle: &Candle) -> Result<()> {
+        let timestamp = candle.timestamp();
+        self.executor.on_candle(candle);
+        for order in std::mem::take(&mut self.due) {
+            self.place(order, candle.open(), candle);
+        }
+        // Exits and orders resting from previous candles are filled next
+        self.exits(candle)?;
+        let responses = self.market.match_orders(&self.fill_model, candle);
+        for response in responses.iter() {
+            self.report(response);
+        }
+        // Order of prices inside the candle is unknown, position opened by a resting
+        // order may have hit its stop in the same candle and is assumed to do so
+        if !responses.is_empty() {
+            self.exits(candle)?;
+        }
+        self.market.update_ratio(candle.close());
+        let signal = strategy.on_candle(candle);
+        // Newer signal replaces orders which did not fill
+        if !self.executor.is_repeated(&signal) {
+            for order in self.market.cancel_all() {
+                self.executor
+                    .forget(&order.get_new_client_order_id().unwrap_or_default());
+            }
+        }
+        let orders = self
+            .executor
+            .on_signal(&signal, &self.market, candle.close(), &self.clock)?;
+        for order in orders {
+            match self.fill_model.get_timing() {
+                Timing::NextBarOpen => self.due.push(order),
+                Timing::SameBarClose => self.place(order, candle.close(), candle),
+            }
+        }
+        self.market.update_ratio(candle.close());
+        self.journal.put(Event::new(
+            timestamp as usize,
+            signal,
+            self.market.clone(),
+            *candle,
+        ));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fill::Slippage;
+    use crate::types::{OrderSide, Signal};
+
+    // Plays given signals one per candle, then sleeps
+    struct Script {
+        signals: Vec<Signal>,
+        index: usize,
+    }
+
+    impl Script {
+        fn new(signals: Vec<Signal>) -> Box<Script> {
+            Box::new(Script { signals, index: 0 })
+        }
+    }
+
+    impl Strategy for Script {
+        fn name(&self) -> &str {
+            "script"
+        }
+        fn window(&self) -> usize {
+            1
+        }
+        fn on_candle(&mut self, _candle: &Candle) -> Signal {
+            self.index += 1;
+            self.signals
+                .get(self.index - 1)
+                .cloned()
+                .unwrap_or(Signal::Sleep)
+        }
+        fn reset(&mut self) {
+            self.index = 0;
+        }
+    }
+
+    fn candles(closes: &[f64]) -> CandleLine {
+        CandleLine::new_from_vec(
+            closes
+                .iter()
+                .enumerate()
+                .map(|(i, c)| Candle::new(i as u64 * 60_000, *c, *c + 1.0, *c - 1.0, *c, 1.0))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn round_trip() {
+        let strategy = Script::new(vec![Signal::long(), Signal::Sleep, Signal::Flat]);
+        let market = Market::new(0.0, 1000.0, 1.0, 0.0, 0.0, 0.0);
+        let mut backtester = Backtester::new(strategy, market);
+        let line = candles(&[100.0, 105.0, 110.0, 120.0]);
+        let result = backtester.run(&line).unwrap();
+        assert_eq!(result.get_journal().get_all().len(), 4);
+        let executions = result.get_executions();
+        assert_eq!(executions.len(), 2);
+        assert_eq!(executions[0].get_side(), OrderSide::BUY);
+        assert_eq!(executions[0].get_price(), 100.0);
+        assert_eq!(executions[1].get_timestamp(), 120_000);
+        assert!((result.get_end_market().get_b_amount() - 1100.0).abs() < 1e-9);
+        assert!((result.get_return() - 0.1).abs() < 1e-9);
+        let trades = result.get_journal().trades();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].get_bars(), 2);
+        assert!((trades[0].get_pnl() - 100.0).abs() < 1e-9);
+        assert!((trades[0].get_mfe() - 0.1).abs() < 1e-9);
+        // Runs do not leak into each other
+        let again = backtester.run(&line).unwrap();
+        assert_eq!(again.get_executions(), executions);
+    }
+
+    #[test]
+    fn next_bar_open() {
+        let strategy = Script::new(vec![Signal::long(), Signal::Sleep, Signal::Flat]);
+        let market = Market::new(0.0, 1000.0, 1.0, 0.0, 0.0, 0.0);
+        let line = CandleLine::new_from_vec(
+            [
+                (99.0, 100.0),
+                (104.0, 105.0),
+                (109.0, 110.0),
+                (119.0, 120.0),
+            ]
+            .iter()
+            .enumerate()
+            .map(|(i, (o, c))| Candle::new(i as u64 * 60_000, *o, *c, *o, *c, 100.0))
+            .collect(),
+        );
+        let model = FillModel::new()
+            .with_timing(Timing::NextBarOpen)
+            .with_spread(20.0)
+            .with_slippage(Slippage::Bps(10.0));
+        let result = Backtester::new(strategy, market)
+            .with_fill_model(model)
+            .run(&line)
+            .unwrap();
+        let executions = result.get_executions();
+        assert_eq!(executions[0].get_timestamp(), 60_000);
+        assert!((executions[0].get_price() - 104.0 * 1.001 * 1.001).abs() < 1e-9);
+        // Sized at the close of 100, buying at the open costs more than there is
+        assert!(executions[0].get_quantity() < 10.0);
+        assert_eq!(executions[1].get_timestamp(), 180_000);
+        assert!((executions[1].get_price() - 119.0 * 0.999 * 0.999).abs() < 1e-9);
+        assert!(result.get_end_market().get_a_amount() < 1e-9);
+    }
+
+    #[test]
+    fn stops_and_limits() {
+        let strategy = Script::new(vec![Signal::long().limit(98.5).stop_loss(96.0)]);
+        let market = Market::new(0.0, 1000.0, 1.0, 0.0, 0.0, 0.001);
+        let result = Backtester::new(strategy, market.clone())
+            .with_sizing(Sizing::FractionOfBalance(0.5))
+            .run(&candles(&[100.0, 99.0, 97.0, 94.0]))
+            .unwrap();
+        let executions = result.get_executions();
+        // Limit waits for the low of the second candle, stop closes it on the third
+        assert_eq!(executions.len(), 2);
+        assert_eq!(executions[0].get_timestamp(), 60_000);
+        assert_eq!(executions[0].get_price(), 98.5);
+        // Sized at the close the signal came with
+        assert_eq!(executions[0].get_quantity(), 5.0);
+        assert!((executions[0].get_fee() - 0.005 * 98.5).abs() < 1e-9);
+        assert_eq!(executions[1].get_side(), OrderSide::SELL);
+        assert_eq!(executions[1].get_timestamp(), 120_000);
+        assert_eq!(executions[1].get_price(), 96.0);
+        assert!(result.get_return() < 0.0);
+
+        // Opening under the limit fills at the open, stop in the same candle is taken
+        // as hit after the entry
+        let strategy = Script::new(vec![Signal::long().limit(98.5).stop_loss(97.5)]);
+        let result = Backtester::new(strategy, market)
+            .run(&candles(&[100.0, 98.0]))
+            .unwrap();
+        let prices: Vec<f64> = result
+            .get_executions()
+            .iter()
+            .map(|e| e.get_price())
+            .collect();
+        assert_eq!(prices, vec![98.0, 97.5]);
+    }
+}
@@ -0,0 +1,626 @@
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use reqwest::{self, Client, Method, RequestBuilder};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{self, json, Value};
+use sha2::Sha256;
+use std::sync::Mutex;
+use tungstenite::client::AutoStream;
+use tungstenite::{connect, Message, WebSocket};
+
+use crate::builder::{OcoOrder, OcoResponse};
+use crate::clock::Clock;
+use crate::error::{ApiError, ApiErrorCode, Error, Result};
+use crate::exchange::{Exchange, KlineStream, KlineUpdate};
+use crate::exchange_info::{ExchangeInfo, SymbolInfo};
+use crate::types::{Balance, Broker, Candle, Order, OrderInfo, OrderRespType, OrderResponse};
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub fn sign(secret: &str, payload: &str) -> String {
+    // HMAC-SHA256 of the payload keyed with the secret, hex encoded
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key size");
+    mac.update(payload.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+pub fn signed_query(broker: &Broker, query: &str) -> String {
+    // Append signature of the whole query string as the last parameter
+    format!(
+        "{}&signature={}",
+        query,
+        sign(&broker.get_secret_key(), query)
+    )
+}
+
+pub fn order_query(order: &Order) -> String {
+    serde_urlencoded::to_string(order).expect("Order always serializes to a query string")
+}
+
+pub fn socket_sub_payload(symbol: &str, interval: &str) -> String {
+    let payload = json!({"method":"SUBSCRIBE",
+    "params":[format!("{}@kline_{}",symbol.to_lowercase(),interval)],
+    "id":1});
+    payload.to_string()
+}
+
+fn field_f64(row: &[Value], index: usize) -> Result<f64> {
+    // Decimal fields are sent as strings
+    row.get(index)
+        .and_then(|v| v.as_str())
+        .and_then(|v| v.parse::<f64>().ok())
+        .ok_or_else(|| Error::Shape(format!("kline field {} is not a decimal string", index)))
+}
+
+pub fn parse_kline_row(row: &[Value]) -> Result<Candle> {
+    let timestamp = row
+        .first()
+        .and_then(|v| v.as_u64())
+        .ok_or_else(|| Error::Shape("kline open time is not an integer".to_string()))?;
+    Ok(Candle::new(
+        timestamp,
+        field_f64(row, 1)?,
+        field_f64(row, 2)?,
+        field_f64(row, 3)?,
+        field_f64(row, 4)?,
+        field_f64(row, 5)?,
+    ))
+}
+
+pub fn parse_klines(data: &str) -> Result<Vec<Candle>> {
+    let rows: Vec<Vec<Value>> = serde_json::from_str(data)?;
+    rows.iter().map(|row| parse_kline_row(row)).collect()
+}
+
+pub struct Binance {
+    broker: Broker,
+    client: Client,
+    clock: Mutex<Clock>,
+}
+
+impl Binance {
+    pub fn new(broker: Broker) -> Binance {
+        Binance {
+            broker,
+            client: Client::new(),
+            clock: Mutex::new(Clock::new()),
+        }
+    }
+    pub fn get_broker(&self) -> &Broker {
+        &self.broker
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<String> {
+        // Exchange reports failures as {"code":..,"msg":..} along with 4xx/5xx status
+        let res = request.send().await?;
+        let status = res.status();
+        let body = res.text().await?;
+        if status.is_success() {
+            Ok(body)
+        } else {
+            match ApiError::from_payload(&body) {
+                Some(e) => Err(Error::Api(e)),
+                None => Err(Error::Shape(format!("HTTP {} with body {}", status, body))),
+            }
+        }
+    }
+
+    async fn get<T: DeserializeOwned>(&self, endpoint: &str, query: &Value) -> Result<T> {
+        let request = self
+            .client
+            .get(self.broker.get_rest_api_url() + endpoint)
+            .query(query);
+        Ok(serde_json::from_str(&self.send(request).await?)?)
+    }
+
+    async fn signed_request(&self, method: Method, endpoint: &str, query: &str) -> Result<String> {
+        let url = format!(
+            "{}{}?{}",
+            self.broker.get_rest_api_url(),
+            endpoint,
+            signed_query(&self.broker, query)
+        );
+        let request = self
+            .client
+            .request(method, url)
+            .header("X-MBX-APIKEY", self.broker.get_api_key());
+        let res = self.send(request).await;
+        if let Err(Error::Api(e)) = &res {
+            if e.code == ApiErrorCode::InvalidTimestamp {
+                // Our idea of exchange time is off, resync before the next request
+                self.clock.lock().unwrap().invalidate();
+            }
+        }
+        res
+    }
+
+    fn windowed_query(&self, query: String, recv_window: Option<u64>) -> String {
+        // Order without its own recvWindow gets the one of the clock
+        match recv_window {
+            Some(_) => query,
+            None => format!("{}&recvWindow={}", query, self.clock().recv_window_ms()),
+        }
+    }
+
+    async fn timestamp_query(&self, query: &str) -> Result<String> {
+        // Appends timestamp and recvWindow derived from the synchronized clock
+        let clock = self.synced_clock().await?;
+        let prefix = if query.is_empty() { "" } else { "&" };
+        Ok(format!(
+            "{}{}timestamp={}&recvWindow={}",
+            query,
+            prefix,
+            clock.now_exchange_ms(),
+            clock.recv_window_ms()
+        ))
+    }
+}
+
+#[async_trait]
+impl Exchange for Binance {
+    fn name(&self) -> String {
+        self.broker.get_name()
+    }
+
+    fn clock(&self) -> Clock {
+        *self.clock.lock().unwrap()
+    }
+
+    async fn sync_clock(&self) -> Result<Clock> {
+        let sent = Clock::local_ms();
+        let server = self.get_server_time().await?;
+        let received = Clock::local_ms();
+        let mut clock = self.clock.lock().unwrap();
+        clock.record_sample(sent, server, received);
+        Ok(*clock)
+    }
+
+    async fn get_candles(&self, symbol: &str, interval: &str, limit: usize) -> Result<Vec<Candle>> {
+        let payload = json!({
+            "symbol":symbol.to_uppercase(),"interval":interval,"limit":limit});
+        let request = self
+            .client
+            .get(self.broker.get_rest_api_url() + "klines")
+            .query(&payload);
+        parse_klines(&self.send(request).await?)
+    }
+
+    async fn get_candles_range(
+        &self,
+        symbol: &str,
+        interval: &str,
+        start_ms: u64,
+        end_ms: u64,
+        limit: usize,
+    ) -> Result<Vec<Candle>> {
+        let payload = json!({
+            "symbol":symbol.to_uppercase(),"interval":interval,
+            "startTime":start_ms,"endTime":end_ms,"limit":limit});
+        let request = self
+            .client
+            .get(self.broker.get_rest_api_url() + "klines")
+            .query(&payload);
+        parse_klines(&self.send(request).await?)
+    }
+
+    async fn get_server_time(&self) -> Result<u64> {
+        let res: Value = self.get("time", &json!({})).await?;
+        res["serverTime"]
+            .as_u64()
+            .ok_or_else(|| Error::Shape("serverTime is missing".to_string()))
+    }
+
+    async fn get_exchange_info(&self) -> Result<ExchangeInfo> {
+        self.get("exchangeInfo", &json!({})).await
+    }
+
+    async fn get_symbol_info(&self, symbol: &str) -> Result<SymbolInfo> {
+        let info: ExchangeInfo = self
+            .get("exchangeInfo", &json!({"symbol":symbol.to_uppercase()}))
+            .await?;
+        info.symbol(symbol)
+            .cloned()
+            .ok_or_else(|| Error::Shape(format!("No symbol info for {}", symbol)))
+    }
+
+    async fn place_order(&self, order: &Order) -> Result<OrderResponse> {
+        let query = self.windowed_query(order_query(order), order.get_recv_window());
+        let res = self.signed_request(Method::POST, "order", &query).await?;
+        // Shape of the response depends on newOrderRespType sent with the order
+        Ok(match order.get_resp_type() {
+            OrderRespType::ACK => OrderResponse::Ack(serde_json::from_str(&res)?),
+            OrderRespType::RESULT => OrderResponse::Result(serde_json::from_str(&res)?),
+            OrderRespType::FULL => OrderResponse::Full(serde_json::from_str(&res)?),
+        })
+    }
+
+    async fn test_order(&self, order: &Order) -> Result<()> {
+        // Validated and signed by the exchange but never sent to the matching engine
+        let query = self.windowed_query(order_query(order), order.get_recv_window());
+        self.signed_request(Method::POST, "order/test", &query)
+            .await?;
+        Ok(())
+    }
+
+    async fn place_oco(&self, oco: &OcoOrder) -> Result<OcoResponse> {
+        let query = self.windowed_query(
+            serde_urlencoded::to_string(oco).expect("OCO always serializes to a query string"),
+            oco.get_recv_window(),
+        );
+        let res = self
+            .signed_request(Method::POST, "order/oco", &query)
+            .await?;
+        Ok(serde_json::from_str(&res)?)
+    }
+
+    async fn cancel_order(&self, symbol: &str, order_id: u64) -> Result<OrderInfo> {
+        let query = self
+            .timestamp_query(&format!(
+                "symbol={}&orderId={}",
+                symbol.to_uppercase(),
+                order_id
+            ))
+            .await?;
+        let res = self.signed_request(Method::DELETE, "order", &query).await?;
+        Ok(serde_json::from_str(&res)?)
+    }
+
+    async fn query_order(&self, symbol: &str, order_id: u64) -> Result<OrderInfo> {
+        let query = self
+            .timestamp_query(&format!(
+                "symbol={}&orderId={}",
+                symbol.to_uppercase(),
+                order_id
+            ))
+            .await?;
+        let res = self.signed_request(Method::GET, "order", &query).await?;
+        Ok(serde_json::from_str(&res)?)
+    }
+
+    async fn get_balances(&self) -> Result<Vec<Balance>> {
+        #[derive(Deserialize)]
+        struct Account {
+            balances: Vec<Balance>,
+        }
+        let query = self.timestamp_query("").await?;
+        let res = self.signed_request(Method::GET, "account", &query).await?;
+        let account: Account = serde_json::from_str(&res)?;
+        Ok(account.balances)
+    }
+
+    fn subscribe_klines(&self, symbol: &str, interval: &str) -> Result<Box<dyn KlineStream>> {
+        let (mut socket, _response) = connect(self.broker.get_ws_api_url())?;
+        socket.write_message(Message::Text(socket_sub_payload(symbol, interval)))?;
+        Ok(Box::new(BinanceKlineStream { socket }))
+    }
+}
+
+#[derive(Deserialize)]
+struct WsKline {
+    t: u64,
+    o: String,
+    h: String,
+    l: String,
+    c: String,
+    v: String,
+    x: bool,
+}
+
+#[derive(Deserialize)]
+struct WsKlineEvent {
+    k: WsKline,
+}
+
+pub fn parse_kline_event(data: &str) -> Result<Option<KlineUpdate>> {
+    // Returns None for messages which are not kline events, e.g. subscription replies
+    let msg: Value = serde_json::from_str(data)?;
+    if msg.get("e").is_none() {
+        return match ApiError::from_payload(data) {
+            Some(e) => Err(Error::Api(e)),
+            None => Ok(None),
+        };
+    }
+    let event: WsKlineEvent = serde_json::from_value(msg)?;
+    let k = event.k;
+    let parse = |name: &str, v: &str| {
+        v.parse::<f64>()
+            .map_err(|_| Error::Shape(format!("kline field {} is not a decimal string", name)))
+    };
+    Ok(Some(KlineUpdate {
+        candle: Candle::new(
+            k.t,
+            parse("o", &k.o)?,
+            parse("h", &k.h)?,
+            parse("l", &k.l)?,
+            parse("c", &k.c)?,
+            parse("v", &k.v)?,
+        ),
+        closed: k.x,
+    }))
+}
+
+pub struct BinanceKlineStream {
+    socket: WebSocket<AutoStream>,
+}
+
+impl KlineStream for BinanceKlineStream {
+    fn next_kline(&mut self) -> Result<KlineUpdate> {
+        loop {
+            if let Message::Text(t) = self.socket.read_message()? {
+                if let Some(update) = parse_kline_event(&t)? {
+                    return Ok(update);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::{OrderSide, OrderStatus, OrderType, TimeInForce};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const SECRET: &str = "NhqPtmdSJYdKjVHjA7PZj4Mge3R5YNiP1e3UZjInClVN65XAbvqqM6A7H5fATj0j";
+
+    fn test_broker(url: String) -> Broker {
+        Broker::new(
+            "BINANCE".to_string(),
+            "api_key".to_string(),
+            SECRET.to_string(),
+            url,
+            String::new(),
+        )
+    }
+
+    fn limit_order(resp_type: OrderRespType) -> Order {
+        Order::new(
+            "LTCBTC".to_string(),
+            OrderSide::BUY,
+            OrderType::LIMIT,
+            Some(TimeInForce::GTC),
+            Some(1.0),
+            None,
+            Some(0.1),
+            None,
+            None,
+            None,
+            Some(resp_type),
+            Some(5000),
+            1499827319559,
+        )
+    }
+
+    // Serves a single request, answering with `body` only if the signature checks out
+    async fn mock_exchange(body: &'static str) -> (String, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/api/v3/", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0u8; 4096];
+            let mut len = 0;
+            while !String::from_utf8_lossy(&buf[..len]).contains("\r\n\r\n") {
+                len += stream.read(&mut buf[len..]).await.unwrap();
+            }
+            let request = String::from_utf8_lossy(&buf[..len]).to_string();
+            let target = request.split_whitespace().nth(1).unwrap().to_string();
+            let signed = target
+                .split_once('?')
+                .and_then(|(_, query)| query.rsplit_once("&signature="));
+            let authorized = match signed {
+                Some((payload, signature)) => {
+                    request.to_lowercase().contains("x-mbx-apikey: api_key")
+                        && signature == sign(SECRET, payload)
+                }
+                // Public endpoints are not signed
+                None => true,
+            };
+            let (status, body) = if authorized {
+                ("200 OK", body)
+            } else {
+                (
+                    "401 Unauthorized",
+                    r#"{"code":-1022,"msg":"Signature for this request is not valid."}"#,
+                )
+            };
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+            request
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn signature_matches_reference() {
+        // Example from the exchange API documentation
+        let query = "symbol=LTCBTC&side=BUY&type=LIMIT&timeInForce=GTC&quantity=1&price=0.1&recvWindow=5000&timestamp=1499827319559";
+        assert_eq!(
+            sign(SECRET, query),
+            "c8db56825ae71d6d79447849e617115f4a920fa2acdcab2b053c4b2838bd6b71"
+        );
+    }
+
+    #[test]
+    fn order_serializes_to_query() {
+        assert_eq!(
+            order_query(&limit_order(OrderRespType::ACK)),
+            "symbol=LTCBTC&side=BUY&type=LIMIT&timeInForce=GTC&quantity=1&price=0.1&newOrderRespType=ACK&recvWindow=5000&timestamp=1499827319559"
+        );
+    }
+
+    #[test]
+    fn small_values_are_plain_decimals() {
+        // Exchange rejects 5e-6 and the signature has to cover what is sent
+        let order = Order::new(
+            "SHIBBTC".to_string(),
+            OrderSide::SELL,
+            OrderType::STOP_LOSS_LIMIT,
+            Some(TimeInForce::GTC),
+            Some(0.000005),
+            None,
+            Some(0.00000123),
+            None,
+            Some(0.0000012345),
+            None,
+            None,
+            None,
+            1499827319559,
+        );
+        let query = order_query(&order);
+        assert_eq!(
+            query,
+            "symbol=SHIBBTC&side=SELL&type=STOP_LOSS_LIMIT&timeInForce=GTC&quantity=0.000005&price=0.00000123&stopPrice=0.0000012345&timestamp=1499827319559"
+        );
+        let broker = test_broker("http://localhost/".to_string());
+        assert_eq!(
+            signed_query(&broker, &query),
+            format!("{}&signature={}", query, sign(SECRET, &query))
+        );
+        let oco = Order::oco("SHIBBTC", OrderSide::SELL, 0.000005, 0.0000015, 0.0000011)
+            .list_client_order_id("list")
+            .build(&Clock::new())
+            .unwrap();
+        let query = serde_urlencoded::to_string(&oco).unwrap();
+        assert!(query.contains("quantity=0.000005&price=0.0000015&stopPrice=0.0000011"));
+    }
+
+    #[tokio::test]
+    async fn place_order_ack() {
+        let (url, server) = mock_exchange(
+            r#"{"symbol":"LTCBTC","orderId":28,"orderListId":-1,"clientOrderId":"6gCrw2kRUAF9CvJDGP16IP","transactTime":1507725176595}"#,
+        )
+        .await;
+        let resp = Binance::new(test_broker(url))
+            .place_order(&limit_order(OrderRespType::ACK))
+            .await
+            .unwrap();
+        assert_eq!(resp.get_order_id(), 28);
+        assert!(matches!(resp, OrderResponse::Ack(_)));
+        assert!(server.await.unwrap().starts_with("POST /api/v3/order?"));
+    }
+
+    #[tokio::test]
+    async fn place_order_full() {
+        let (url, _server) = mock_exchange(
+            r#"{"symbol":"LTCBTC","orderId":28,"orderListId":-1,"clientOrderId":"6gCrw2kRUAF9CvJDGP16IP","transactTime":1507725176595,"price":"0.10000000","origQty":"1.00000000","executedQty":"1.00000000","cummulativeQuoteQty":"0.10000000","status":"FILLED","timeInForce":"GTC","type":"LIMIT","side":"BUY","fills":[{"price":"0.10000000","qty":"1.00000000","commission":"0.00100000","commissionAsset":"LTC","tradeId":56}]}"#,
+        )
+        .await;
+        let resp = Binance::new(test_broker(url))
+            .place_order(&limit_order(OrderRespType::FULL))
+            .await
+            .unwrap();
+        match resp {
+            OrderResponse::Full(full) => {
+                assert_eq!(full.result.status, OrderStatus::FILLED);
+                assert_eq!(full.result.executed_qty, 1.0);
+                assert_eq!(full.fills.len(), 1);
+                assert_eq!(full.fills[0].commission, 0.001);
+            }
+            _ => panic!("Expected FULL response"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_order_rejects_bad_signature() {
+        let (url, server) = mock_exchange("{}").await;
+        assert!(Binance::new(test_broker(url))
+            .test_order(&limit_order(OrderRespType::ACK))
+            .await
+            .is_ok());
+        let request = server.await.unwrap();
+        assert!(request.starts_with("POST /api/v3/order/test?"));
+        assert!(request.contains("&recvWindow="));
+
+        let (url, _server) = mock_exchange("{}").await;
+        let broker = Broker::new(
+            "BINANCE".to_string(),
+            "api_key".to_string(),
+            "wrong_secret".to_string(),
+            url,
+            String::new(),
+        );
+        match Binance::new(broker)
+            .test_order(&limit_order(OrderRespType::ACK))
+            .await
+        {
+            Err(Error::Api(e)) => assert_eq!(e.code, ApiErrorCode::InvalidSignature),
+            other => panic!("Expected API error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn place_oco_sends_recv_window() {
+        let (url, server) = mock_exchange(
+            r#"{"orderListId":0,"listClientOrderId":"C3wyj4WVEktd7u9aVBRXcN","transactionTime":1574040868128,"symbol":"LTCBTC","orders":[]}"#,
+        )
+        .await;
+        let oco = Order::oco("LTCBTC", OrderSide::SELL, 1.0, 0.11, 0.09)
+            .build(&Clock::new())
+            .unwrap();
+        let resp = Binance::new(test_broker(url))
+            .place_oco(&oco)
+            .await
+            .unwrap();
+        assert_eq!(resp.order_list_id, 0);
+        let request = server.await.unwrap();
+        assert!(request.starts_with("POST /api/v3/order/oco?"));
+        assert!(request.contains("&recvWindow="));
+    }
+
+    #[test]
+    fn malformed_klines() {
+        let ok = r#"[[1499040000000,"0.01634790","0.80000000","0.01575800","0.01577100","148976.11427815",1499644799999,"2434.19055334",308,"1756.87402397","28.46694368","0"]]"#;
+        let candles = parse_klines(ok).unwrap();
+        assert_eq!(candles[0].timestamp(), 1499040000000);
+        assert_eq!(candles[0].close(), 0.015771);
+
+        assert!(matches!(
+            parse_klines(r#"[[1499040000000,"0.1","0.2"]]"#),
+            Err(Error::Shape(_))
+        ));
+        assert!(matches!(
+            parse_klines(r#"[[1499040000000,0.1,"0.2","0.1","0.1","1"]]"#),
+            Err(Error::Shape(_))
+        ));
+        assert!(matches!(parse_klines("{"), Err(Error::Json(_))));
+    }
+
+    #[test]
+    fn kline_events() {
+        let event = r#"{"e":"kline","E":123456789,"s":"BNBBTC","k":{"t":123400000,"T":123460000,"s":"BNBBTC","i":"1m","f":100,"L":200,"o":"0.0010","c":"0.0020","h":"0.0025","l":"0.0015","v":"1000","n":100,"x":false,"q":"1.0000","V":"500","Q":"0.500","B":"123456"}}"#;
+        let update = parse_kline_event(event).unwrap().unwrap();
+        assert_eq!(update.candle.high(), 0.0025);
+        assert!(!update.closed);
+
+        assert!(parse_kline_event(r#"{"result":null,"id":1}"#)
+            .unwrap()
+            .is_none());
+        match parse_kline_event(r#"{"code":2,"msg":"Invalid request"}"#) {
+            Err(Error::Api(e)) => assert_eq!(e.code, ApiErrorCode::Other(2)),
+            other => panic!("Expected API error, got {:?}", other),
+        }
+        assert!(parse_kline_event(r#"{"e":"kline","k":{"t":1}}"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn sync_clock_with_server() {
+        let (url, server) = mock_exchange(r#"{"serverTime":4102444800000}"#).await;
+        let binance = Binance::new(test_broker(url));
+        assert!(!binance.clock().is_synced());
+
+        let clock = binance.sync_clock().await.unwrap();
+        assert!(server.await.unwrap().starts_with("GET /api/v3/time"));
+        assert!(clock.is_synced());
+        // Exchange pretends to be in 2100, so local clock is far behind
+        let diff = 4102444800000 - clock.now_exchange_ms() as i64;
+        assert!(diff.abs() < 1000);
+        assert_eq!(binance.clock().get_offset_ms(), clock.get_offset_ms());
+    }
+}
@@ -0,0 +1,421 @@
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::clock::Clock;
+use crate::error::{Error, Result};
+use crate::types::{
+    ser_decimal, ser_opt_decimal, Order, OrderRespType, OrderSide, OrderType, TimeInForce,
+};
+
+static ORDER_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+pub fn new_client_order_id() -> String {
+    // Unique within this process, allowed chars and length are limited by the exchange
+    let n = ORDER_COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("tt{}{:05}", Clock::local_ms(), n % 100_000)
+}
+
+#[derive(Debug, Clone)]
+pub struct OrderBuilder {
+    symbol: String,
+    side: OrderSide,
+    r#type: OrderType,
+    time_in_force: Option<TimeInForce>,
+    quantity: Option<f64>,
+    quote_order_qty: Option<f64>,
+    price: Option<f64>,
+    client_order_id: Option<String>,
+    stop_price: Option<f64>,
+    iceberg_qty: Option<f64>,
+    resp_type: Option<OrderRespType>,
+    recv_window: Option<u64>,
+}
+
+impl OrderBuilder {
+    fn new(symbol: &str, side: OrderSide, r#type: OrderType) -> OrderBuilder {
+        OrderBuilder {
+            symbol: symbol.to_uppercase(),
+            side,
+            r#type,
+            time_in_force: None,
+            quantity: None,
+            quote_order_qty: None,
+            price: None,
+            client_order_id: None,
+            stop_price: None,
+            iceberg_qty: None,
+            resp_type: None,
+            recv_window: None,
+        }
+    }
+    pub fn quantity(mut self, quantity: f64) -> OrderBuilder {
+        // Quantity and quote quantity are mutually exclusive, last one wins
+        self.quantity = Some(quantity);
+        self.quote_order_qty = None;
+        self
+    }
+    pub fn quote_qty(mut self, quote_order_qty: f64) -> OrderBuilder {
+        self.quote_order_qty = Some(quote_order_qty);
+        self.quantity = None;
+        self
+    }
+    pub fn tif(mut self, time_in_force: TimeInForce) -> OrderBuilder {
+        self.time_in_force = Some(time_in_force);
+        self
+    }
+    pub fn iceberg_qty(mut self, iceberg_qty: f64) -> OrderBuilder {
+        self.iceberg_qty = Some(iceberg_qty);
+        self
+    }
+    pub fn client_order_id(mut self, id: &str) -> OrderBuilder {
+        self.client_order_id = Some(id.to_string());
+        self
+    }
+    pub fn resp_type(mut self, resp_type: OrderRespType) -> OrderBuilder {
+        self.resp_type = Some(resp_type);
+        self
+    }
+    pub fn recv_window(mut self, recv_window: u64) -> OrderBuilder {
+        self.recv_window = Some(recv_window);
+        self
+    }
+    pub fn build(self, clock: &Clock) -> Result<Order> {
+        // Timestamp is taken at build time, so build right before sending
+        let order = Order::new(
+            self.symbol,
+            self.side,
+            self.r#type,
+            self.time_in_force,
+            self.quantity,
+            self.quote_order_qty,
+            self.price,
+            Some(self.client_order_id.unwrap_or_else(new_client_order_id)),
+            self.stop_price,
+            self.iceberg_qty,
+            self.resp_type,
+            self.recv_window,
+            clock.now_exchange_ms(),
+        );
+        order.check_fields()?;
+        Ok(order)
+    }
+}
+
+impl Order {
+    pub fn market(symbol: &str, side: OrderSide) -> OrderBuilder {
+        // Needs either quantity or quote_qty before building
+        OrderBuilder::new(symbol, side, OrderType::MARKET)
+    }
+    pub fn market_buy(symbol: &str) -> OrderBuilder {
+        Order::market(symbol, OrderSide::BUY)
+    }
+    pub fn market_sell(symbol: &str) -> OrderBuilder {
+        Order::market(symbol, OrderSide::SELL)
+    }
+    pub fn limit(symbol: &str, side: OrderSide, price: f64, quantity: f64) -> OrderBuilder {
+        let mut b = OrderBuilder::new(symbol, side, OrderType::LIMIT);
+        b.price = Some(price);
+        b.quantity(quantity).tif(TimeInForce::GTC)
+    }
+    pub fn limit_maker(symbol: &str, side: OrderSide, price: f64, quantity: f64) -> OrderBuilder {
+        // Rejected by the exchange if it would match immediately, so no time in force
+        let mut b = OrderBuilder::new(symbol, side, OrderType::LIMIT_MAKER);
+        b.price = Some(price);
+        b.quantity(quantity)
+    }
+    pub fn stop_loss(
+        symbol: &str,
+        side: OrderSide,
+        stop_price: f64,
+        quantity: f64,
+    ) -> OrderBuilder {
+        let mut b = OrderBuilder::new(symbol, side, OrderType::STOP_LOSS);
+        b.stop_price = Some(stop_price);
+        b.quantity(quantity)
+    }
+    pub fn stop_loss_limit(
+        symbol: &str,
+        side: OrderSide,
+        stop_price: f64,
+        price: f64,
+        quantity: f64,
+    ) -> OrderBuilder {
+        let mut b = OrderBuilder::new(symbol, side, OrderType::STOP_LOSS_LIMIT);
+        b.stop_price = Some(stop_price);
+        b.price = Some(price);
+        b.quantity(quantity).tif(TimeInForce::GTC)
+    }
+    pub fn take_profit(
+        symbol: &str,
+        side: OrderSide,
+        stop_price: f64,
+        quantity: f64,
+    ) -> OrderBuilder {
+        let mut b = OrderBuilder::new(symbol, side, OrderType::TAKE_PROFIT);
+        b.stop_price = Some(stop_price);
+        b.quantity(quantity)
+    }
+    pub fn take_profit_limit(
+        symbol: &str,
+        side: OrderSide,
+        stop_price: f64,
+        price: f64,
+        quantity: f64,
+    ) -> OrderBuilder {
+        let mut b = OrderBuilder::new(symbol, side, OrderType::TAKE_PROFIT_LIMIT);
+        b.stop_price = Some(stop_price);
+        b.price = Some(price);
+        b.quantity(quantity).tif(TimeInForce::GTC)
+    }
+    pub fn oco(
+        symbol: &str,
+        side: OrderSide,
+        quantity: f64,
+        price: f64,
+        stop_price: f64,
+    ) -> OcoBuilder {
+        // Limit order at price paired with a stop at stop_price, filling one cancels the other
+        OcoBuilder {
+            symbol: symbol.to_uppercase(),
+            side,
+            quantity,
+            price,
+            stop_price,
+            stop_limit_price: None,
+            stop_limit_time_in_force: None,
+            list_client_order_id: None,
+            resp_type: None,
+            recv_window: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct OcoOrder {
+    symbol: String,
+    list_client_order_id: String,
+    side: OrderSide,
+    #[serde(serialize_with = "ser_decimal")]
+    quantity: f64,
+    #[serde(serialize_with = "ser_decimal")]
+    price: f64,
+    #[serde(serialize_with = "ser_decimal")]
+    stop_price: f64,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "ser_opt_decimal"
+    )]
+    stop_limit_price: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop_limit_time_in_force: Option<TimeInForce>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    new_order_resp_type: Option<OrderRespType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    recv_window: Option<u64>,
+    timestamp: u64,
+}
+impl OcoOrder {
+    pub fn get_symbol(&self) -> String {
+        self.symbol.clone()
+    }
+    pub fn get_side(&self) -> OrderSide {
+        self.side
+    }
+    pub fn get_quantity(&self) -> f64 {
+        self.quantity
+    }
+    pub fn get_price(&self) -> f64 {
+        self.price
+    }
+    pub fn get_stop_price(&self) -> f64 {
+        self.stop_price
+    }
+    pub fn get_stop_limit_price(&self) -> Option<f64> {
+        self.stop_limit_price
+    }
+    pub fn get_list_client_order_id(&self) -> String {
+        self.list_client_order_id.clone()
+    }
+    pub fn get_recv_window(&self) -> Option<u64> {
+        self.recv_window
+    }
+    pub fn get_timestamp(&self) -> u64 {
+        self.timestamp
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OcoBuilder {
+    symbol: String,
+    side: OrderSide,
+    quantity: f64,
+    price: f64,
+    stop_price: f64,
+    stop_limit_price: Option<f64>,
+    stop_limit_time_in_force: Option<TimeInForce>,
+    list_client_order_id: Option<String>,
+    resp_type: Option<OrderRespType>,
+    recv_window: Option<u64>,
+}
+
+impl OcoBuilder {
+    pub fn stop_limit(mut self, stop_limit_price: f64, time_in_force: TimeInForce) -> OcoBuilder {
+        // Stop leg becomes STOP_LOSS_LIMIT instead of STOP_LOSS
+        self.stop_limit_price = Some(stop_limit_price);
+        self.stop_limit_time_in_force = Some(time_in_force);
+        self
+    }
+    pub fn list_client_order_id(mut self, id: &str) -> OcoBuilder {
+        self.list_client_order_id = Some(id.to_string());
+        self
+    }
+    pub fn resp_type(mut self, resp_type: OrderRespType) -> OcoBuilder {
+        self.resp_type = Some(resp_type);
+        self
+    }
+    pub fn recv_window(mut self, recv_window: u64) -> OcoBuilder {
+        self.recv_window = Some(recv_window);
+        self
+    }
+    pub fn build(self, clock: &Clock) -> Result<OcoOrder> {
+        // Limit leg has to be on the profitable side of the stop
+        let ordered = match self.side {
+            OrderSide::SELL => self.price > self.stop_price,
+            OrderSide::BUY => self.price < self.stop_price,
+        };
+        if !ordered {
+            return Err(Error::Validation(format!(
+                "OCO {:?} needs limit price {} on the other side of stop price {}",
+                self.side, self.price, self.stop_price
+            )));
+        }
+        Ok(OcoOrder {
+            symbol: self.symbol,
+            list_client_order_id: self
+                .list_client_order_id
+                .unwrap_or_else(new_client_order_id),
+            side: self.side,
+            quantity: self.quantity,
+            price: self.price,
+            stop_price: self.stop_price,
+            stop_limit_price: self.stop_limit_price,
+            stop_limit_time_in_force: self.stop_limit_time_in_force,
+            new_order_resp_type: self.resp_type,
+            recv_window: self.recv_window,
+            timestamp: clock.now_exchange_ms(),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OcoOrderRef {
+    pub symbol: String,
+    pub order_id: u64,
+    pub client_order_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OcoResponse {
+    pub order_list_id: i64,
+    pub list_client_order_id: String,
+    pub transaction_time: u64,
+    pub symbol: String,
+    pub orders: Vec<OcoOrderRef>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn market_orders() {
+        let clock = Clock::new();
+        let o = Order::market_buy("btcusdt")
+            .quote_qty(20.0)
+            .build(&clock)
+            .unwrap();
+        assert_eq!(o.get_symbol(), "BTCUSDT");
+        assert_eq!(o.get_type(), OrderType::MARKET);
+        assert_eq!(o.get_quote_order_qty(), Some(20.0));
+        assert_eq!(o.get_quantity(), None);
+        assert!(o.get_timestamp() > 0);
+        assert!(Order::market_sell("BTCUSDT").build(&clock).is_err());
+        assert!(Order::market_sell("BTCUSDT")
+            .quantity(1.0)
+            .tif(TimeInForce::GTC)
+            .build(&clock)
+            .is_err());
+    }
+
+    #[test]
+    fn priced_orders() {
+        let clock = Clock::new();
+        let o = Order::limit("BTCUSDT", OrderSide::BUY, 100.0, 1.0)
+            .tif(TimeInForce::IOC)
+            .build(&clock)
+            .unwrap();
+        assert_eq!(o.get_time_in_force(), Some(TimeInForce::IOC));
+        assert_eq!(o.get_price(), Some(100.0));
+
+        let o = Order::stop_loss_limit("BTCUSDT", OrderSide::SELL, 95.0, 94.0, 1.0)
+            .build(&clock)
+            .unwrap();
+        assert_eq!(o.get_stop_price(), Some(95.0));
+        assert_eq!(o.get_time_in_force(), Some(TimeInForce::GTC));
+
+        assert!(Order::stop_loss("BTCUSDT", OrderSide::SELL, 95.0, 1.0)
+            .build(&clock)
+            .is_ok());
+        assert!(Order::take_profit("BTCUSDT", OrderSide::SELL, 105.0, 1.0)
+            .build(&clock)
+            .is_ok());
+        assert!(
+            Order::take_profit_limit("BTCUSDT", OrderSide::SELL, 105.0, 104.0, 1.0)
+                .build(&clock)
+                .is_ok()
+        );
+        assert!(Order::limit_maker("BTCUSDT", OrderSide::BUY, 99.0, 1.0)
+            .tif(TimeInForce::GTC)
+            .build(&clock)
+            .is_err());
+    }
+
+    #[test]
+    fn client_order_ids_are_unique() {
+        let clock = Clock::new();
+        let a = Order::market_buy("BTCUSDT")
+            .quantity(1.0)
+            .build(&clock)
+            .unwrap();
+        let b = Order::market_buy("BTCUSDT")
+            .quantity(1.0)
+            .build(&clock)
+            .unwrap();
+        assert_ne!(a.get_new_client_order_id(), b.get_new_client_order_id());
+        assert!(a.get_new_client_order_id().unwrap().len() <= 36);
+
+        let c = Order::market_buy("BTCUSDT")
+            .quantity(1.0)
+            .client_order_id("mine")
+            .build(&clock)
+            .unwrap();
+        assert_eq!(c.get_new_client_order_id(), Some("mine".to_string()));
+    }
+
+    #[test]
+    fn oco_orders() {
+        let clock = Clock::new();
+        let oco = Order::oco("BTCUSDT", OrderSide::SELL, 1.0, 110.0, 95.0)
+            .stop_limit(94.0, TimeInForce::GTC)
+            .build(&clock)
+            .unwrap();
+        let query = serde_urlencoded::to_string(&oco).unwrap();
+        assert!(query.contains("stopLimitPrice=94&stopLimitTimeInForce=GTC"));
+        assert!(Order::oco("BTCUSDT", OrderSide::SELL, 1.0, 90.0, 95.0)
+            .build(&clock)
+            .is_err());
+    }
+}
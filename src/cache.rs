@@ -0,0 +1,333 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::clock::Clock;
+use crate::download::{find_gaps, interval_ms, Downloader};
+use crate::error::{Error, Result};
+use crate::types::{Candle, CandleLine};
+
+pub struct CandleCache {
+    dir: PathBuf,
+}
+
+impl CandleCache {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> CandleCache {
+        CandleCache { dir: dir.into() }
+    }
+    pub fn path(&self, symbol: &str, interval: &str) -> PathBuf {
+        // One append-only file per symbol and interval, a candle per line
+        self.dir
+            .join(format!("{}_{}.jsonl", symbol.to_uppercase(), interval))
+    }
+
+    fn covered_path(&self, symbol: &str, interval: &str) -> PathBuf {
+        self.dir.join(format!(
+            "{}_{}.covered.json",
+            symbol.to_uppercase(),
+            interval
+        ))
+    }
+
+    pub fn covered(&self, symbol: &str, interval: &str) -> Result<Vec<(u64, u64)>> {
+        // Open times of first and last candle of ranges already asked from the exchange,
+        // gaps in them have no data and are not asked for again
+        let path = self.covered_path(symbol, interval);
+        if !path.exists() {
+            return Ok(vec![]);
+        }
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+    fn cover(&self, symbol: &str, interval: &str, step: u64, from: u64, to: u64) -> Result<()> {
+        let mut ranges = self.covered(symbol, interval)?;
+        ranges.push((from, to));
+        ranges.sort_unstable();
+        // Overlapping and adjacent ranges are kept as one
+        let mut merged: Vec<(u64, u64)> = Vec::new();
+        for (from, to) in ranges {
+            match merged.last_mut() {
+                Some(last) if from <= last.1 + step => last.1 = last.1.max(to),
+                _ => merged.push((from, to)),
+            }
+        }
+        fs::create_dir_all(&self.dir)?;
+        fs::write(
+            self.covered_path(symbol, interval),
+            serde_json::to_string(&merged)?,
+        )?;
+        Ok(())
+    }
+
+    pub fn load(&self, symbol: &str, interval: &str) -> Result<CandleLine> {
+        // Sorted by time, for repeated timestamps the last written candle wins
+        let path = self.path(symbol, interval);
+        if !path.exists() {
+            return Ok(CandleLine::new());
+        }
+        let mut candles = CandleLine::read_jsonl(fs::File::open(path)?)?.all();
+        candles.reverse();
+        candles.sort_by_key(|c| c.timestamp());
+        candles.dedup_by_key(|c| c.timestamp());
+        Ok(CandleLine::new_from_vec(candles))
+    }
+    pub fn load_range(
+        &self,
+        symbol: &str,
+        interval: &str,
+        start_ms: u64,
+        end_ms: u64,
+    ) -> Result<CandleLine> {
+        let candles = self.load(symbol, interval)?;
+        Ok(CandleLine::new_from_vec(
+            candles
+                .all()
+                .into_iter()
+                .filter(|c| c.timestamp() >= start_ms && c.timestamp() <= end_ms)
+                .collect(),
+        ))
+    }
+    pub fn append(&self, symbol: &str, interval: &str, candles: &[Candle]) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path(symbol, interval))?;
+        let mut buf = String::new();
+        for candle in candles {
+            buf.push_str(&serde_json::to_string(candle)?);
+            buf.push('\n');
+        }
+        file.write_all(buf.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn missing_ranges(
+        &self,
+        cached: &CandleLine,
+        covered: &[(u64, u64)],
+        interval_ms: u64,
+        start_ms: u64,
+        end_ms: u64,
+    ) -> Vec<(u64, u64)> {
+        // Ranges within [start_ms, end_ms] which are neither cached nor known to be empty
+        let first = start_ms.div_ceil(interval_ms) * interval_ms;
+        let mut ranges = Vec::new();
+        if cached.is_empty() {
+            if first <= end_ms {
+                ranges.push((first, end_ms));
+            }
+        } else {
+            if first < cached.first().timestamp() {
+                ranges.push((first, cached.first().timestamp() - interval_ms));
+            }
+            for gap in find_gaps(cached, interval_ms) {
+                ranges.push((gap.from, gap.to));
+            }
+            if cached.last().timestamp() + interval_ms <= end_ms {
+                ranges.push((cached.last().timestamp() + interval_ms, end_ms));
+            }
+        }
+        ranges
+            .into_iter()
+            .flat_map(|range| subtract(range, covered, interval_ms))
+            .collect()
+    }
+
+    pub fn missing(
+        &self,
+        symbol: &str,
+        interval: &str,
+        start_ms: u64,
+        end_ms: u64,
+    ) -> Result<Vec<(u64, u64)>> {
+        let step = interval_ms(interval)
+            .ok_or_else(|| Error::Validation(format!("Unsupported interval {}", interval)))?;
+        let cached = self.load_range(symbol, interval, start_ms, end_ms)?;
+        let covered = self.covered(symbol, interval)?;
+        Ok(self.missing_ranges(&cached, &covered, step, start_ms, end_ms))
+    }
+
+    pub fn load_offline(
+        &self,
+        symbol: &str,
+        interval: &str,
+        start_ms: u64,
+        end_ms: u64,
+    ) -> Result<CandleLine> {
+        // Range served from disk alone, fails when part of it was never downloaded
+        let missing = self.missing(symbol, interval, start_ms, end_ms)?;
+        if !missing.is_empty() {
+            return Err(Error::Validation(format!(
+                "{} {} is not cached in {:?}",
+                symbol, interval, missing
+            )));
+        }
+        self.load_range(symbol, interval, start_ms, end_ms)
+    }
+
+    pub async fn fetch(
+        &self,
+        downloader: &mut Downloader<'_>,
+        symbol: &str,
+        interval: &str,
+        start_ms: u64,
+        end_ms: u64,
+    ) -> Result<CandleLine> {
+        // Downloads only what is missing, stores it and returns the whole range from cache
+        let step = interval_ms(interval)
+            .ok_or_else(|| Error::Validation(format!("Unsupported interval {}", interval)))?;
+        let now = Clock::local_ms();
+        // Candles after the last closed one may still come, they are not covered yet
+        let closed_until = (now / step * step).saturating_sub(step);
+        for (from, to) in self.missing(symbol, interval, start_ms, end_ms)? {
+            let fresh = downloader.download(symbol, interval, from, to).await?;
+            // Candle which is still open would freeze its current state in the cache
+            let closed: Vec<Candle> = fresh
+                .all()
+                .into_iter()
+                .filter(|c| c.timestamp() + step <= now)
+                .collect();
+            self.append(symbol, interval, &closed)?;
+            let to = (to / step * step).min(closed_until);
+            if to >= from {
+                self.cover(symbol, interval, step, from, to)?;
+            }
+        }
+        self.load_range(symbol, interval, start_ms, end_ms)
+    }
+}
+
+fn subtract(range: (u64, u64), covered: &[(u64, u64)], step: u64) -> Vec<(u64, u64)> {
+    // Parts of the range outside of the sorted covered ranges
+    let (mut from, to) = range;
+    let mut left = Vec::new();
+    for &(start, end) in covered.iter() {
+        if end < from || start > to {
+            continue;
+        }
+        if start > from {
+            left.push((from, start - step));
+        }
+        if end >= to {
+            return left;
+        }
+        from = end + step;
+    }
+    left.push((from, to));
+    left
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{minute_candles, FakeExchange};
+
+    fn temp_cache(name: &str) -> CandleCache {
+        let dir =
+            std::env::temp_dir().join(format!("tradeterm-cache-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        CandleCache::new(dir)
+    }
+
+    #[test]
+    fn append_and_load() {
+        let cache = temp_cache("append");
+        assert!(cache.load("BTCUSDT", "1m").unwrap().is_empty());
+
+        cache
+            .append("BTCUSDT", "1m", &minute_candles(&[3, 4]))
+            .unwrap();
+        cache
+            .append("btcusdt", "1m", &minute_candles(&[0, 1, 4]))
+            .unwrap();
+        let line = cache.load("BTCUSDT", "1m").unwrap();
+        assert_eq!(line.timestamps(), vec![0, 60_000, 180_000, 240_000]);
+        assert_eq!(
+            cache
+                .load_range("BTCUSDT", "1m", 60_000, 180_000)
+                .unwrap()
+                .len(),
+            2
+        );
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[test]
+    fn missing() {
+        let cache = temp_cache("missing");
+        let line = CandleLine::new_from_vec(minute_candles(&[2, 3, 6]));
+        assert_eq!(
+            cache.missing_ranges(&line, &[], 60_000, 30_000, 480_000),
+            vec![(60_000, 60_000), (240_000, 300_000), (420_000, 480_000)]
+        );
+        assert_eq!(
+            cache.missing_ranges(&CandleLine::new(), &[], 60_000, 0, 60_000),
+            vec![(0, 60_000)]
+        );
+        // Covered ranges are known to have no more data
+        assert_eq!(
+            cache.missing_ranges(
+                &line,
+                &[(240_000, 240_000), (420_000, 600_000)],
+                60_000,
+                30_000,
+                480_000
+            ),
+            vec![(60_000, 60_000), (300_000, 300_000)]
+        );
+    }
+
+    #[tokio::test]
+    async fn fetch_fills_incrementally() {
+        let cache = temp_cache("fetch");
+        let exchange = FakeExchange::new(minute_candles(&(0..30).collect::<Vec<u64>>()));
+
+        let mut downloader = Downloader::new(&exchange);
+        let line = cache
+            .fetch(&mut downloader, "BTCUSDT", "1m", 0, 10 * 60_000)
+            .await
+            .unwrap();
+        assert_eq!(line.len(), 11);
+        let requests = exchange.requests();
+
+        // Cached part is served from disk, only the tail is downloaded
+        let line = cache
+            .fetch(&mut downloader, "BTCUSDT", "1m", 5 * 60_000, 20 * 60_000)
+            .await
+            .unwrap();
+        assert_eq!(line.len(), 16);
+        assert_eq!(exchange.requests(), requests + 1);
+
+        // Fully cached range does not touch the exchange
+        cache
+            .fetch(&mut downloader, "BTCUSDT", "1m", 0, 20 * 60_000)
+            .await
+            .unwrap();
+        assert_eq!(exchange.requests(), requests + 1);
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn empty_ranges_are_remembered() {
+        let cache = temp_cache("covered");
+        let minutes: Vec<u64> = (0..10).chain(15..20).collect();
+        let exchange = FakeExchange::new(minute_candles(&minutes));
+        let mut downloader = Downloader::new(&exchange);
+        assert!(cache.load_offline("BTCUSDT", "1m", 0, 25 * 60_000).is_err());
+        cache
+            .fetch(&mut downloader, "BTCUSDT", "1m", 0, 25 * 60_000)
+            .await
+            .unwrap();
+        let requests = exchange.requests();
+        // Neither the gap nor the tail without data are asked for again
+        let line = cache
+            .fetch(&mut downloader, "BTCUSDT", "1m", 0, 25 * 60_000)
+            .await
+            .unwrap();
+        assert_eq!(exchange.requests(), requests);
+        assert_eq!(line.len(), 15);
+        let offline = cache.load_offline("BTCUSDT", "1m", 0, 25 * 60_000).unwrap();
+        assert_eq!(offline.timestamps(), line.timestamps());
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+}
@@ -0,0 +1,392 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::error::{Error, Result};
+use crate::types::{Candle, CandleLine};
+
+// Header names used when columns are not mapped by name
+const FIELDS: [&str; 6] = ["timestamp", "open", "high", "low", "close", "volume"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeUnit {
+    Seconds,
+    Milliseconds,
+    Microseconds,
+    Nanoseconds,
+}
+
+impl TimeUnit {
+    pub fn to_ms(&self, value: u64) -> u64 {
+        match self {
+            TimeUnit::Seconds => value * 1_000,
+            TimeUnit::Milliseconds => value,
+            TimeUnit::Microseconds => value / 1_000,
+            TimeUnit::Nanoseconds => value / 1_000_000,
+        }
+    }
+    pub fn from_ms(&self, ms: u64) -> u64 {
+        match self {
+            TimeUnit::Seconds => ms / 1_000,
+            TimeUnit::Milliseconds => ms,
+            TimeUnit::Microseconds => ms * 1_000,
+            TimeUnit::Nanoseconds => ms * 1_000_000,
+        }
+    }
+    fn parse_ms(&self, field: &str) -> Option<u64> {
+        // Some sources write timestamps as floats, e.g. "1622505600.0"
+        let field = field.trim();
+        match field.parse::<u64>() {
+            Ok(value) => Some(self.to_ms(value)),
+            Err(_) => {
+                let value = field.parse::<f64>().ok().filter(|v| *v >= 0.0)?;
+                Some((value * self.to_ms(1_000_000) as f64 / 1_000_000.0).round() as u64)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Column {
+    Index(usize),
+    Name(String),
+}
+
+impl From<usize> for Column {
+    fn from(index: usize) -> Column {
+        Column::Index(index)
+    }
+}
+
+impl From<&str> for Column {
+    fn from(name: &str) -> Column {
+        Column::Name(name.to_string())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CsvFormat {
+    delimiter: u8,
+    has_headers: bool,
+    time_unit: TimeUnit,
+    // Columns of timestamp, open, high, low, close and volume
+    columns: [Column; 6],
+}
+
+impl Default for CsvFormat {
+    fn default() -> CsvFormat {
+        CsvFormat {
+            delimiter: b',',
+            has_headers: true,
+            time_unit: TimeUnit::Milliseconds,
+            columns: FIELDS.map(Column::from),
+        }
+    }
+}
+
+impl CsvFormat {
+    pub fn new() -> CsvFormat {
+        CsvFormat::default()
+    }
+    pub fn with_delimiter(mut self, delimiter: u8) -> CsvFormat {
+        self.delimiter = delimiter;
+        self
+    }
+    pub fn with_headers(mut self, has_headers: bool) -> CsvFormat {
+        self.has_headers = has_headers;
+        self
+    }
+    pub fn with_time_unit(mut self, time_unit: TimeUnit) -> CsvFormat {
+        self.time_unit = time_unit;
+        self
+    }
+    pub fn with_columns<C: Into<Column>>(
+        mut self,
+        timestamp: C,
+        open: C,
+        high: C,
+        low: C,
+        close: C,
+        volume: C,
+    ) -> CsvFormat {
+        self.columns = [
+            timestamp.into(),
+            open.into(),
+            high.into(),
+            low.into(),
+            close.into(),
+            volume.into(),
+        ];
+        self
+    }
+    pub fn get_delimiter(&self) -> u8 {
+        self.delimiter
+    }
+    pub fn get_has_headers(&self) -> bool {
+        self.has_headers
+    }
+    pub fn get_time_unit(&self) -> TimeUnit {
+        self.time_unit
+    }
+    pub fn get_columns(&self) -> &[Column; 6] {
+        &self.columns
+    }
+
+    fn resolve(&self, headers: Option<&csv::StringRecord>) -> Result<[usize; 6]> {
+        // Position of every candle field within a record
+        let mut indexes = [0; 6];
+        for (i, column) in self.columns.iter().enumerate() {
+            indexes[i] = match column {
+                Column::Index(index) => *index,
+                Column::Name(name) => headers
+                    .and_then(|h| h.iter().position(|field| field.trim() == name))
+                    .ok_or_else(|| Error::Validation(format!("Missing CSV column {}", name)))?,
+            };
+        }
+        Ok(indexes)
+    }
+    fn layout(&self) -> Vec<Option<(usize, &str)>> {
+        // Field and header of each written column, fields mapped by index keep their position
+        let mut next = self
+            .columns
+            .iter()
+            .filter_map(|c| match c {
+                Column::Index(i) => Some(i + 1),
+                Column::Name(_) => None,
+            })
+            .max()
+            .unwrap_or(0);
+        let mut positions = Vec::new();
+        for (field, column) in self.columns.iter().enumerate() {
+            match column {
+                Column::Index(index) => positions.push((*index, field, FIELDS[field])),
+                Column::Name(name) => {
+                    positions.push((next, field, name.as_str()));
+                    next += 1;
+                }
+            }
+        }
+        let width = positions.iter().map(|(p, _, _)| p + 1).max().unwrap_or(0);
+        let mut layout = vec![None; width];
+        for (position, field, name) in positions {
+            layout[position] = Some((field, name));
+        }
+        layout
+    }
+}
+
+impl CandleLine {
+    pub fn from_csv<P: AsRef<Path>>(path: P, format: &CsvFormat) -> Result<CandleLine> {
+        CandleLine::read_csv(File::open(path)?, format)
+    }
+    pub fn read_csv<R: Read>(reader: R, format: &CsvFormat) -> Result<CandleLine> {
+        // Candles come out sorted by time without repeated timestamps, whatever the
+        // order in the source
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(format.delimiter)
+            .has_headers(format.has_headers)
+            .flexible(true)
+            .from_reader(reader);
+        let headers = if format.has_headers {
+            Some(reader.headers()?.clone())
+        } else {
+            None
+        };
+        let [ts, open, high, low, close, volume] = format.resolve(headers.as_ref())?;
+        let mut candles = Vec::new();
+        for (row, record) in reader.records().enumerate() {
+            let record = record?;
+            let field = |index: usize| {
+                record.get(index).ok_or_else(|| {
+                    Error::Validation(format!("Row {} has no column {}", row + 1, index))
+                })
+            };
+            let number = |index: usize| {
+                field(index)?.trim().parse::<f64>().map_err(|_| {
+                    Error::Validation(format!("Row {} column {} is not a number", row + 1, index))
+                })
+            };
+            let timestamp = format.time_unit.parse_ms(field(ts)?).ok_or_else(|| {
+                Error::Validation(format!("Row {} has invalid timestamp", row + 1))
+            })?;
+            candles.push(Candle::new(
+                timestamp,
+                number(open)?,
+                number(high)?,
+                number(low)?,
+                number(close)?,
+                number(volume)?,
+            ));
+        }
+        Ok(sorted(candles))
+    }
+    pub fn to_csv<P: AsRef<Path>>(&self, path: P, format: &CsvFormat) -> Result<()> {
+        self.write_csv(File::create(path)?, format)
+    }
+    pub fn write_csv<W: Write>(&self, writer: W, format: &CsvFormat) -> Result<()> {
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(format.delimiter)
+            .from_writer(writer);
+        let layout = format.layout();
+        if format.has_headers {
+            writer.write_record(layout.iter().map(|c| c.map_or("", |(_, name)| name)))?;
+        }
+        for candle in self.all() {
+            let values = [
+                format.time_unit.from_ms(candle.timestamp()).to_string(),
+                candle.open().to_string(),
+                candle.high().to_string(),
+                candle.low().to_string(),
+                candle.close().to_string(),
+                candle.volume().to_string(),
+            ];
+            writer.write_record(
+                layout
+                    .iter()
+                    .map(|c| c.map_or("", |(field, _)| values[field].as_str())),
+            )?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn from_jsonl<P: AsRef<Path>>(path: P) -> Result<CandleLine> {
+        CandleLine::read_jsonl(File::open(path)?)
+    }
+    pub fn read_jsonl<R: Read>(reader: R) -> Result<CandleLine> {
+        // One candle object per line, blank lines are skipped. Candles come out sorted
+        // by time like from csv
+        let mut candles = Vec::new();
+        for line in BufReader::new(reader).lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                candles.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(sorted(candles))
+    }
+    pub fn to_jsonl<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.write_jsonl(File::create(path)?)
+    }
+    pub fn write_jsonl<W: Write>(&self, writer: W) -> Result<()> {
+        let mut writer = BufWriter::new(writer);
+        for candle in self.all() {
+            serde_json::to_writer(&mut writer, &candle)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+fn sorted(mut candles: Vec<Candle>) -> CandleLine {
+    // First candle of each timestamp is kept, sort is stable so that is the first read
+    candles.sort_by_key(|c| c.timestamp());
+    candles.dedup_by_key(|c| c.timestamp());
+    CandleLine::new_from_vec(candles)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn time_units() {
+        assert_eq!(
+            TimeUnit::Seconds.parse_ms("1622505600"),
+            Some(1_622_505_600_000)
+        );
+        assert_eq!(
+            TimeUnit::Seconds.parse_ms("1622505600.5"),
+            Some(1_622_505_600_500)
+        );
+        assert_eq!(
+            TimeUnit::Nanoseconds.parse_ms("1622505600000000000"),
+            Some(1_622_505_600_000)
+        );
+        assert_eq!(TimeUnit::Milliseconds.parse_ms("-1"), None);
+        assert_eq!(TimeUnit::Microseconds.from_ms(2), 2_000);
+    }
+
+    #[test]
+    fn read_mapped_csv() {
+        let data = "Date;Vol;Close;High;Low;Open\n\
+                    1622505660;12.5;101;102;99;100\n\
+                    1622505600;3;100;100.5;98;99.5\n";
+        let format = CsvFormat::new()
+            .with_delimiter(b';')
+            .with_time_unit(TimeUnit::Seconds)
+            .with_columns("Date", "Open", "High", "Low", "Close", "Vol");
+        let line = CandleLine::read_csv(data.as_bytes(), &format).unwrap();
+        assert_eq!(
+            line.timestamps(),
+            vec![1_622_505_600_000, 1_622_505_660_000]
+        );
+        assert_eq!(line.opens(), vec![99.5, 100.0]);
+        assert_eq!(line.volumes(), vec![3.0, 12.5]);
+
+        let headless = CsvFormat::new()
+            .with_headers(false)
+            .with_columns(0, 1, 2, 3, 4, 5);
+        let line = CandleLine::read_csv("0,1,2,0.5,1.5,10\n".as_bytes(), &headless).unwrap();
+        assert_eq!(line.closes(), vec![1.5]);
+
+        let missing = CandleLine::read_csv("time,open\n0,1\n".as_bytes(), &CsvFormat::new());
+        assert!(matches!(missing, Err(Error::Validation(_))));
+        let broken = CandleLine::read_csv("0,x,2,0.5,1.5,10\n".as_bytes(), &headless);
+        assert!(matches!(broken, Err(Error::Validation(_))));
+    }
+
+    #[test]
+    fn csv_roundtrip() {
+        let line = CandleLine::new_from_vec(vec![
+            Candle::new(60_000, 1.0, 2.5, 0.5, 2.0, 100.0),
+            Candle::new(120_000, 2.0, 3.0, 1.75, 2.25, 0.001),
+        ]);
+        let mut out = Vec::new();
+        line.write_csv(&mut out, &CsvFormat::new()).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.starts_with("timestamp,open,high,low,close,volume\n60000,1,2.5,0.5,2,100\n"));
+
+        let format = CsvFormat::new()
+            .with_time_unit(TimeUnit::Seconds)
+            .with_columns(5, 0, 1, 2, 3, 4);
+        let mut out = Vec::new();
+        line.write_csv(&mut out, &format).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.starts_with("open,high,low,close,volume,timestamp\n1,2.5,0.5,2,100,60\n"));
+        let back = CandleLine::read_csv(text.as_bytes(), &format).unwrap();
+        assert_eq!(back.timestamps(), line.timestamps());
+        assert_eq!(back.lows(), line.lows());
+        assert_eq!(back.volumes(), line.volumes());
+    }
+
+    #[test]
+    fn jsonl_roundtrip() {
+        let line = CandleLine::new_from_vec(vec![
+            Candle::new(0, 1.0, 2.0, 0.5, 1.5, 10.0),
+            Candle::new(60_000, 1.5, 2.0, 1.0, 1.25, 7.5),
+        ]);
+        let mut out = Vec::new();
+        line.write_jsonl(&mut out).unwrap();
+        assert_eq!(out.iter().filter(|b| **b == b'\n').count(), 2);
+        let back = CandleLine::read_jsonl(&out[..]).unwrap();
+        assert_eq!(back.timestamps(), line.timestamps());
+        assert_eq!(back.closes(), line.closes());
+        assert!(CandleLine::read_jsonl("{\"timestamp\":1}\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn jsonl_sorted() {
+        let line = CandleLine::new_from_vec(vec![
+            Candle::new(60_000, 1.5, 2.0, 1.0, 1.25, 7.5),
+            Candle::new(0, 1.0, 2.0, 0.5, 1.5, 10.0),
+            Candle::new(60_000, 9.0, 9.0, 9.0, 9.0, 9.0),
+        ]);
+        let mut out = Vec::new();
+        line.write_jsonl(&mut out).unwrap();
+        let back = CandleLine::read_jsonl(&out[..]).unwrap();
+        assert_eq!(back.timestamps(), vec![0, 60_000]);
+        assert_eq!(back.closes(), vec![1.5, 1.25]);
+    }
+}
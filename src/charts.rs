@@ -0,0 +1,386 @@
+use crate::indicators::{batch, HeikinAshi};
+use crate::types::{Candle, CandleLine};
+
+// Tolerance for prices landing exactly on a box boundary, e.g. 0.3 / 0.1
+const BOX_EPSILON: f64 = 1e-9;
+
+fn valid_size(size: f64) -> bool {
+    // Brick, range and box sizes have to move the price, anything else never ends a bar
+    size.is_finite() && size > 0.0
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bar {
+    timestamp: u64,
+    open: f64,
+    high: f64,
+    low: f64,
+    close: f64,
+    volume: f64,
+}
+
+impl Bar {
+    fn new(timestamp: u64, open: f64, close: f64) -> Bar {
+        Bar {
+            timestamp,
+            open,
+            high: open.max(close),
+            low: open.min(close),
+            close,
+            volume: 0.0,
+        }
+    }
+    fn candle(&self) -> Candle {
+        Candle::new(
+            self.timestamp,
+            self.open,
+            self.high,
+            self.low,
+            self.close,
+            self.volume,
+        )
+    }
+}
+
+#[derive(Default)]
+struct Bars {
+    bars: Vec<Candle>,
+    last_ts: Option<u64>,
+}
+
+impl Bars {
+    fn timestamp(&mut self, timestamp: u64) -> u64 {
+        // Several bars may come from one candle, their open times are kept unique
+        // so streaming indicators do not take them for updates of the same bar
+        let ts = match self.last_ts {
+            Some(last) if timestamp <= last => last + 1,
+            _ => timestamp,
+        };
+        self.last_ts = Some(ts);
+        ts
+    }
+    fn push(&mut self, bar: Bar) {
+        self.bars.push(bar.candle());
+    }
+    fn line(self) -> CandleLine {
+        CandleLine::new_from_vec(self.bars)
+    }
+}
+
+impl CandleLine {
+    pub fn heikinashi(&self) -> CandleLine {
+        // Method generating Heikin Ashi candlesticks, best use with buffer of at least 10
+        // candles in past to decrease "synthetic" first candle
+        CandleLine::new_from_vec(batch(&mut HeikinAshi::new(), self.all()))
+    }
+
+    pub fn renko(&self, brick_size: f64) -> CandleLine {
+        // Bricks built from closes, reversal needs a move of two bricks. Empty for a
+        // brick size which is not positive
+        let mut out = Bars::default();
+        if !valid_size(brick_size) {
+            return out.line();
+        }
+        let data = self.all();
+        let (mut top, mut bottom) = match data.first() {
+            Some(first) => (first.close(), first.close()),
+            None => return out.line(),
+        };
+        let mut volume = 0.0;
+        for candle in data.iter() {
+            volume += candle.volume();
+            let price = candle.close();
+            while price >= top + brick_size {
+                let mut brick = Bar::new(out.timestamp(candle.timestamp()), top, top + brick_size);
+                brick.volume = std::mem::take(&mut volume);
+                out.push(brick);
+                bottom = top;
+                top += brick_size;
+            }
+            while price <= bottom - brick_size {
+                let mut brick = Bar::new(
+                    out.timestamp(candle.timestamp()),
+                    bottom,
+                    bottom - brick_size,
+                );
+                brick.volume = std::mem::take(&mut volume);
+                out.push(brick);
+                top = bottom;
+                bottom -= brick_size;
+            }
+        }
+        out.line()
+    }
+
+    pub fn range_bars(&self, range: f64) -> CandleLine {
+        // Every bar spans exactly the range, except the last one which is still forming.
+        // Path inside a candle is assumed to be open, low, high, close for rising
+        // candles and open, high, low, close for falling ones. Empty for a range which is
+        // not positive
+        let mut out = Bars::default();
+        if !valid_size(range) {
+            return out.line();
+        }
+        let mut current: Option<Bar> = None;
+        for candle in self.all() {
+            let path = if candle.close() >= candle.open() {
+                [candle.open(), candle.low(), candle.high(), candle.close()]
+            } else {
+                [candle.open(), candle.high(), candle.low(), candle.close()]
+            };
+            let mut volume = candle.volume();
+            for price in path {
+                loop {
+                    let mut bar = match current {
+                        Some(bar) => bar,
+                        None => Bar::new(out.timestamp(candle.timestamp()), price, price),
+                    };
+                    bar.volume += std::mem::take(&mut volume);
+                    let limit = if price > bar.high && price - bar.low >= range {
+                        Some(bar.low + range)
+                    } else if price < bar.low && bar.high - price >= range {
+                        Some(bar.high - range)
+                    } else {
+                        None
+                    };
+                    match limit {
+                        Some(limit) => {
+                            bar.high = bar.high.max(limit);
+                            bar.low = bar.low.min(limit);
+                            bar.close = limit;
+                            out.push(bar);
+                            current =
+                                Some(Bar::new(out.timestamp(candle.timestamp()), limit, limit));
+                        }
+                        None => {
+                            bar.high = bar.high.max(price);
+                            bar.low = bar.low.min(price);
+                            bar.close = price;
+                            current = Some(bar);
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        if let Some(bar) = current {
+            out.push(bar);
+        }
+        out.line()
+    }
+
+    pub fn kagi(&self, reversal: f64) -> CandleLine {
+        // Each line of the chart is a candle from its start to its extreme, based on closes.
+        // Empty for a reversal which is not positive
+        let mut out = Bars::default();
+        if !valid_size(reversal) {
+            return out.line();
+        }
+        let data = self.all();
+        let mut line = match data.first() {
+            Some(first) => Bar::new(
+                out.timestamp(first.timestamp()),
+                first.close(),
+                first.close(),
+            ),
+            None => return out.line(),
+        };
+        for candle in data.iter() {
+            let price = candle.close();
+            let rising = line.close > line.open || (line.close == line.open && price > line.open);
+            let falling = line.close < line.open || (line.close == line.open && price < line.open);
+            let reversed = (rising && line.close - price >= reversal)
+                || (falling && price - line.close >= reversal);
+            if reversed {
+                out.push(line);
+                line = Bar::new(out.timestamp(candle.timestamp()), line.close, price);
+            } else if (rising && price > line.close) || (falling && price < line.close) {
+                line.close = price;
+                line.high = line.high.max(price);
+                line.low = line.low.min(price);
+            }
+            line.volume += candle.volume();
+        }
+        out.push(line);
+        out.line()
+    }
+
+    pub fn point_and_figure(&self, box_size: f64, reversal_boxes: usize) -> CandleLine {
+        // Columns of X are rising candles from bottom to top box, columns of O falling ones.
+        // Empty for a box size which is not positive
+        let mut out = Bars::default();
+        if !valid_size(box_size) {
+            return out.line();
+        }
+        let floor = |price: f64| (price / box_size + BOX_EPSILON).floor() * box_size;
+        let ceil = |price: f64| (price / box_size - BOX_EPSILON).ceil() * box_size;
+        let reversal = box_size * reversal_boxes.max(1) as f64;
+        let data = self.all();
+        let start = match data.first() {
+            Some(first) => floor(first.close()),
+            None => return out.line(),
+        };
+        let mut column: Option<Bar> = None;
+        let mut volume = 0.0;
+        for candle in data.iter() {
+            let price = candle.close();
+            volume += candle.volume();
+            column = match column {
+                None if price >= start + box_size => Some(Bar::new(
+                    out.timestamp(candle.timestamp()),
+                    start,
+                    floor(price),
+                )),
+                None if price <= start - box_size => Some(Bar::new(
+                    out.timestamp(candle.timestamp()),
+                    start,
+                    ceil(price),
+                )),
+                None => None,
+                Some(mut col) if col.close > col.open => {
+                    if price >= col.close + box_size {
+                        col.close = floor(price);
+                        col.high = col.close;
+                        Some(col)
+                    } else if price <= col.close - reversal {
+                        out.push(col);
+                        let top = col.close - box_size;
+                        Some(Bar::new(
+                            out.timestamp(candle.timestamp()),
+                            top,
+                            ceil(price),
+                        ))
+                    } else {
+                        Some(col)
+                    }
+                }
+                Some(mut col) => {
+                    if price <= col.close - box_size {
+                        col.close = ceil(price);
+                        col.low = col.close;
+                        Some(col)
+                    } else if price >= col.close + reversal {
+                        out.push(col);
+                        let bottom = col.close + box_size;
+                        Some(Bar::new(
+                            out.timestamp(candle.timestamp()),
+                            bottom,
+                            floor(price),
+                        ))
+                    } else {
+                        Some(col)
+                    }
+                }
+            };
+            if let Some(col) = column.as_mut() {
+                col.volume += std::mem::take(&mut volume);
+            }
+        }
+        if let Some(col) = column {
+            out.push(col);
+        }
+        out.line()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn closes(values: &[f64]) -> CandleLine {
+        CandleLine::new_from_vec(
+            values
+                .iter()
+                .enumerate()
+                .map(|(i, c)| Candle::new(i as u64 * 60_000, *c, *c, *c, *c, 1.0))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn heikinashi() {
+        let line = CandleLine::new_from_vec(vec![
+            Candle::new(0, 10.0, 12.0, 9.0, 11.0, 1.0),
+            Candle::new(60_000, 11.0, 11.5, 10.5, 11.2, 1.0),
+            Candle::new(120_000, 12.0, 13.0, 11.8, 12.9, 1.0),
+        ]);
+        let ha = line.heikinashi();
+        assert_eq!(ha.opens(), vec![10.5, 10.5, 10.775]);
+        for (c, e) in ha.closes().iter().zip([10.5, 11.05, 12.425].iter()) {
+            assert!((c - e).abs() < 1e-9);
+        }
+        assert_eq!(ha.highs(), vec![12.0, 11.5, 13.0]);
+        // Gap up leaves HA open below the raw low
+        assert_eq!(ha.lows(), vec![9.0, 10.5, 10.775]);
+        assert!(CandleLine::new().heikinashi().is_empty());
+    }
+
+    #[test]
+    fn renko() {
+        let bricks = closes(&[100.0, 101.0, 103.5, 102.0, 99.5, 98.0]).renko(1.0);
+        assert_eq!(
+            bricks.opens(),
+            vec![100.0, 101.0, 102.0, 102.0, 101.0, 100.0, 99.0]
+        );
+        assert_eq!(
+            bricks.closes(),
+            vec![101.0, 102.0, 103.0, 101.0, 100.0, 99.0, 98.0]
+        );
+        assert_eq!(
+            bricks.timestamps(),
+            vec![60_000, 120_000, 120_001, 240_000, 240_001, 300_000, 300_001]
+        );
+        // Volume since the last brick goes to the next one
+        assert_eq!(bricks.volumes(), vec![2.0, 1.0, 0.0, 2.0, 0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn range_bars() {
+        let line = CandleLine::new_from_vec(vec![
+            Candle::new(0, 10.0, 11.0, 9.5, 10.5, 1.0),
+            Candle::new(60_000, 10.5, 13.0, 10.5, 12.5, 2.0),
+        ]);
+        let bars = line.range_bars(2.0);
+        assert_eq!(bars.timestamps(), vec![0, 60_000]);
+        assert_eq!(bars.opens(), vec![10.0, 11.5]);
+        assert_eq!(bars.highs(), vec![11.5, 13.0]);
+        assert_eq!(bars.lows(), vec![9.5, 11.5]);
+        assert_eq!(bars.closes(), vec![11.5, 12.5]);
+        assert_eq!(bars.volumes(), vec![3.0, 0.0]);
+        // Large candle is split into several full bars
+        let big = CandleLine::new_from_vec(vec![Candle::new(0, 0.0, 5.0, 0.0, 5.0, 1.0)]);
+        assert_eq!(big.range_bars(2.0).closes(), vec![2.0, 4.0, 5.0]);
+    }
+
+    #[test]
+    fn kagi() {
+        let lines = closes(&[10.0, 11.0, 13.0, 12.0, 10.5, 11.0, 14.0]).kagi(2.0);
+        assert_eq!(lines.opens(), vec![10.0, 13.0, 10.5]);
+        assert_eq!(lines.closes(), vec![13.0, 10.5, 14.0]);
+        assert_eq!(lines.timestamps(), vec![0, 240_000, 360_000]);
+        assert_eq!(lines.volumes(), vec![4.0, 2.0, 1.0]);
+    }
+
+    #[test]
+    fn invalid_sizes() {
+        let line = closes(&[10.0, 12.0, 9.0]);
+        for size in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(line.renko(size).is_empty());
+            assert!(line.range_bars(size).is_empty());
+            assert!(line.kagi(size).is_empty());
+            assert!(line.point_and_figure(size, 3).is_empty());
+        }
+    }
+
+    #[test]
+    fn point_and_figure() {
+        let columns = closes(&[10.0, 12.5, 13.2, 11.0, 10.1, 9.5, 12.9]).point_and_figure(1.0, 3);
+        assert_eq!(columns.opens(), vec![10.0, 12.0]);
+        assert_eq!(columns.closes(), vec![13.0, 10.0]);
+        assert_eq!(columns.highs(), vec![13.0, 12.0]);
+        assert_eq!(columns.lows(), vec![10.0, 10.0]);
+        // Price exactly on a box boundary fills that box
+        let top = closes(&[1.0, 1.3]).point_and_figure(0.1, 3).last().close();
+        assert!((top - 1.3).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,110 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Exchange default, signed requests older than this are rejected
+pub const DEFAULT_RECV_WINDOW: u64 = 5_000;
+// Upper bound accepted by the exchange
+pub const MAX_RECV_WINDOW: u64 = 60_000;
+// Weight of a new sample in the running offset
+const OFFSET_SMOOTHING: f64 = 0.25;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Clock {
+    // Difference between exchange time and local time in milliseconds
+    offset_ms: i64,
+    // Round trip of the last time request
+    rtt_ms: u64,
+    // Local time of the last sync, zero if never synced
+    last_sync_ms: u64,
+}
+
+impl Clock {
+    pub fn new() -> Clock {
+        Clock {
+            offset_ms: 0,
+            rtt_ms: 0,
+            last_sync_ms: 0,
+        }
+    }
+    pub fn local_ms() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("System clock is set before unix epoch")
+            .as_millis() as u64
+    }
+    pub fn get_offset_ms(&self) -> i64 {
+        self.offset_ms
+    }
+    pub fn set_offset_ms(&mut self, offset_ms: i64) {
+        self.offset_ms = offset_ms;
+    }
+    pub fn get_rtt_ms(&self) -> u64 {
+        self.rtt_ms
+    }
+    pub fn get_last_sync_ms(&self) -> u64 {
+        self.last_sync_ms
+    }
+    pub fn is_synced(&self) -> bool {
+        self.last_sync_ms != 0
+    }
+    pub fn now_exchange_ms(&self) -> u64 {
+        // Local time corrected by the known offset
+        (Clock::local_ms() as i64 + self.offset_ms) as u64
+    }
+
+    pub fn record_sample(&mut self, sent_ms: u64, server_ms: u64, received_ms: u64) {
+        // Server time is assumed to be taken halfway through the round trip
+        let rtt = received_ms.saturating_sub(sent_ms);
+        let sample = server_ms as i64 - (sent_ms + rtt / 2) as i64;
+        self.offset_ms = if self.is_synced() {
+            self.offset_ms + ((sample - self.offset_ms) as f64 * OFFSET_SMOOTHING).round() as i64
+        } else {
+            sample
+        };
+        self.rtt_ms = rtt;
+        self.last_sync_ms = received_ms;
+    }
+    pub fn needs_sync(&self, interval_ms: u64) -> bool {
+        !self.is_synced() || Clock::local_ms().saturating_sub(self.last_sync_ms) >= interval_ms
+    }
+    pub fn invalidate(&mut self) {
+        // Forces resync before next signed request, offset is kept until then
+        self.last_sync_ms = 0;
+    }
+    pub fn recv_window_ms(&self) -> u64 {
+        // Slow connections need more slack than the default window
+        (DEFAULT_RECV_WINDOW + self.rtt_ms).min(MAX_RECV_WINDOW)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn offset_from_samples() {
+        let mut clock = Clock::new();
+        assert!(clock.needs_sync(1000));
+
+        // Exchange is 500 ms ahead, request took 100 ms
+        clock.record_sample(1_000, 1_550, 1_100);
+        assert_eq!(clock.get_offset_ms(), 500);
+        assert_eq!(clock.get_rtt_ms(), 100);
+        assert_eq!(clock.recv_window_ms(), DEFAULT_RECV_WINDOW + 100);
+
+        // Following samples move the offset only partially
+        clock.record_sample(2_000, 2_950, 2_100);
+        assert_eq!(clock.get_offset_ms(), 600);
+
+        clock.invalidate();
+        assert!(clock.needs_sync(u64::MAX));
+    }
+
+    #[test]
+    fn exchange_time() {
+        let mut clock = Clock::new();
+        clock.set_offset_ms(-10_000);
+        let diff = Clock::local_ms() as i64 - clock.now_exchange_ms() as i64;
+        assert!((10_000..10_100).contains(&diff));
+        assert!(Clock::new().recv_window_ms() <= MAX_RECV_WINDOW);
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::clock::Clock;
+use crate::error::{Error, Result};
+use crate::exchange::Exchange;
+use crate::exchange_info::{RateLimit, RateLimitType};
+use crate::types::{Candle, CandleLine};
+
+// Most klines the exchange returns in a single request
+pub const MAX_PAGE_SIZE: usize = 1000;
+// Request weight of a klines call with limit above 500
+pub const KLINES_WEIGHT: u64 = 2;
+
+pub fn interval_ms(interval: &str) -> Option<u64> {
+    // Length of exchange interval like "15m" or "4h", months have no fixed length
+    let (num, unit) = interval.split_at(interval.len().checked_sub(1)?);
+    let num = num.parse::<u64>().ok()?;
+    let unit_ms = match unit {
+        "s" => 1_000,
+        "m" => 60_000,
+        "h" => 3_600_000,
+        "d" => 86_400_000,
+        "w" => 604_800_000,
+        _ => return None,
+    };
+    Some(num * unit_ms)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Gap {
+    // Open times of the first and last missing candle
+    pub from: u64,
+    pub to: u64,
+}
+
+pub fn find_gaps(candles: &CandleLine, interval_ms: u64) -> Vec<Gap> {
+    // Expects candles sorted by time, out of order neighbours are no gap
+    candles
+        .timestamps()
+        .windows(2)
+        .filter(|w| w[1].saturating_sub(w[0]) > interval_ms)
+        .map(|w| Gap {
+            from: w[0] + interval_ms,
+            to: w[1] - interval_ms,
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    limit: u64,
+    window_ms: u64,
+    // Local time and weight of requests made within the window
+    used: VecDeque<(u64, u64)>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: u64, window_ms: u64) -> RateLimiter {
+        RateLimiter {
+            limit,
+            window_ms,
+            used: VecDeque::new(),
+        }
+    }
+    pub fn from_rate_limits(limits: &[RateLimit]) -> RateLimiter {
+        // Follow the strictest request weight limit, exchange default otherwise
+        limits
+            .iter()
+            .filter(|l| l.rate_limit_type == RateLimitType::REQUEST_WEIGHT)
+            .map(|l| RateLimiter::new(l.limit, l.interval_ms()))
+            .min_by(|a, b| {
+                (a.limit as f64 / a.window_ms as f64)
+                    .partial_cmp(&(b.limit as f64 / b.window_ms as f64))
+                    .unwrap()
+            })
+            .unwrap_or_default()
+    }
+    pub fn wait_ms(&mut self, now_ms: u64, weight: u64) -> u64 {
+        // How long to wait before a request of given weight fits into the window
+        while let Some(&(t, _)) = self.used.front() {
+            if t + self.window_ms <= now_ms {
+                self.used.pop_front();
+            } else {
+                break;
+            }
+        }
+        let mut total: u64 = self.used.iter().map(|(_, w)| w).sum();
+        for &(t, w) in self.used.iter() {
+            if total + weight <= self.limit {
+                break;
+            }
+            total -= w;
+            if total + weight <= self.limit {
+                return t + self.window_ms - now_ms;
+            }
+        }
+        0
+    }
+    pub fn record(&mut self, now_ms: u64, weight: u64) {
+        self.used.push_back((now_ms, weight));
+    }
+    pub async fn acquire(&mut self, weight: u64) {
+        let wait = self.wait_ms(Clock::local_ms(), weight);
+        if wait > 0 {
+            tokio::time::sleep(Duration::from_millis(wait)).await;
+        }
+        self.record(Clock::local_ms(), weight);
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> RateLimiter {
+        // Exchange default for REQUEST_WEIGHT
+        RateLimiter::new(1200, 60_000)
+    }
+}
+
+pub struct Downloader<'a> {
+    exchange: &'a dyn Exchange,
+    limiter: RateLimiter,
+    page_size: usize,
+}
+
+impl<'a> Downloader<'a> {
+    pub fn new(exchange: &'a dyn Exchange) -> Downloader<'a> {
+        Downloader {
+            exchange,
+            limiter: RateLimiter::default(),
+            page_size: MAX_PAGE_SIZE,
+        }
+    }
+    pub fn with_limiter(mut self, limiter: RateLimiter) -> Downloader<'a> {
+        self.limiter = limiter;
+        self
+    }
+    pub fn with_page_size(mut self, page_size: usize) -> Downloader<'a> {
+        self.page_size = page_size.clamp(1, MAX_PAGE_SIZE);
+        self
+    }
+
+    pub async fn download(
+        &mut self,
+        symbol: &str,
+        interval: &str,
+        start_ms: u64,
+        end_ms: u64,
+    ) -> Result<CandleLine> {
+        // All candles opened within [start_ms, end_ms], without duplicates and oldest first
+        let step = interval_ms(interval)
+            .ok_or_else(|| Error::Validation(format!("Unsupported interval {}", interval)))?;
+        let mut candles: Vec<Candle> = Vec::new();
+        let mut cursor = start_ms;
+        while cursor <= end_ms {
+            self.limiter.acquire(KLINES_WEIGHT).await;
+            let page = self
+                .exchange
+                .get_candles_range(symbol, interval, cursor, end_ms, self.page_size)
+                .await?;
+            let full_page = page.len() >= self.page_size;
+            for candle in page {
+                let ts = candle.timestamp();
+                // Pages may overlap or contain candles outside of requested range
+                let is_new = candles.last().is_none_or(|c| ts > c.timestamp());
+                if is_new && ts >= start_ms && ts <= end_ms {
+                    candles.push(candle);
+                }
+            }
+            match candles.last() {
+                Some(last) if full_page && last.timestamp() + step > cursor => {
+                    cursor = last.timestamp() + step
+                }
+                _ => break,
+            }
+        }
+        Ok(CandleLine::new_from_vec(candles))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{minute_candles, FakeExchange};
+
+    #[test]
+    fn intervals() {
+        assert_eq!(interval_ms("1m"), Some(60_000));
+        assert_eq!(interval_ms("4h"), Some(14_400_000));
+        assert_eq!(interval_ms("1w"), Some(604_800_000));
+        assert_eq!(interval_ms("1M"), None);
+        assert_eq!(interval_ms(""), None);
+    }
+
+    #[test]
+    fn gaps() {
+        let line = CandleLine::new_from_vec(minute_candles(&[0, 1, 2, 5, 6, 8]));
+        assert_eq!(
+            find_gaps(&line, 60_000),
+            vec![
+                Gap {
+                    from: 180_000,
+                    to: 240_000
+                },
+                Gap {
+                    from: 420_000,
+                    to: 420_000
+                }
+            ]
+        );
+        // Unsorted candles don't underflow
+        let unsorted = CandleLine::new_from_vec(minute_candles(&[5, 2, 4, 1]));
+        assert_eq!(
+            find_gaps(&unsorted, 60_000),
+            vec![Gap {
+                from: 180_000,
+                to: 180_000
+            }]
+        );
+    }
+
+    #[test]
+    fn rate_limiter() {
+        let mut limiter = RateLimiter::new(4, 1000);
+        assert_eq!(limiter.wait_ms(0, 2), 0);
+        limiter.record(0, 2);
+        limiter.record(100, 2);
+        // Full until the first request leaves the window
+        assert_eq!(limiter.wait_ms(200, 2), 800);
+        assert_eq!(limiter.wait_ms(200, 4), 900);
+        assert_eq!(limiter.wait_ms(1000, 2), 0);
+    }
+
+    #[tokio::test]
+    async fn download_pages() {
+        let minutes: Vec<u64> = (0..25).filter(|m| *m != 13 && *m != 14).collect();
+        let exchange = FakeExchange::new(minute_candles(&minutes));
+        let line = Downloader::new(&exchange)
+            .with_page_size(5)
+            .download("BTCUSDT", "1m", 2 * 60_000, 20 * 60_000)
+            .await
+            .unwrap();
+        assert_eq!(line.first().timestamp(), 2 * 60_000);
+        assert_eq!(line.last().timestamp(), 20 * 60_000);
+        // 19 minutes in range minus the two missing ones
+        assert_eq!(line.len(), 17);
+        assert!(line.timestamps().windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(find_gaps(&line, 60_000).len(), 1);
+        assert!(exchange.requests() >= 4);
+    }
+}
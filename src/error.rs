@@ -0,0 +1,222 @@
+use serde::Deserialize;
+use std::fmt;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    // Transport level failure of a REST request
+    Http(reqwest::Error),
+    // Payload is not valid JSON or does not match the expected type
+    Json(serde_json::Error),
+    // JSON is valid, but a field is missing or has unexpected type
+    Shape(String),
+    // Exchange answered with an error payload
+    Api(ApiError),
+    WebSocket(Box<tungstenite::Error>),
+    // Reading or writing local files
+    Io(std::io::Error),
+    // Malformed CSV record or field
+    Csv(csv::Error),
+    // Rejected locally before anything was sent
+    Validation(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Http(e) => write!(f, "HTTP error: {}", e),
+            Error::Json(e) => write!(f, "JSON error: {}", e),
+            Error::Shape(msg) => write!(f, "Unexpected response shape: {}", msg),
+            Error::Api(e) => write!(f, "Exchange error {}: {}", e.code.code(), e.msg),
+            Error::WebSocket(e) => write!(f, "WebSocket error: {}", e),
+            Error::Io(e) => write!(f, "IO error: {}", e),
+            Error::Csv(e) => write!(f, "CSV error: {}", e),
+            Error::Validation(msg) => write!(f, "Validation error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Http(e) => Some(e),
+            Error::Json(e) => Some(e),
+            Error::WebSocket(e) => Some(e.as_ref()),
+            Error::Io(e) => Some(e),
+            Error::Csv(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Error {
+        Error::Http(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Error {
+        Error::Json(e)
+    }
+}
+
+impl From<tungstenite::Error> for Error {
+    fn from(e: tungstenite::Error) -> Error {
+        Error::WebSocket(Box::new(e))
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<csv::Error> for Error {
+    fn from(e: csv::Error) -> Error {
+        Error::Csv(e)
+    }
+}
+
+impl From<ApiError> for Error {
+    fn from(e: ApiError) -> Error {
+        Error::Api(e)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ApiError {
+    #[serde(deserialize_with = "de_api_error_code")]
+    pub code: ApiErrorCode,
+    pub msg: String,
+}
+
+fn de_api_error_code<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<ApiErrorCode, D::Error> {
+    Ok(ApiErrorCode::from_code(i64::deserialize(deserializer)?))
+}
+
+impl ApiError {
+    pub fn from_payload(data: &str) -> Option<ApiError> {
+        // Error payloads look like {"code":-1121,"msg":"Invalid symbol."}
+        serde_json::from_str(data).ok()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ApiErrorCode {
+    Unknown,
+    Disconnected,
+    Unauthorized,
+    TooManyRequests,
+    UnexpectedResponse,
+    Timeout,
+    TooManyOrders,
+    ServiceShuttingDown,
+    UnsupportedOperation,
+    InvalidTimestamp,
+    InvalidSignature,
+    IllegalChars,
+    TooManyParameters,
+    MandatoryParamEmptyOrMalformed,
+    UnknownParam,
+    BadPrecision,
+    InvalidParameter,
+    BadSymbol,
+    FilterFailure,
+    NewOrderRejected,
+    CancelRejected,
+    NoSuchOrder,
+    BadApiKeyFormat,
+    RejectedApiKey,
+    Other(i64),
+}
+
+impl ApiErrorCode {
+    pub fn from_code(code: i64) -> ApiErrorCode {
+        match code {
+            -1000 => ApiErrorCode::Unknown,
+            -1001 => ApiErrorCode::Disconnected,
+            -1002 => ApiErrorCode::Unauthorized,
+            -1003 => ApiErrorCode::TooManyRequests,
+            -1006 => ApiErrorCode::UnexpectedResponse,
+            -1007 => ApiErrorCode::Timeout,
+            -1013 => ApiErrorCode::FilterFailure,
+            -1015 => ApiErrorCode::TooManyOrders,
+            -1016 => ApiErrorCode::ServiceShuttingDown,
+            -1020 => ApiErrorCode::UnsupportedOperation,
+            -1021 => ApiErrorCode::InvalidTimestamp,
+            -1022 => ApiErrorCode::InvalidSignature,
+            -1100 => ApiErrorCode::IllegalChars,
+            -1101 => ApiErrorCode::TooManyParameters,
+            -1102 => ApiErrorCode::MandatoryParamEmptyOrMalformed,
+            -1104 => ApiErrorCode::UnknownParam,
+            -1111 => ApiErrorCode::BadPrecision,
+            -1121 => ApiErrorCode::BadSymbol,
+            -1130 => ApiErrorCode::InvalidParameter,
+            -2010 => ApiErrorCode::NewOrderRejected,
+            -2011 => ApiErrorCode::CancelRejected,
+            -2013 => ApiErrorCode::NoSuchOrder,
+            -2014 => ApiErrorCode::BadApiKeyFormat,
+            -2015 => ApiErrorCode::RejectedApiKey,
+            other => ApiErrorCode::Other(other),
+        }
+    }
+    pub fn code(&self) -> i64 {
+        match self {
+            ApiErrorCode::Unknown => -1000,
+            ApiErrorCode::Disconnected => -1001,
+            ApiErrorCode::Unauthorized => -1002,
+            ApiErrorCode::TooManyRequests => -1003,
+            ApiErrorCode::UnexpectedResponse => -1006,
+            ApiErrorCode::Timeout => -1007,
+            ApiErrorCode::FilterFailure => -1013,
+            ApiErrorCode::TooManyOrders => -1015,
+            ApiErrorCode::ServiceShuttingDown => -1016,
+            ApiErrorCode::UnsupportedOperation => -1020,
+            ApiErrorCode::InvalidTimestamp => -1021,
+            ApiErrorCode::InvalidSignature => -1022,
+            ApiErrorCode::IllegalChars => -1100,
+            ApiErrorCode::TooManyParameters => -1101,
+            ApiErrorCode::MandatoryParamEmptyOrMalformed => -1102,
+            ApiErrorCode::UnknownParam => -1104,
+            ApiErrorCode::BadPrecision => -1111,
+            ApiErrorCode::BadSymbol => -1121,
+            ApiErrorCode::InvalidParameter => -1130,
+            ApiErrorCode::NewOrderRejected => -2010,
+            ApiErrorCode::CancelRejected => -2011,
+            ApiErrorCode::NoSuchOrder => -2013,
+            ApiErrorCode::BadApiKeyFormat => -2014,
+            ApiErrorCode::RejectedApiKey => -2015,
+            ApiErrorCode::Other(code) => *code,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decode_error_payload() {
+        let e = ApiError::from_payload(r#"{"code":-1121,"msg":"Invalid symbol."}"#).unwrap();
+        assert_eq!(e.code, ApiErrorCode::BadSymbol);
+        assert_eq!(e.msg, "Invalid symbol.");
+
+        let e = ApiError::from_payload(r#"{"code":-9999,"msg":"?"}"#).unwrap();
+        assert_eq!(e.code, ApiErrorCode::Other(-9999));
+        assert_eq!(e.code.code(), -9999);
+
+        assert!(ApiError::from_payload(r#"{"serverTime":1}"#).is_none());
+    }
+
+    #[test]
+    fn codes_roundtrip() {
+        for code in -2020..-999 {
+            assert_eq!(ApiErrorCode::from_code(code).code(), code);
+        }
+    }
+}
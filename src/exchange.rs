@@ -0,0 +1,91 @@
+use crate::binance::Binance;
+use crate::builder::{OcoOrder, OcoResponse};
+use crate::clock::Clock;
+use crate::error::{Error, Result};
+use crate::exchange_info::{ExchangeInfo, SymbolInfo};
+use crate::types::{Balance, Broker, Candle, Order, OrderInfo, OrderResponse};
+use async_trait::async_trait;
+
+// How often exchange clock offset is refreshed
+pub const CLOCK_RESYNC_MS: u64 = 10 * 60 * 1000;
+
+#[derive(Debug, Clone, Copy)]
+pub struct KlineUpdate {
+    // Latest state of a kline; closed klines will not change anymore
+    pub candle: Candle,
+    pub closed: bool,
+}
+
+pub trait KlineStream: Send {
+    // Blocks until the next kline update arrives
+    fn next_kline(&mut self) -> Result<KlineUpdate>;
+}
+
+#[async_trait]
+pub trait Exchange: Send + Sync {
+    fn name(&self) -> String;
+    // Clock used to timestamp signed requests, see Order builders
+    fn clock(&self) -> Clock;
+    // Measures offset to exchange time and folds it into the clock
+    async fn sync_clock(&self) -> Result<Clock>;
+    async fn synced_clock(&self) -> Result<Clock> {
+        // Resyncs only once the last sync is older than CLOCK_RESYNC_MS
+        let clock = self.clock();
+        if clock.needs_sync(CLOCK_RESYNC_MS) {
+            self.sync_clock().await
+        } else {
+            Ok(clock)
+        }
+    }
+    async fn get_candles(&self, symbol: &str, interval: &str, limit: usize) -> Result<Vec<Candle>>;
+    // Up to limit candles opened within [start_ms, end_ms], oldest first
+    async fn get_candles_range(
+        &self,
+        symbol: &str,
+        interval: &str,
+        start_ms: u64,
+        end_ms: u64,
+        limit: usize,
+    ) -> Result<Vec<Candle>>;
+    async fn get_server_time(&self) -> Result<u64>;
+    async fn get_exchange_info(&self) -> Result<ExchangeInfo>;
+    async fn get_symbol_info(&self, symbol: &str) -> Result<SymbolInfo>;
+    async fn place_order(&self, order: &Order) -> Result<OrderResponse>;
+    async fn test_order(&self, order: &Order) -> Result<()>;
+    async fn place_oco(&self, oco: &OcoOrder) -> Result<OcoResponse>;
+    async fn cancel_order(&self, symbol: &str, order_id: u64) -> Result<OrderInfo>;
+    async fn query_order(&self, symbol: &str, order_id: u64) -> Result<OrderInfo>;
+    async fn get_balances(&self) -> Result<Vec<Balance>>;
+    fn subscribe_klines(&self, symbol: &str, interval: &str) -> Result<Box<dyn KlineStream>>;
+}
+
+impl Broker {
+    pub fn exchange(&self) -> Result<Box<dyn Exchange>> {
+        // Pick implementation by broker name
+        match self.get_name().as_str() {
+            "BINANCE" => Ok(Box::new(Binance::new(self.clone()))),
+            name => Err(Error::Validation(format!("Unsupported exchange {}", name))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn broker(name: &str) -> Broker {
+        Broker::new(
+            name.to_string(),
+            String::new(),
+            String::new(),
+            String::new(),
+            String::new(),
+        )
+    }
+
+    #[test]
+    fn exchange_by_name() {
+        assert_eq!(broker("binance").exchange().unwrap().name(), "BINANCE");
+        assert!(broker("nowhere").exchange().is_err());
+    }
+}
@@ -0,0 +1,267 @@
+use serde::{Deserialize, Serialize};
+
+use crate::types::{de_str_f64, Market, OrderType};
+
+#[allow(non_camel_case_types)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum RateLimitType {
+    REQUEST_WEIGHT,
+    ORDERS,
+    RAW_REQUESTS,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum RateLimitInterval {
+    SECOND,
+    MINUTE,
+    DAY,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimit {
+    pub rate_limit_type: RateLimitType,
+    pub interval: RateLimitInterval,
+    pub interval_num: u64,
+    pub limit: u64,
+}
+impl RateLimit {
+    pub fn interval_ms(&self) -> u64 {
+        // Length of the window this limit applies to
+        let unit = match self.interval {
+            RateLimitInterval::SECOND => 1_000,
+            RateLimitInterval::MINUTE => 60_000,
+            RateLimitInterval::DAY => 86_400_000,
+        };
+        unit * self.interval_num
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PriceFilter {
+    #[serde(deserialize_with = "de_str_f64")]
+    pub min_price: f64,
+    #[serde(deserialize_with = "de_str_f64")]
+    pub max_price: f64,
+    #[serde(deserialize_with = "de_str_f64")]
+    pub tick_size: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PercentPrice {
+    #[serde(deserialize_with = "de_str_f64")]
+    pub multiplier_up: f64,
+    #[serde(deserialize_with = "de_str_f64")]
+    pub multiplier_down: f64,
+    pub avg_price_mins: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LotSize {
+    #[serde(deserialize_with = "de_str_f64")]
+    pub min_qty: f64,
+    #[serde(deserialize_with = "de_str_f64")]
+    pub max_qty: f64,
+    #[serde(deserialize_with = "de_str_f64")]
+    pub step_size: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MinNotional {
+    #[serde(deserialize_with = "de_str_f64")]
+    pub min_notional: f64,
+    pub apply_to_market: bool,
+    pub avg_price_mins: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MaxNumOrders {
+    pub max_num_orders: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "filterType")]
+pub enum Filter {
+    #[serde(rename = "PRICE_FILTER")]
+    Price(PriceFilter),
+    #[serde(rename = "PERCENT_PRICE")]
+    PercentPrice(PercentPrice),
+    #[serde(rename = "LOT_SIZE")]
+    LotSize(LotSize),
+    #[serde(rename = "MIN_NOTIONAL")]
+    MinNotional(MinNotional),
+    #[serde(rename = "MARKET_LOT_SIZE")]
+    MarketLotSize(LotSize),
+    #[serde(rename = "MAX_NUM_ORDERS")]
+    MaxNumOrders(MaxNumOrders),
+    // Filters we do not check locally
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SymbolInfo {
+    pub symbol: String,
+    pub status: String,
+    pub base_asset: String,
+    pub base_asset_precision: u32,
+    pub quote_asset: String,
+    pub quote_asset_precision: u32,
+    pub order_types: Vec<OrderType>,
+    pub iceberg_allowed: bool,
+    pub oco_allowed: bool,
+    pub is_spot_trading_allowed: bool,
+    pub filters: Vec<Filter>,
+}
+impl SymbolInfo {
+    pub fn price_filter(&self) -> Option<&PriceFilter> {
+        self.filters.iter().find_map(|f| match f {
+            Filter::Price(p) => Some(p),
+            _ => None,
+        })
+    }
+    pub fn percent_price(&self) -> Option<&PercentPrice> {
+        self.filters.iter().find_map(|f| match f {
+            Filter::PercentPrice(p) => Some(p),
+            _ => None,
+        })
+    }
+    pub fn lot_size(&self) -> Option<&LotSize> {
+        self.filters.iter().find_map(|f| match f {
+            Filter::LotSize(l) => Some(l),
+            _ => None,
+        })
+    }
+    pub fn market_lot_size(&self) -> Option<&LotSize> {
+        self.filters.iter().find_map(|f| match f {
+            Filter::MarketLotSize(l) => Some(l),
+            _ => None,
+        })
+    }
+    pub fn min_notional(&self) -> Option<&MinNotional> {
+        self.filters.iter().find_map(|f| match f {
+            Filter::MinNotional(m) => Some(m),
+            _ => None,
+        })
+    }
+    pub fn max_num_orders(&self) -> Option<&MaxNumOrders> {
+        self.filters.iter().find_map(|f| match f {
+            Filter::MaxNumOrders(m) => Some(m),
+            _ => None,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ExchangeInfo {
+    pub timezone: String,
+    pub server_time: u64,
+    pub rate_limits: Vec<RateLimit>,
+    pub symbols: Vec<SymbolInfo>,
+}
+impl ExchangeInfo {
+    pub fn symbol(&self, symbol: &str) -> Option<&SymbolInfo> {
+        self.symbols
+            .iter()
+            .find(|s| s.symbol.eq_ignore_ascii_case(symbol))
+    }
+}
+
+impl Market {
+    pub fn from_symbol_info(
+        info: &SymbolInfo,
+        currency_a_amount: f64,
+        currency_b_amount: f64,
+        ratio_a_to_b: f64,
+        transaction_fee: f64,
+    ) -> Market {
+        // Minimal transaction and step are taken from LOT_SIZE, symbols without it accept anything
+        let (min_a_transaction, step_size) = match info.lot_size() {
+            Some(lot) => (lot.min_qty, lot.step_size),
+            None => (0.0, 0.0),
+        };
+        Market::new(
+            currency_a_amount,
+            currency_b_amount,
+            ratio_a_to_b,
+            min_a_transaction,
+            step_size,
+            transaction_fee,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const EXCHANGE_INFO: &str = r#"{
+        "timezone": "UTC",
+        "serverTime": 1565246363776,
+        "rateLimits": [
+            {"rateLimitType": "REQUEST_WEIGHT", "interval": "MINUTE", "intervalNum": 1, "limit": 1200},
+            {"rateLimitType": "ORDERS", "interval": "SECOND", "intervalNum": 10, "limit": 50},
+            {"rateLimitType": "RAW_REQUESTS", "interval": "MINUTE", "intervalNum": 5, "limit": 6100}
+        ],
+        "exchangeFilters": [],
+        "symbols": [{
+            "symbol": "BTCUSDT",
+            "status": "TRADING",
+            "baseAsset": "BTC",
+            "baseAssetPrecision": 8,
+            "quoteAsset": "USDT",
+            "quotePrecision": 8,
+            "quoteAssetPrecision": 8,
+            "orderTypes": ["LIMIT", "LIMIT_MAKER", "MARKET", "STOP_LOSS_LIMIT", "TAKE_PROFIT_LIMIT"],
+            "icebergAllowed": true,
+            "ocoAllowed": true,
+            "isSpotTradingAllowed": true,
+            "isMarginTradingAllowed": true,
+            "filters": [
+                {"filterType": "PRICE_FILTER", "minPrice": "0.01000000", "maxPrice": "1000000.00000000", "tickSize": "0.01000000"},
+                {"filterType": "PERCENT_PRICE", "multiplierUp": "5", "multiplierDown": "0.2", "avgPriceMins": 5},
+                {"filterType": "LOT_SIZE", "minQty": "0.00000100", "maxQty": "9000.00000000", "stepSize": "0.00000100"},
+                {"filterType": "MIN_NOTIONAL", "minNotional": "10.00000000", "applyToMarket": true, "avgPriceMins": 5},
+                {"filterType": "ICEBERG_PARTS", "limit": 10},
+                {"filterType": "MARKET_LOT_SIZE", "minQty": "0.00000000", "maxQty": "100.00000000", "stepSize": "0.00000000"},
+                {"filterType": "MAX_NUM_ORDERS", "maxNumOrders": 200},
+                {"filterType": "MAX_NUM_ALGO_ORDERS", "maxNumAlgoOrders": 5}
+            ],
+            "permissions": ["SPOT", "MARGIN"]
+        }]
+    }"#;
+
+    #[test]
+    fn parse_exchange_info() {
+        let info: ExchangeInfo = serde_json::from_str(EXCHANGE_INFO).unwrap();
+        assert_eq!(info.rate_limits.len(), 3);
+        assert_eq!(info.rate_limits[1].interval_ms(), 10_000);
+
+        let sym = info.symbol("btcusdt").unwrap();
+        assert_eq!(sym.filters.len(), 8);
+        assert_eq!(sym.price_filter().unwrap().tick_size, 0.01);
+        assert_eq!(sym.percent_price().unwrap().multiplier_down, 0.2);
+        assert_eq!(sym.lot_size().unwrap().step_size, 0.000001);
+        assert_eq!(sym.min_notional().unwrap().min_notional, 10.0);
+        assert_eq!(sym.market_lot_size().unwrap().max_qty, 100.0);
+        assert_eq!(sym.max_num_orders().unwrap().max_num_orders, 200);
+        assert!(info.symbol("ETHUSDT").is_none());
+    }
+
+    #[test]
+    fn market_from_info() {
+        let info: ExchangeInfo = serde_json::from_str(EXCHANGE_INFO).unwrap();
+        let mut market =
+            Market::from_symbol_info(info.symbol("BTCUSDT").unwrap(), 0.0, 100.0, 30000.0, 0.001);
+        market.buy_max();
+        // 100 / 30000 = 0.0033333.. floored to the 0.000001 step
+        assert!((market.get_b_amount() - (100.0 - 0.003333 * 30000.0)).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,483 @@
+use std::collections::HashMap;
+
+use crate::builder::OrderBuilder;
+use crate::clock::Clock;
+use crate::error::{Error, Result};
+use crate::exchange_info::SymbolInfo;
+use crate::indicators::{Atr, Stream};
+use crate::position::{Position, Side};
+use crate::sizing::{Sizing, SizingInput};
+use crate::types::{
+    Candle, Intent, Market, Order, OrderInfo, OrderResponse, OrderSide, OrderStatus, Signal,
+};
+use crate::validation::round_down_to_step;
+
+#[derive(Debug, Clone)]
+struct Pending {
+    // Side the position moves to once the order fills
+    target: Side,
+    intent: Intent,
+    // Exchange reports cumulative quantity, only the difference is new
+    executed: f64,
+}
+
+pub struct Executor {
+    info: SymbolInfo,
+    sizing: Sizing,
+    // Fed only when the sizing needs it
+    atr: Option<Stream<Atr>>,
+    last_atr: Option<f64>,
+    position: Position,
+    // Last signal turned into orders, repeating it does nothing
+    last_signal: Option<Signal>,
+    // Orders sent and not finished yet, by client order id
+    pending: HashMap<String, Pending>,
+}
+
+impl Executor {
+    pub fn new(info: SymbolInfo) -> Executor {
+        Executor {
+            info,
+            sizing: Sizing::default(),
+            atr: None,
+            last_atr: None,
+            position: Position::new(),
+            last_signal: None,
+            pending: HashMap::new(),
+        }
+    }
+    pub fn with_sizing(mut self, sizing: Sizing) -> Executor {
+        self.atr = sizing
+            .atr_period()
+            .map(|period| Stream::new(Atr::new(period)));
+        self.sizing = sizing;
+        self
+    }
+    pub fn on_candle(&mut self, candle: &Candle) {
+        // Keeps indicators used by the sizing up to date, call with every candle or tick
+        if let Some(atr) = self.atr.as_mut() {
+            self.last_atr = atr.update(candle.timestamp(), *candle);
+        }
+    }
+    pub fn get_position(&self) -> &Position {
+        &self.position
+    }
+    pub fn get_symbol(&self) -> String {
+        self.info.symbol.clone()
+    }
+    pub fn pending(&self) -> Vec<String> {
+        // Client order ids of orders waiting for a report
+        self.pending.keys().cloned().collect()
+    }
+    pub fn forget(&mut self, client_order_id: &str) {
+        // Order was cancelled, the signal which placed it may act again
+        if self.pending.remove(client_order_id).is_some() {
+            self.last_signal = None;
+        }
+    }
+    pub fn is_repeated(&self, signal: &Signal) -> bool {
+        *signal == Signal::Sleep || self.last_signal.as_ref() == Some(signal)
+    }
+
+    fn quantity(&self, quantity: f64, price: f64) -> Option<f64> {
+        // Quantity capped and rounded to the lot filter, None if the exchange would not
+        // accept it at the price the order carries
+        let quantity = match self.info.lot_size() {
+            Some(lot) if lot.max_qty > 0.0 => {
+                round_down_to_step(quantity.min(lot.max_qty), lot.step_size)
+            }
+            Some(lot) => round_down_to_step(quantity, lot.step_size),
+            None => quantity,
+        };
+        let too_small = self
+            .info
+            .lot_size()
+            .is_some_and(|lot| quantity < lot.min_qty)
+            || self
+                .info
+                .min_notional()
+                .is_some_and(|min| quantity * price < min.min_notional);
+        if quantity <= 0.0 || too_small {
+            None
+        } else {
+            Some(quantity)
+        }
+    }
+
+    fn place(
+        &mut self,
+        builder: OrderBuilder,
+        target: Side,
+        intent: Intent,
+        price: f64,
+        clock: &Clock,
+    ) -> Result<Option<Order>> {
+        // Order the symbol filters reject is no order, not a failure of the bot
+        let order = match builder
+            .build(clock)
+            .and_then(|order| order.normalize(&self.info, Some(price)))
+        {
+            Ok(order) => order,
+            Err(Error::Validation(_)) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        if let Some(id) = order.get_new_client_order_id() {
+            let pending = Pending {
+                target,
+                intent,
+                executed: 0.0,
+            };
+            self.pending.insert(id, pending);
+        }
+        Ok(Some(order))
+    }
+
+    pub fn on_signal(
+        &mut self,
+        signal: &Signal,
+        market: &Market,
+        price: f64,
+        clock: &Clock,
+    ) -> Result<Vec<Order>> {
+        // Orders moving held amount of A to what the signal asks for, sized from market
+        // balances at the given price. Until the orders in flight are reported or
+        // forgotten new signals are ignored, and come back with the next tick
+        if self.is_repeated(signal) || !self.pending.is_empty() {
+            return Ok(vec![]);
+        }
+        self.last_signal = Some(signal.clone());
+        // Spot market can't borrow, short only closes the long position and never
+        // leaves a short one behind
+        let target = match self.position.target(signal) {
+            Some(Side::Short) => Side::Flat,
+            Some(target) => target,
+            None => return Ok(vec![]),
+        };
+        let intent = signal.get_intent().cloned().unwrap_or_default();
+        let held = market.get_a_amount();
+        let wanted = match target {
+            Side::Long => {
+                let input = SizingInput {
+                    price,
+                    equity: market.equity(),
+                    stop: self.stop(&intent, price),
+                    atr: self.last_atr,
+                    fraction: intent.get_fraction(),
+                };
+                match self.sizing.quantity(&input) {
+                    Some(wanted) => wanted,
+                    // Policy can't size it yet, signal may come again
+                    None => {
+                        self.last_signal = None;
+                        return Ok(vec![]);
+                    }
+                }
+            }
+            _ => 0.0,
+        };
+        let (side, quantity) = if wanted > held {
+            (OrderSide::BUY, wanted - held)
+        } else {
+            (OrderSide::SELL, held - wanted)
+        };
+        // Limit order is checked against the filters at its own price
+        let order_price = intent.get_limit().unwrap_or(price);
+        let quantity = match self.quantity(quantity, order_price) {
+            Some(quantity) => quantity,
+            // Long position needs something to hold
+            None if target == Side::Long && self.quantity(held, price).is_none() => {
+                self.last_signal = None;
+                return Ok(vec![]);
+            }
+            None => {
+                // Balances already match, only the exits are updated
+                self.position
+                    .open(target, clock.now_exchange_ms(), price, &intent);
+                return Ok(vec![]);
+            }
+        };
+        let symbol = self.get_symbol();
+        let builder = match intent.get_limit() {
+            Some(limit) => Order::limit(&symbol, side, limit, quantity),
+            None => Order::market(&symbol, side).quantity(quantity),
+        };
+        match self.place(builder, target, intent, price, clock)? {
+            Some(order) => Ok(vec![order]),
+            None => {
+                self.last_signal = None;
+                Ok(vec![])
+            }
+        }
+    }
+
+    fn stop(&self, intent: &Intent, price: f64) -> Option<f64> {
+        // Stop the position would have after entry at the price
+        intent
+            .get_stop_loss()
+            .or_else(|| intent.get_trailing_stop().map(|d| price * (1.0 - d)))
+            .or_else(|| self.position.stop_price())
+    }
+
+    pub fn check_exit(
+        &mut self,
+        candle: &Candle,
+        market: &Market,
+        clock: &Clock,
+    ) -> Result<Option<(f64, Order)>> {
+        // Price at which a stop or target of the position was hit and market order
+        // selling what is held. Last signal is kept, so repeating it won't reenter
+        let price = match self.position.check_exit(candle) {
+            Some(price) => price,
+            None => return Ok(None),
+        };
+        let quantity = match self.quantity(market.get_a_amount(), price) {
+            Some(quantity) => quantity,
+            None => return Ok(None),
+        };
+        let builder = Order::market_sell(&self.get_symbol()).quantity(quantity);
+        let order = self.place(builder, Side::Flat, Intent::default(), price, clock)?;
+        Ok(order.map(|order| (price, order)))
+    }
+
+    fn fill(&mut self, id: &str, status: OrderStatus, executed: f64, quote: f64, time: u64) {
+        let pending = match self.pending.get_mut(id) {
+            Some(pending) => pending,
+            None => return,
+        };
+        if executed > pending.executed {
+            pending.executed = executed;
+            let price = quote / executed;
+            let (target, intent) = (pending.target, pending.intent.clone());
+            self.position.open(target, time, price, &intent);
+        }
+        let finished = !matches!(status, OrderStatus::NEW | OrderStatus::PARTIALLY_FILLED);
+        if finished {
+            self.pending.remove(id);
+        }
+    }
+
+    pub fn report(&mut self, response: &OrderResponse, timestamp: u64) {
+        // Response to a placed order, ACK carries nothing about fills
+        let result = match response {
+            OrderResponse::Ack(_) => return,
+            OrderResponse::Result(result) => result,
+            OrderResponse::Full(full) => &full.result,
+        };
+        self.fill(
+            &result.client_order_id,
+            result.status,
+            result.executed_qty,
+            result.cummulative_quote_qty,
+            timestamp,
+        );
+    }
+
+    pub fn report_info(&mut self, info: &OrderInfo) {
+        // State of an order polled later, e.g. resting limit
+        self.fill(
+            &info.client_order_id,
+            info.status,
+            info.executed_qty,
+            info.cummulative_quote_qty,
+            info.update_time,
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::exchange_info::{Filter, LotSize, MinNotional};
+    use crate::fill::FillModel;
+    use crate::types::{OrderResponse, OrderType};
+
+    fn info() -> SymbolInfo {
+        SymbolInfo {
+            symbol: "BTCUSDT".to_string(),
+            status: "TRADING".to_string(),
+            base_asset: "BTC".to_string(),
+            base_asset_precision: 8,
+            quote_asset: "USDT".to_string(),
+            quote_asset_precision: 8,
+            order_types: vec![],
+            iceberg_allowed: true,
+            oco_allowed: true,
+            is_spot_trading_allowed: true,
+            filters: vec![
+                Filter::LotSize(LotSize {
+                    min_qty: 0.001,
+                    max_qty: 9000.0,
+                    step_size: 0.001,
+                }),
+                Filter::MinNotional(MinNotional {
+                    min_notional: 10.0,
+                    apply_to_market: true,
+                    avg_price_mins: 5,
+                }),
+            ],
+        }
+    }
+
+    fn place(market: &mut Market, order: &Order, ts: u64) -> OrderResponse {
+        // Candle of a single price, the market ratio
+        let price = market.get_ratio();
+        let candle = Candle::new(ts, price, price, price, price, 1.0);
+        market.place(order.clone(), &FillModel::new(), &candle)
+    }
+
+    fn run(executor: &mut Executor, market: &mut Market, signal: &Signal, ts: u64) -> usize {
+        let orders = executor
+            .on_signal(signal, market, market.get_ratio(), &Clock::new())
+            .unwrap();
+        for order in orders.iter() {
+            executor.report(&place(market, order, ts), ts);
+        }
+        orders.len()
+    }
+
+    #[test]
+    fn sizing_and_dedupe() {
+        let mut executor = Executor::new(info());
+        let mut market = Market::new(0.0, 1000.0, 300.0, 0.001, 0.001, 0.0);
+        let clock = Clock::new();
+        let signal = Signal::long().fraction(0.5);
+        let orders = executor.on_signal(&signal, &market, 300.0, &clock).unwrap();
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].get_type(), OrderType::MARKET);
+        assert_eq!(orders[0].get_side(), OrderSide::BUY);
+        // 500 / 300 rounded down to the lot step
+        assert_eq!(orders[0].get_quantity(), Some(1.666));
+        // Nothing new while the order is in flight, nor for the same signal later
+        assert!(executor
+            .on_signal(&signal, &market, 300.0, &clock)
+            .unwrap()
+            .is_empty());
+        executor.report(&place(&mut market, &orders[0], 60_000), 60_000);
+        assert_eq!(executor.get_position().get_side(), Side::Long);
+        assert_eq!(executor.get_position().get_entry_time(), 60_000);
+        assert!(executor.pending().is_empty());
+        assert!(executor
+            .on_signal(&signal, &market, 300.0, &clock)
+            .unwrap()
+            .is_empty());
+
+        let orders = executor
+            .on_signal(&Signal::Flat, &market, 300.0, &clock)
+            .unwrap();
+        assert_eq!(orders[0].get_side(), OrderSide::SELL);
+        assert_eq!(orders[0].get_quantity(), Some(1.666));
+        executor.report(&place(&mut market, &orders[0], 120_000), 120_000);
+        assert!(executor.get_position().is_flat());
+    }
+
+    #[test]
+    fn sizing_policies() {
+        let mut market = Market::new(0.0, 1000.0, 100.0, 0.001, 0.001, 0.0);
+        let clock = Clock::new();
+        let mut executor = Executor::new(info()).with_sizing(Sizing::FixedRisk(0.01));
+        // Risk sizing without a stop has nothing to go on
+        assert!(executor
+            .on_signal(&Signal::long(), &market, 100.0, &clock)
+            .unwrap()
+            .is_empty());
+        let signal = Signal::long().stop_loss(98.0);
+        let orders = executor.on_signal(&signal, &market, 100.0, &clock).unwrap();
+        assert_eq!(orders[0].get_quantity(), Some(5.0));
+
+        let volatility = Sizing::Volatility {
+            risk: 0.01,
+            period: 2,
+        };
+        let mut executor = Executor::new(info()).with_sizing(volatility);
+        assert_eq!(run(&mut executor, &mut market, &Signal::long(), 0), 0);
+        for ts in 0..3 {
+            executor.on_candle(&Candle::new(ts, 100.0, 102.0, 98.0, 100.0, 1.0));
+        }
+        // ATR of 4 risks 10 of 1000 with 2.5 A
+        let orders = executor
+            .on_signal(&Signal::long(), &market, 100.0, &clock)
+            .unwrap();
+        assert_eq!(orders[0].get_quantity(), Some(2.5));
+    }
+
+    #[test]
+    fn limits_and_exits() {
+        let mut executor = Executor::new(info());
+        let mut market = Market::new(0.0, 1000.0, 100.0, 0.001, 0.001, 0.0);
+        let clock = Clock::new();
+        let signal = Signal::long().limit(90.0).stop_loss(80.0);
+        let order = executor.on_signal(&signal, &market, 100.0, &clock).unwrap()[0].clone();
+        assert_eq!(order.get_price(), Some(90.0));
+        // Not reached yet, order keeps waiting
+        executor.report(&place(&mut market, &order, 0), 0);
+        assert_eq!(executor.pending().len(), 1);
+        assert!(executor.get_position().is_flat());
+        // Cancelled order lets the same signal through again
+        executor.forget(&order.get_new_client_order_id().unwrap());
+        assert!(!executor.is_repeated(&signal));
+
+        assert_eq!(
+            run(
+                &mut executor,
+                &mut market,
+                &Signal::long().stop_loss(80.0),
+                0
+            ),
+            1
+        );
+        let candle = Candle::new(60_000, 100.0, 101.0, 75.0, 90.0, 1.0);
+        let (price, order) = executor
+            .check_exit(&candle, &market, &clock)
+            .unwrap()
+            .unwrap();
+        assert_eq!(price, 80.0);
+        assert_eq!(order.get_quantity(), Some(10.0));
+        market.update_ratio(price);
+        executor.report(&place(&mut market, &order, 60_000), 60_000);
+        assert!(executor.get_position().is_flat());
+        assert_eq!(market.get_b_amount(), 800.0);
+        // Balance too small for the exchange gives no order
+        let mut poor = Market::new(0.0, 5.0, 100.0, 0.001, 0.001, 0.0);
+        assert_eq!(
+            run(&mut Executor::new(info()), &mut poor, &Signal::long(), 0),
+            0
+        );
+    }
+
+    #[test]
+    fn spot_shorts() {
+        let mut executor = Executor::new(info());
+        let mut market = Market::new(0.0, 1000.0, 100.0, 0.001, 0.001, 0.0);
+        run(&mut executor, &mut market, &Signal::long(), 0);
+        let short = Signal::short().stop_loss(110.0).take_profit(90.0);
+        assert_eq!(run(&mut executor, &mut market, &short, 60_000), 1);
+        assert_eq!(market.get_a_amount(), 0.0);
+        // Nothing held means no position and no exits to trigger
+        assert!(executor.get_position().is_flat());
+        assert_eq!(executor.get_position().stop_price(), None);
+        let candle = Candle::new(120_000, 100.0, 120.0, 80.0, 100.0, 1.0);
+        assert!(executor
+            .check_exit(&candle, &market, &Clock::new())
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn filter_rejections() {
+        let clock = Clock::new();
+        // Notional is checked at the limit, far below the market it is too small
+        let market = Market::new(0.0, 15.0, 100.0, 0.001, 0.001, 0.0);
+        let mut executor = Executor::new(info());
+        let signal = Signal::long().limit(50.0);
+        let orders = executor.on_signal(&signal, &market, 100.0, &clock).unwrap();
+        assert!(orders.is_empty());
+        assert!(!executor.is_repeated(&signal));
+        assert!(executor.pending().is_empty());
+        // Quantity is capped by the lot filter
+        let rich = Market::new(0.0, 1_000_000.0, 100.0, 0.001, 0.001, 0.0);
+        let orders = executor
+            .on_signal(&Signal::long(), &rich, 100.0, &clock)
+            .unwrap();
+        assert_eq!(orders[0].get_quantity(), Some(9000.0));
+    }
+}
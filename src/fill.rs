@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+
+use crate::types::{Candle, OrderSide};
+
+// Basis point is 0.01%
+const BPS: f64 = 10_000.0;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Slippage {
+    None,
+    // Constant move against the order, in basis points
+    Bps(f64),
+    // Move in basis points for an order as large as the whole candle volume,
+    // smaller orders slip proportionally less
+    Volume(f64),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Timing {
+    // Orders for a signal fill at the close of the candle which produced it
+    SameBarClose,
+    // Orders for a signal fill at the open of the following candle
+    NextBarOpen,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct FillModel {
+    slippage: Slippage,
+    // Whole distance between bid and ask in basis points, candles are taken as mid prices
+    spread: f64,
+    timing: Timing,
+}
+
+impl Default for FillModel {
+    fn default() -> FillModel {
+        FillModel::new()
+    }
+}
+
+impl FillModel {
+    pub fn new() -> FillModel {
+        // Fills exactly at candle prices, no costs besides the market fee
+        FillModel {
+            slippage: Slippage::None,
+            spread: 0.0,
+            timing: Timing::SameBarClose,
+        }
+    }
+    pub fn with_slippage(mut self, slippage: Slippage) -> FillModel {
+        self.slippage = slippage;
+        self
+    }
+    pub fn with_spread(mut self, spread_bps: f64) -> FillModel {
+        self.spread = spread_bps.max(0.0);
+        self
+    }
+    pub fn with_timing(mut self, timing: Timing) -> FillModel {
+        self.timing = timing;
+        self
+    }
+    pub fn get_slippage(&self) -> Slippage {
+        self.slippage
+    }
+    pub fn get_spread(&self) -> f64 {
+        self.spread
+    }
+    pub fn get_timing(&self) -> Timing {
+        self.timing
+    }
+
+    fn quote(&self, side: OrderSide, mid: f64) -> f64 {
+        // Ask for buys and bid for sells
+        let half = self.spread / 2.0 / BPS;
+        match side {
+            OrderSide::BUY => mid * (1.0 + half),
+            OrderSide::SELL => mid * (1.0 - half),
+        }
+    }
+
+    pub fn market_price(&self, side: OrderSide, mid: f64, quantity: f64, candle: &Candle) -> f64 {
+        // Price a market order gets when the market trades at mid
+        let slip = match self.slippage {
+            Slippage::None => 0.0,
+            Slippage::Bps(bps) => bps / BPS,
+            // Candle without volume can't absorb anything, full impact applies
+            Slippage::Volume(bps) if candle.volume() > 0.0 => {
+                bps / BPS * (quantity / candle.volume()).min(1.0)
+            }
+            Slippage::Volume(bps) => bps / BPS,
+        };
+        let price = self.quote(side, mid);
+        match side {
+            OrderSide::BUY => price * (1.0 + slip),
+            OrderSide::SELL => price * (1.0 - slip),
+        }
+    }
+
+    pub fn limit_price(&self, side: OrderSide, limit: f64, candle: &Candle) -> Option<f64> {
+        // Price a resting limit order fills at during the candle. Opening beyond the limit
+        // fills at the open, otherwise the price has to trade through the limit, as just
+        // touching it does not mean our order was reached in the queue
+        let open = self.quote(side, candle.open());
+        match side {
+            OrderSide::BUY if open <= limit => Some(open),
+            OrderSide::BUY if self.quote(side, candle.low()) < limit => Some(limit),
+            OrderSide::SELL if open >= limit => Some(open),
+            OrderSide::SELL if self.quote(side, candle.high()) > limit => Some(limit),
+            _ => None,
+        }
+    }
+
+    pub fn marketable(&self, side: OrderSide, limit: f64, mid: f64) -> Option<f64> {
+        // Price a new limit order fills at right away, None if it has to rest
+        let price = self.quote(side, mid);
+        match side {
+            OrderSide::BUY if price <= limit => Some(price),
+            OrderSide::SELL if price >= limit => Some(price),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn candle() -> Candle {
+        Candle::new(0, 100.0, 104.0, 96.0, 102.0, 50.0)
+    }
+
+    #[test]
+    fn market_prices() {
+        let plain = FillModel::new();
+        assert_eq!(
+            plain.market_price(OrderSide::BUY, 100.0, 1.0, &candle()),
+            100.0
+        );
+        let model = FillModel::new()
+            .with_spread(20.0)
+            .with_slippage(Slippage::Bps(10.0));
+        let buy = model.market_price(OrderSide::BUY, 100.0, 1.0, &candle());
+        assert!((buy - 100.0 * 1.001 * 1.001).abs() < 1e-9);
+        let sell = model.market_price(OrderSide::SELL, 100.0, 1.0, &candle());
+        assert!((sell - 100.0 * 0.999 * 0.999).abs() < 1e-9);
+        // Tenth of the candle volume slips a tenth of the full impact
+        let volume = FillModel::new().with_slippage(Slippage::Volume(100.0));
+        let buy = volume.market_price(OrderSide::BUY, 100.0, 5.0, &candle());
+        assert!((buy - 100.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn limit_prices() {
+        let plain = FillModel::new();
+        assert_eq!(
+            plain.limit_price(OrderSide::BUY, 98.0, &candle()),
+            Some(98.0)
+        );
+        // Touching the low is not enough
+        assert_eq!(plain.limit_price(OrderSide::BUY, 96.0, &candle()), None);
+        assert_eq!(plain.limit_price(OrderSide::SELL, 104.0, &candle()), None);
+        assert_eq!(
+            plain.limit_price(OrderSide::SELL, 99.0, &candle()),
+            Some(100.0)
+        );
+        // Ask has to get under the limit
+        let spread = FillModel::new().with_spread(200.0);
+        assert_eq!(spread.limit_price(OrderSide::BUY, 96.5, &candle()), None);
+        assert_eq!(
+            spread.limit_price(OrderSide::BUY, 98.0, &candle()),
+            Some(98.0)
+        );
+        assert_eq!(spread.marketable(OrderSide::BUY, 100.5, 100.0), None);
+        assert_eq!(spread.marketable(OrderSide::SELL, 98.0, 100.0), Some(99.0));
+    }
+}
@@ -0,0 +1,1576 @@
+use std::collections::VecDeque;
+
+use crate::types::{Candle, CandleLine};
+
+pub trait Indicator {
+    type Input;
+    type Output;
+    // Feeds the value of a new bar
+    fn next(&mut self, input: Self::Input) -> Self::Output;
+    // Feeds updated value of the last bar, like a tick of a candle which is not closed yet
+    fn replace(&mut self, input: Self::Input) -> Self::Output;
+    fn reset(&mut self);
+}
+
+pub fn batch<I, T>(indicator: &mut I, inputs: T) -> Vec<I::Output>
+where
+    I: Indicator,
+    T: IntoIterator<Item = I::Input>,
+{
+    // Output for every input, as if they were all closed bars
+    inputs.into_iter().map(|i| indicator.next(i)).collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct Stream<I> {
+    indicator: I,
+    last_ts: Option<u64>,
+    bars: usize,
+}
+
+impl<I: Indicator> Stream<I> {
+    pub fn new(indicator: I) -> Stream<I> {
+        Stream {
+            indicator,
+            last_ts: None,
+            bars: 0,
+        }
+    }
+    pub fn update(&mut self, timestamp: u64, input: I::Input) -> I::Output {
+        // Input with the open time of the previous one replaces it
+        if self.last_ts == Some(timestamp) {
+            self.indicator.replace(input)
+        } else {
+            self.last_ts = Some(timestamp);
+            self.bars += 1;
+            self.indicator.next(input)
+        }
+    }
+    pub fn bars(&self) -> usize {
+        // Number of distinct bars seen so far
+        self.bars
+    }
+    pub fn get_indicator(&self) -> &I {
+        &self.indicator
+    }
+    pub fn reset(&mut self) {
+        self.indicator.reset();
+        self.last_ts = None;
+        self.bars = 0;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Window {
+    size: usize,
+    values: VecDeque<f64>,
+    sum: f64,
+}
+
+impl Window {
+    pub fn new(size: usize) -> Window {
+        Window {
+            size: size.max(1),
+            values: VecDeque::with_capacity(size.max(1)),
+            sum: 0.0,
+        }
+    }
+    pub fn push(&mut self, value: f64) -> Option<f64> {
+        // Adds value, returns the one which fell out of the window
+        let evicted = if self.values.len() == self.size {
+            self.values.pop_front()
+        } else {
+            None
+        };
+        self.values.push_back(value);
+        self.sum += value - evicted.unwrap_or(0.0);
+        evicted
+    }
+    pub fn replace_last(&mut self, value: f64) -> Option<f64> {
+        let last = self.values.back_mut()?;
+        let old = std::mem::replace(last, value);
+        self.sum += value - old;
+        Some(old)
+    }
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+    pub fn is_full(&self) -> bool {
+        self.values.len() == self.size
+    }
+    pub fn get_size(&self) -> usize {
+        self.size
+    }
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+    pub fn mean(&self) -> Option<f64> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self.sum / self.values.len() as f64)
+        }
+    }
+    pub fn values(&self) -> &VecDeque<f64> {
+        &self.values
+    }
+    pub fn clear(&mut self) {
+        self.values.clear();
+        self.sum = 0.0;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Sma {
+    window: Window,
+}
+
+impl Sma {
+    pub fn new(period: usize) -> Sma {
+        Sma {
+            window: Window::new(period),
+        }
+    }
+    fn value(&self) -> Option<f64> {
+        // Nothing until the window is full
+        if self.window.is_full() {
+            self.window.mean()
+        } else {
+            None
+        }
+    }
+}
+
+impl Indicator for Sma {
+    type Input = f64;
+    type Output = Option<f64>;
+    fn next(&mut self, input: f64) -> Option<f64> {
+        self.window.push(input);
+        self.value()
+    }
+    fn replace(&mut self, input: f64) -> Option<f64> {
+        if self.window.replace_last(input).is_none() {
+            self.window.push(input);
+        }
+        self.value()
+    }
+    fn reset(&mut self) {
+        self.window.clear();
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct HeikinAshi {
+    // Heikin Ashi candles of the previous and the current bar
+    prev: Option<Candle>,
+    current: Option<Candle>,
+}
+
+impl HeikinAshi {
+    pub fn new() -> HeikinAshi {
+        HeikinAshi::default()
+    }
+    fn calculate(&mut self, candle: Candle) -> Candle {
+        let close = (candle.open() + candle.high() + candle.low() + candle.close()) / 4.0;
+        // First candle has no predecessor, so its open is synthetic
+        let open = match self.prev {
+            Some(prev) => (prev.open() + prev.close()) / 2.0,
+            None => (candle.open() + candle.close()) / 2.0,
+        };
+        let ha = Candle::new(
+            candle.timestamp(),
+            open,
+            candle.high().max(open).max(close),
+            candle.low().min(open).min(close),
+            close,
+            candle.volume(),
+        );
+        self.current = Some(ha);
+        ha
+    }
+}
+
+impl Indicator for HeikinAshi {
+    type Input = Candle;
+    type Output = Candle;
+    fn next(&mut self, input: Candle) -> Candle {
+        if self.current.is_some() {
+            self.prev = self.current;
+        }
+        self.calculate(input)
+    }
+    fn replace(&mut self, input: Candle) -> Candle {
+        self.calculate(input)
+    }
+    fn reset(&mut self) {
+        self.prev = None;
+        self.current = None;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Extremum {
+    period: usize,
+    max: bool,
+    // Monotonic queue of closed bars within the window, best value at the front
+    queue: VecDeque<(usize, f64)>,
+    bars: usize,
+    current: Option<f64>,
+}
+
+impl Extremum {
+    pub fn max(period: usize) -> Extremum {
+        Extremum::new(period, true)
+    }
+    pub fn min(period: usize) -> Extremum {
+        Extremum::new(period, false)
+    }
+    fn new(period: usize, max: bool) -> Extremum {
+        Extremum {
+            period: period.max(1),
+            max,
+            queue: VecDeque::new(),
+            bars: 0,
+            current: None,
+        }
+    }
+    fn better(&self, a: f64, b: f64) -> bool {
+        if self.max {
+            a >= b
+        } else {
+            a <= b
+        }
+    }
+    fn value(&self) -> f64 {
+        // Last bar may still change, so it is kept out of the queue
+        let current = self.current.unwrap_or(f64::NAN);
+        match self.queue.front() {
+            Some(&(_, best)) if self.better(best, current) => best,
+            _ => current,
+        }
+    }
+    pub fn is_full(&self) -> bool {
+        self.bars >= self.period
+    }
+}
+
+impl Indicator for Extremum {
+    type Input = f64;
+    type Output = f64;
+    fn next(&mut self, input: f64) -> f64 {
+        if let Some(closed) = self.current {
+            while let Some(&(_, v)) = self.queue.back() {
+                if self.better(closed, v) {
+                    self.queue.pop_back();
+                } else {
+                    break;
+                }
+            }
+            self.queue.push_back((self.bars - 1, closed));
+        }
+        self.bars += 1;
+        self.current = Some(input);
+        while let Some(&(i, _)) = self.queue.front() {
+            if i + self.period < self.bars {
+                self.queue.pop_front();
+            } else {
+                break;
+            }
+        }
+        self.value()
+    }
+    fn replace(&mut self, input: f64) -> f64 {
+        if self.current.is_none() {
+            return self.next(input);
+        }
+        self.current = Some(input);
+        self.value()
+    }
+    fn reset(&mut self) {
+        self.queue.clear();
+        self.bars = 0;
+        self.current = None;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bands {
+    pub upper: f64,
+    pub middle: f64,
+    pub lower: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct EmaState {
+    count: usize,
+    // Sum of the first values, used as seed
+    sum: f64,
+    value: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Ema {
+    period: usize,
+    alpha: f64,
+    committed: EmaState,
+    state: EmaState,
+}
+
+impl Ema {
+    pub fn new(period: usize) -> Ema {
+        let period = period.max(1);
+        Ema {
+            period,
+            alpha: 2.0 / (period as f64 + 1.0),
+            committed: EmaState::default(),
+            state: EmaState::default(),
+        }
+    }
+    fn step(&mut self, input: f64) -> Option<f64> {
+        // Seeded with simple average of the first period values
+        let mut state = self.committed;
+        state.count += 1;
+        if state.count < self.period {
+            state.sum += input;
+        } else if state.count == self.period {
+            state.value = (state.sum + input) / self.period as f64;
+        } else {
+            state.value += self.alpha * (input - state.value);
+        }
+        self.state = state;
+        if state.count >= self.period {
+            Some(state.value)
+        } else {
+            None
+        }
+    }
+}
+
+impl Indicator for Ema {
+    type Input = f64;
+    type Output = Option<f64>;
+    fn next(&mut self, input: f64) -> Option<f64> {
+        self.committed = self.state;
+        self.step(input)
+    }
+    fn replace(&mut self, input: f64) -> Option<f64> {
+        self.step(input)
+    }
+    fn reset(&mut self) {
+        self.committed = EmaState::default();
+        self.state = EmaState::default();
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Wma {
+    window: Window,
+    // Sum of values weighted 1 for the oldest up to window length for the newest
+    weighted: f64,
+}
+
+impl Wma {
+    pub fn new(period: usize) -> Wma {
+        Wma {
+            window: Window::new(period),
+            weighted: 0.0,
+        }
+    }
+    fn value(&self) -> Option<f64> {
+        let n = self.window.get_size() as f64;
+        if self.window.is_full() {
+            Some(self.weighted / (n * (n + 1.0) / 2.0))
+        } else {
+            None
+        }
+    }
+}
+
+impl Indicator for Wma {
+    type Input = f64;
+    type Output = Option<f64>;
+    fn next(&mut self, input: f64) -> Option<f64> {
+        if self.window.is_full() {
+            // Every value loses one weight, the oldest one drops to zero
+            self.weighted += self.window.get_size() as f64 * input - self.window.sum();
+        } else {
+            self.weighted += (self.window.len() + 1) as f64 * input;
+        }
+        self.window.push(input);
+        self.value()
+    }
+    fn replace(&mut self, input: f64) -> Option<f64> {
+        match self.window.replace_last(input) {
+            Some(old) => self.weighted += self.window.len() as f64 * (input - old),
+            None => return self.next(input),
+        }
+        self.value()
+    }
+    fn reset(&mut self) {
+        self.window.clear();
+        self.weighted = 0.0;
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct WilderState {
+    count: usize,
+    sum: f64,
+    value: f64,
+}
+
+impl WilderState {
+    fn step(mut self, period: usize, input: f64) -> WilderState {
+        // Wilder smoothing seeded with simple average of the first period values
+        self.count += 1;
+        if self.count <= period {
+            self.sum += input;
+            self.value = self.sum / self.count as f64;
+        } else {
+            self.value = (self.value * (period as f64 - 1.0) + input) / period as f64;
+        }
+        self
+    }
+    fn ready(&self, period: usize) -> Option<f64> {
+        if self.count >= period {
+            Some(self.value)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Smma {
+    // Smoothed (Wilder's, also RMA) moving average, like EMA with alpha 1 / period
+    period: usize,
+    committed: WilderState,
+    state: WilderState,
+}
+
+impl Smma {
+    pub fn new(period: usize) -> Smma {
+        Smma {
+            period: period.max(1),
+            committed: WilderState::default(),
+            state: WilderState::default(),
+        }
+    }
+}
+
+impl Indicator for Smma {
+    type Input = f64;
+    type Output = Option<f64>;
+    fn next(&mut self, input: f64) -> Option<f64> {
+        self.committed = self.state;
+        self.replace(input)
+    }
+    fn replace(&mut self, input: f64) -> Option<f64> {
+        self.state = self.committed.step(self.period, input);
+        self.state.ready(self.period)
+    }
+    fn reset(&mut self) {
+        self.committed = WilderState::default();
+        self.state = WilderState::default();
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct RsiState {
+    prev: Option<f64>,
+    gain: WilderState,
+    loss: WilderState,
+}
+
+#[derive(Debug, Clone)]
+pub struct Rsi {
+    period: usize,
+    committed: RsiState,
+    state: RsiState,
+}
+
+impl Rsi {
+    pub fn new(period: usize) -> Rsi {
+        Rsi {
+            period: period.max(1),
+            committed: RsiState::default(),
+            state: RsiState::default(),
+        }
+    }
+    fn step(&mut self, input: f64) -> Option<f64> {
+        let mut state = self.committed;
+        if let Some(prev) = state.prev {
+            let change = input - prev;
+            state.gain = state.gain.step(self.period, change.max(0.0));
+            state.loss = state.loss.step(self.period, (-change).max(0.0));
+        }
+        state.prev = Some(input);
+        self.state = state;
+        let gain = state.gain.ready(self.period)?;
+        let loss = state.loss.ready(self.period)?;
+        if loss == 0.0 {
+            // No losses in the window, flat prices count as neutral
+            Some(if gain == 0.0 { 50.0 } else { 100.0 })
+        } else {
+            Some(100.0 - 100.0 / (1.0 + gain / loss))
+        }
+    }
+}
+
+impl Indicator for Rsi {
+    type Input = f64;
+    type Output = Option<f64>;
+    fn next(&mut self, input: f64) -> Option<f64> {
+        self.committed = self.state;
+        self.step(input)
+    }
+    fn replace(&mut self, input: f64) -> Option<f64> {
+        self.step(input)
+    }
+    fn reset(&mut self) {
+        self.committed = RsiState::default();
+        self.state = RsiState::default();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MacdValue {
+    pub macd: f64,
+    pub signal: Option<f64>,
+    pub histogram: Option<f64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Macd {
+    fast: Ema,
+    slow: Ema,
+    signal: Ema,
+}
+
+impl Macd {
+    pub fn new(fast: usize, slow: usize, signal: usize) -> Macd {
+        Macd {
+            fast: Ema::new(fast),
+            slow: Ema::new(slow),
+            signal: Ema::new(signal),
+        }
+    }
+    fn value(&mut self, fast: Option<f64>, slow: Option<f64>, next: bool) -> Option<MacdValue> {
+        // Signal line is fed only once both averages exist
+        let macd = fast? - slow?;
+        let signal = if next {
+            self.signal.next(macd)
+        } else {
+            self.signal.replace(macd)
+        };
+        Some(MacdValue {
+            macd,
+            signal,
+            histogram: signal.map(|s| macd - s),
+        })
+    }
+}
+
+impl Default for Macd {
+    fn default() -> Macd {
+        Macd::new(12, 26, 9)
+    }
+}
+
+impl Indicator for Macd {
+    type Input = f64;
+    type Output = Option<MacdValue>;
+    fn next(&mut self, input: f64) -> Option<MacdValue> {
+        let fast = self.fast.next(input);
+        let slow = self.slow.next(input);
+        self.value(fast, slow, true)
+    }
+    fn replace(&mut self, input: f64) -> Option<MacdValue> {
+        let fast = self.fast.replace(input);
+        let slow = self.slow.replace(input);
+        self.value(fast, slow, false)
+    }
+    fn reset(&mut self) {
+        self.fast.reset();
+        self.slow.reset();
+        self.signal.reset();
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Bollinger {
+    values: Window,
+    k: f64,
+}
+
+impl Bollinger {
+    pub fn new(period: usize, k: f64) -> Bollinger {
+        Bollinger {
+            values: Window::new(period),
+            k,
+        }
+    }
+    fn value(&self) -> Option<Bands> {
+        if !self.values.is_full() {
+            return None;
+        }
+        // Population deviation in two passes over the window, mean of squares minus
+        // square of the mean loses all precision once prices are large against their
+        // spread
+        let values = self.values.values();
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+        let deviation = variance.sqrt();
+        Some(Bands {
+            upper: mean + self.k * deviation,
+            middle: mean,
+            lower: mean - self.k * deviation,
+        })
+    }
+}
+
+impl Default for Bollinger {
+    fn default() -> Bollinger {
+        Bollinger::new(20, 2.0)
+    }
+}
+
+impl Indicator for Bollinger {
+    type Input = f64;
+    type Output = Option<Bands>;
+    fn next(&mut self, input: f64) -> Option<Bands> {
+        self.values.push(input);
+        self.value()
+    }
+    fn replace(&mut self, input: f64) -> Option<Bands> {
+        if self.values.replace_last(input).is_none() {
+            return self.next(input);
+        }
+        self.value()
+    }
+    fn reset(&mut self) {
+        self.values.clear();
+    }
+}
+
+fn true_range(candle: &Candle, prev_close: Option<f64>) -> f64 {
+    let range = candle.high() - candle.low();
+    match prev_close {
+        Some(close) => range
+            .max((candle.high() - close).abs())
+            .max((candle.low() - close).abs()),
+        None => range,
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct AtrState {
+    prev_close: Option<f64>,
+    tr: WilderState,
+}
+
+#[derive(Debug, Clone)]
+pub struct Atr {
+    period: usize,
+    committed: AtrState,
+    state: AtrState,
+}
+
+impl Atr {
+    pub fn new(period: usize) -> Atr {
+        Atr {
+            period: period.max(1),
+            committed: AtrState::default(),
+            state: AtrState::default(),
+        }
+    }
+    fn step(&mut self, input: Candle) -> Option<f64> {
+        let mut state = self.committed;
+        state.tr = state
+            .tr
+            .step(self.period, true_range(&input, state.prev_close));
+        state.prev_close = Some(input.close());
+        self.state = state;
+        state.tr.ready(self.period)
+    }
+}
+
+impl Indicator for Atr {
+    type Input = Candle;
+    type Output = Option<f64>;
+    fn next(&mut self, input: Candle) -> Option<f64> {
+        self.committed = self.state;
+        self.step(input)
+    }
+    fn replace(&mut self, input: Candle) -> Option<f64> {
+        self.step(input)
+    }
+    fn reset(&mut self) {
+        self.committed = AtrState::default();
+        self.state = AtrState::default();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StochasticValue {
+    pub k: f64,
+    pub d: Option<f64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Stochastic {
+    highest: Extremum,
+    lowest: Extremum,
+    d: Sma,
+}
+
+impl Stochastic {
+    pub fn new(k_period: usize, d_period: usize) -> Stochastic {
+        Stochastic {
+            highest: Extremum::max(k_period),
+            lowest: Extremum::min(k_period),
+            d: Sma::new(d_period),
+        }
+    }
+    fn value(&mut self, close: f64, high: f64, low: f64, next: bool) -> Option<StochasticValue> {
+        if !self.highest.is_full() {
+            return None;
+        }
+        // Flat window has no range, close is then in the middle of it
+        let k = if high > low {
+            100.0 * (close - low) / (high - low)
+        } else {
+            50.0
+        };
+        let d = if next {
+            self.d.next(k)
+        } else {
+            self.d.replace(k)
+        };
+        Some(StochasticValue { k, d })
+    }
+}
+
+impl Default for Stochastic {
+    fn default() -> Stochastic {
+        Stochastic::new(14, 3)
+    }
+}
+
+impl Indicator for Stochastic {
+    type Input = Candle;
+    type Output = Option<StochasticValue>;
+    fn next(&mut self, input: Candle) -> Option<StochasticValue> {
+        let high = self.highest.next(input.high());
+        let low = self.lowest.next(input.low());
+        self.value(input.close(), high, low, true)
+    }
+    fn replace(&mut self, input: Candle) -> Option<StochasticValue> {
+        let high = self.highest.replace(input.high());
+        let low = self.lowest.replace(input.low());
+        self.value(input.close(), high, low, false)
+    }
+    fn reset(&mut self) {
+        self.highest.reset();
+        self.lowest.reset();
+        self.d.reset();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdxValue {
+    pub plus_di: f64,
+    pub minus_di: f64,
+    pub adx: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct AdxState {
+    prev: Option<Candle>,
+    count: usize,
+    // Wilder sums of true range and directional movements
+    tr: f64,
+    plus_dm: f64,
+    minus_dm: f64,
+    dx: WilderState,
+}
+
+#[derive(Debug, Clone)]
+pub struct Adx {
+    period: usize,
+    committed: AdxState,
+    state: AdxState,
+}
+
+impl Adx {
+    pub fn new(period: usize) -> Adx {
+        Adx {
+            period: period.max(1),
+            committed: AdxState::default(),
+            state: AdxState::default(),
+        }
+    }
+    fn step(&mut self, input: Candle) -> Option<AdxValue> {
+        let mut state = self.committed;
+        let prev = state.prev.replace(input);
+        self.state = state;
+        let prev = prev?;
+        let up = input.high() - prev.high();
+        let down = prev.low() - input.low();
+        let plus_dm = if up > down && up > 0.0 { up } else { 0.0 };
+        let minus_dm = if down > up && down > 0.0 { down } else { 0.0 };
+        let tr = true_range(&input, Some(prev.close()));
+        let n = self.period as f64;
+        state.count += 1;
+        if state.count <= self.period {
+            state.tr += tr;
+            state.plus_dm += plus_dm;
+            state.minus_dm += minus_dm;
+        } else {
+            state.tr += tr - state.tr / n;
+            state.plus_dm += plus_dm - state.plus_dm / n;
+            state.minus_dm += minus_dm - state.minus_dm / n;
+        }
+        if state.count < self.period {
+            self.state = state;
+            return None;
+        }
+        let (plus_di, minus_di) = if state.tr > 0.0 {
+            (
+                100.0 * state.plus_dm / state.tr,
+                100.0 * state.minus_dm / state.tr,
+            )
+        } else {
+            (0.0, 0.0)
+        };
+        let dx = if plus_di + minus_di > 0.0 {
+            100.0 * (plus_di - minus_di).abs() / (plus_di + minus_di)
+        } else {
+            0.0
+        };
+        state.dx = state.dx.step(self.period, dx);
+        self.state = state;
+        Some(AdxValue {
+            plus_di,
+            minus_di,
+            adx: state.dx.ready(self.period),
+        })
+    }
+}
+
+impl Indicator for Adx {
+    type Input = Candle;
+    type Output = Option<AdxValue>;
+    fn next(&mut self, input: Candle) -> Option<AdxValue> {
+        self.committed = self.state;
+        self.step(input)
+    }
+    fn replace(&mut self, input: Candle) -> Option<AdxValue> {
+        self.step(input)
+    }
+    fn reset(&mut self) {
+        self.committed = AdxState::default();
+        self.state = AdxState::default();
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct ObvState {
+    prev_close: Option<f64>,
+    value: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Obv {
+    committed: ObvState,
+    state: ObvState,
+}
+
+impl Obv {
+    pub fn new() -> Obv {
+        Obv::default()
+    }
+    fn step(&mut self, input: Candle) -> f64 {
+        let mut state = self.committed;
+        if let Some(prev) = state.prev_close {
+            if input.close() > prev {
+                state.value += input.volume();
+            } else if input.close() < prev {
+                state.value -= input.volume();
+            }
+        }
+        state.prev_close = Some(input.close());
+        self.state = state;
+        state.value
+    }
+}
+
+impl Indicator for Obv {
+    type Input = Candle;
+    type Output = f64;
+    fn next(&mut self, input: Candle) -> f64 {
+        self.committed = self.state;
+        self.step(input)
+    }
+    fn replace(&mut self, input: Candle) -> f64 {
+        self.step(input)
+    }
+    fn reset(&mut self) {
+        self.committed = ObvState::default();
+        self.state = ObvState::default();
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct VwapState {
+    session: u64,
+    price_volume: f64,
+    volume: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Vwap {
+    session_ms: u64,
+    committed: VwapState,
+    state: VwapState,
+}
+
+impl Vwap {
+    pub fn new() -> Vwap {
+        // Resets at UTC midnight
+        Vwap::with_session(86_400_000)
+    }
+    pub fn with_session(session_ms: u64) -> Vwap {
+        // Zero session never resets
+        Vwap {
+            session_ms,
+            committed: VwapState::default(),
+            state: VwapState::default(),
+        }
+    }
+    fn step(&mut self, input: Candle) -> Option<f64> {
+        let mut state = self.committed;
+        let session = input.timestamp().checked_div(self.session_ms).unwrap_or(0);
+        if session != state.session {
+            state = VwapState {
+                session,
+                ..VwapState::default()
+            };
+        }
+        let typical = (input.high() + input.low() + input.close()) / 3.0;
+        state.price_volume += typical * input.volume();
+        state.volume += input.volume();
+        self.state = state;
+        if state.volume > 0.0 {
+            Some(state.price_volume / state.volume)
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for Vwap {
+    fn default() -> Vwap {
+        Vwap::new()
+    }
+}
+
+impl Indicator for Vwap {
+    type Input = Candle;
+    type Output = Option<f64>;
+    fn next(&mut self, input: Candle) -> Option<f64> {
+        self.committed = self.state;
+        self.step(input)
+    }
+    fn replace(&mut self, input: Candle) -> Option<f64> {
+        self.step(input)
+    }
+    fn reset(&mut self) {
+        self.committed = VwapState::default();
+        self.state = VwapState::default();
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Donchian {
+    highest: Extremum,
+    lowest: Extremum,
+}
+
+impl Donchian {
+    pub fn new(period: usize) -> Donchian {
+        Donchian {
+            highest: Extremum::max(period),
+            lowest: Extremum::min(period),
+        }
+    }
+    fn value(&self, upper: f64, lower: f64) -> Option<Bands> {
+        if !self.highest.is_full() {
+            return None;
+        }
+        Some(Bands {
+            upper,
+            middle: (upper + lower) / 2.0,
+            lower,
+        })
+    }
+}
+
+impl Indicator for Donchian {
+    type Input = Candle;
+    type Output = Option<Bands>;
+    fn next(&mut self, input: Candle) -> Option<Bands> {
+        let upper = self.highest.next(input.high());
+        let lower = self.lowest.next(input.low());
+        self.value(upper, lower)
+    }
+    fn replace(&mut self, input: Candle) -> Option<Bands> {
+        let upper = self.highest.replace(input.high());
+        let lower = self.lowest.replace(input.low());
+        self.value(upper, lower)
+    }
+    fn reset(&mut self) {
+        self.highest.reset();
+        self.lowest.reset();
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Keltner {
+    ema: Ema,
+    atr: Atr,
+    multiplier: f64,
+}
+
+impl Keltner {
+    pub fn new(period: usize, atr_period: usize, multiplier: f64) -> Keltner {
+        Keltner {
+            ema: Ema::new(period),
+            atr: Atr::new(atr_period),
+            multiplier,
+        }
+    }
+    fn value(&self, middle: Option<f64>, atr: Option<f64>) -> Option<Bands> {
+        let (middle, atr) = (middle?, atr?);
+        Some(Bands {
+            upper: middle + self.multiplier * atr,
+            middle,
+            lower: middle - self.multiplier * atr,
+        })
+    }
+}
+
+impl Default for Keltner {
+    fn default() -> Keltner {
+        Keltner::new(20, 10, 2.0)
+    }
+}
+
+impl Indicator for Keltner {
+    type Input = Candle;
+    type Output = Option<Bands>;
+    fn next(&mut self, input: Candle) -> Option<Bands> {
+        let middle = self.ema.next(input.close());
+        let atr = self.atr.next(input);
+        self.value(middle, atr)
+    }
+    fn replace(&mut self, input: Candle) -> Option<Bands> {
+        let middle = self.ema.replace(input.close());
+        let atr = self.atr.replace(input);
+        self.value(middle, atr)
+    }
+    fn reset(&mut self) {
+        self.ema.reset();
+        self.atr.reset();
+    }
+}
+
+impl CandleLine {
+    // Batch forms over the whole line, outputs are aligned with candles
+    pub fn sma(&self, period: usize) -> Vec<Option<f64>> {
+        batch(&mut Sma::new(period), self.closes())
+    }
+    pub fn smma(&self, period: usize) -> Vec<Option<f64>> {
+        batch(&mut Smma::new(period), self.closes())
+    }
+    pub fn ema(&self, period: usize) -> Vec<Option<f64>> {
+        batch(&mut Ema::new(period), self.closes())
+    }
+    pub fn wma(&self, period: usize) -> Vec<Option<f64>> {
+        batch(&mut Wma::new(period), self.closes())
+    }
+    pub fn rsi(&self, period: usize) -> Vec<Option<f64>> {
+        batch(&mut Rsi::new(period), self.closes())
+    }
+    pub fn macd(&self, fast: usize, slow: usize, signal: usize) -> Vec<Option<MacdValue>> {
+        batch(&mut Macd::new(fast, slow, signal), self.closes())
+    }
+    pub fn bollinger(&self, period: usize, k: f64) -> Vec<Option<Bands>> {
+        batch(&mut Bollinger::new(period, k), self.closes())
+    }
+    pub fn atr(&self, period: usize) -> Vec<Option<f64>> {
+        batch(&mut Atr::new(period), self.all())
+    }
+    pub fn stochastic(&self, k_period: usize, d_period: usize) -> Vec<Option<StochasticValue>> {
+        batch(&mut Stochastic::new(k_period, d_period), self.all())
+    }
+    pub fn adx(&self, period: usize) -> Vec<Option<AdxValue>> {
+        batch(&mut Adx::new(period), self.all())
+    }
+    pub fn obv(&self) -> Vec<f64> {
+        batch(&mut Obv::new(), self.all())
+    }
+    pub fn vwap(&self) -> Vec<Option<f64>> {
+        batch(&mut Vwap::new(), self.all())
+    }
+    pub fn donchian(&self, period: usize) -> Vec<Option<Bands>> {
+        batch(&mut Donchian::new(period), self.all())
+    }
+    pub fn keltner(&self, period: usize, atr_period: usize, multiplier: f64) -> Vec<Option<Bands>> {
+        batch(
+            &mut Keltner::new(period, atr_period, multiplier),
+            self.all(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sample() -> CandleLine {
+        // Hourly candles, reference values of indicators without a published example
+        // were computed with straightforward non-incremental formulas over the same data
+        let data = [
+            (0, 50.0, 50.7, 49.7, 50.3, 100.0),
+            (3600000, 50.3, 51.77, 49.9, 51.17, 139.0),
+            (7200000, 51.17, 53.35, 50.67, 52.55, 178.0),
+            (10800000, 52.55, 54.14, 51.95, 53.74, 217.0),
+            (14400000, 53.74, 55.14, 53.04, 54.54, 256.0),
+            (18000000, 54.54, 55.34, 53.61, 53.91, 295.0),
+            (21600000, 53.91, 54.31, 52.1, 52.5, 323.0),
+            (25200000, 52.5, 53.1, 50.43, 50.93, 103.0),
+            (28800000, 50.93, 51.73, 49.68, 50.28, 142.0),
+            (32400000, 50.28, 50.68, 49.51, 50.21, 181.0),
+            (36000000, 50.21, 51.7, 49.91, 51.1, 220.0),
+            (39600000, 51.1, 53.28, 50.7, 52.48, 248.0),
+            (43200000, 52.48, 54.46, 51.98, 54.06, 287.0),
+            (46800000, 54.06, 55.04, 53.46, 54.44, 326.0),
+            (50400000, 54.44, 55.24, 53.09, 53.79, 106.0),
+            (54000000, 53.79, 54.19, 52.07, 52.37, 145.0),
+            (57600000, 52.37, 52.97, 50.8, 51.2, 184.0),
+            (61200000, 51.2, 52.0, 49.67, 50.17, 212.0),
+            (64800000, 50.17, 50.57, 49.52, 50.12, 251.0),
+            (68400000, 50.12, 51.62, 49.42, 51.02, 290.0),
+            (72000000, 51.02, 53.61, 50.72, 52.81, 329.0),
+            (75600000, 52.81, 54.38, 52.41, 53.98, 109.0),
+            (79200000, 53.98, 54.93, 53.48, 54.33, 137.0),
+            (82800000, 54.33, 55.13, 53.06, 53.66, 176.0),
+            (86400000, 53.66, 54.06, 51.93, 52.63, 215.0),
+            (90000000, 52.63, 53.23, 50.77, 51.07, 254.0),
+            (93600000, 51.07, 51.87, 49.66, 50.06, 293.0),
+            (97200000, 50.06, 50.46, 49.54, 50.04, 332.0),
+            (100800000, 50.04, 51.96, 49.44, 51.36, 101.0),
+            (104400000, 51.36, 53.55, 50.66, 52.75, 140.0),
+            (108000000, 52.75, 54.3, 52.45, 53.9, 179.0),
+            (111600000, 53.9, 54.83, 53.5, 54.23, 218.0),
+            (115200000, 54.23, 55.03, 53.43, 53.93, 257.0),
+            (118800000, 53.93, 54.33, 51.89, 52.49, 285.0),
+            (122400000, 52.49, 53.09, 50.23, 50.93, 324.0),
+            (126000000, 50.93, 51.73, 49.64, 49.94, 104.0),
+            (129600000, 49.94, 50.74, 49.54, 50.34, 143.0),
+            (133200000, 50.34, 51.88, 49.84, 51.28, 182.0),
+            (136800000, 51.28, 53.47, 50.68, 52.67, 221.0),
+            (140400000, 52.67, 54.21, 51.97, 53.81, 249.0),
+        ];
+        CandleLine::new_from_vec(
+            data.iter()
+                .map(|&(t, o, h, l, c, v)| Candle::new(t, o, h, l, c, v))
+                .collect(),
+        )
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    fn at(values: &[Option<f64>], expected: &[(usize, f64)]) {
+        for &(i, e) in expected {
+            let v = values[i].unwrap_or_else(|| panic!("No value at {}", i));
+            assert!(close(v, e), "{} != {} at {}", v, e, i);
+        }
+    }
+
+    fn published(values: &[Option<f64>], from: usize, expected: &[f64]) {
+        // Published tables round to cents, some of them intermediate values too
+        assert!(values[..from].iter().all(|v| v.is_none()));
+        assert_eq!(values.len(), from + expected.len());
+        for (v, e) in values[from..].iter().zip(expected.iter()) {
+            assert!((v.unwrap() - e).abs() < 0.015, "{:?} != {}", v, e);
+        }
+    }
+
+    fn candles(highs: &[f64], lows: &[f64], closes: &[f64]) -> Vec<Candle> {
+        (0..closes.len())
+            .map(|i| Candle::new(i as u64, closes[i], highs[i], lows[i], closes[i], 1.0))
+            .collect()
+    }
+
+    fn bands(values: &[Option<Bands>], expected: &[(usize, f64, f64, f64)]) {
+        for &(i, upper, middle, lower) in expected {
+            let b = values[i].unwrap();
+            assert!(close(b.upper, upper) && close(b.middle, middle) && close(b.lower, lower));
+        }
+    }
+
+    #[test]
+    fn moving_averages() {
+        // 10 day EMA example as published by StockCharts
+        let closes = [
+            22.27, 22.19, 22.08, 22.17, 22.18, 22.13, 22.23, 22.43, 22.24, 22.29, 22.15, 22.39,
+            22.38, 22.61, 23.36, 24.05, 23.75, 23.83, 23.95, 23.63, 23.82, 23.87, 23.65, 23.19,
+            23.10, 23.33, 22.68, 23.10, 22.40, 22.17,
+        ];
+        published(
+            &batch(&mut Ema::new(10), closes),
+            9,
+            &[
+                22.22, 22.21, 22.24, 22.27, 22.33, 22.52, 22.80, 22.97, 23.13, 23.28, 23.34, 23.43,
+                23.51, 23.54, 23.47, 23.40, 23.39, 23.26, 23.23, 23.08, 22.92,
+            ],
+        );
+        let line = sample();
+        let wma = line.wma(10);
+        assert!(wma[8].is_none());
+        at(
+            &wma,
+            &[
+                (9, 51.835818181818176),
+                (19, 51.640181818181816),
+                (29, 51.79),
+                (39, 52.05727272727273),
+            ],
+        );
+    }
+
+    #[test]
+    fn rsi() {
+        // Wilder's example as published by StockCharts, which rounds intermediate values
+        let closes = [
+            44.34, 44.09, 44.15, 43.61, 44.33, 44.83, 45.10, 45.42, 45.84, 46.08, 45.89, 46.03,
+            45.61, 46.28, 46.28, 46.00, 46.03, 46.41, 46.22, 45.64,
+        ];
+        let rsi = batch(&mut Rsi::new(14), closes);
+        assert!(rsi[13].is_none());
+        let published = [70.53, 66.32, 66.55, 69.41, 66.36, 57.97];
+        for (v, p) in rsi[14..].iter().zip(published.iter()) {
+            assert!((v.unwrap() - p).abs() < 0.1);
+        }
+        at(
+            &sample().rsi(14),
+            &[
+                (14, 62.9739776951673),
+                (19, 51.45790070138303),
+                (29, 55.250319502235115),
+                (39, 57.6337974889519),
+            ],
+        );
+        assert_eq!(
+            batch(&mut Rsi::new(2), [1.0, 1.0, 1.0]),
+            vec![None, None, Some(50.0)]
+        );
+    }
+
+    #[test]
+    fn macd() {
+        // EMA seeded with the average trails a straight line by (period - 1) / 2 bars
+        // from the start, rising 0.5 a bar the lines are 0.5 * (12.5 - 5.5) apart and
+        // the signal is flat
+        let macd = batch(
+            &mut Macd::default(),
+            (0..40).map(|i| 100.0 + 0.5 * i as f64),
+        );
+        assert!(macd[24].is_none());
+        assert!(macd[25..33].iter().all(|v| v.unwrap().signal.is_none()));
+        for v in macd[25..].iter() {
+            assert!((v.unwrap().macd - 3.5).abs() < 1e-9);
+        }
+        for v in macd[33..].iter() {
+            assert!((v.unwrap().signal.unwrap() - 3.5).abs() < 1e-9);
+            assert!(v.unwrap().histogram.unwrap().abs() < 1e-9);
+        }
+        // Line is the difference of the two averages, no published values are used here,
+        // the averages themselves are checked in moving_averages
+        let closes = sample().closes();
+        let fast = batch(&mut Ema::new(12), closes.clone());
+        let slow = batch(&mut Ema::new(26), closes.clone());
+        for (i, v) in batch(&mut Macd::default(), closes).iter().enumerate() {
+            assert_eq!(v.map(|v| v.macd), slow[i].map(|s| fast[i].unwrap() - s));
+        }
+    }
+
+    #[test]
+    fn volatility() {
+        // Bollinger and ATR examples as published by StockCharts
+        let closes = [
+            86.16, 89.09, 88.78, 90.32, 89.07, 91.15, 89.44, 89.18, 86.93, 87.68, 86.96, 89.43,
+            89.32, 88.72, 87.45, 87.26, 89.50, 87.90, 89.13, 90.70, 92.90, 92.98, 91.80, 92.66,
+            92.68, 92.30, 92.77, 92.54, 92.95, 93.20, 91.07, 89.83, 89.74, 90.40, 90.74, 88.02,
+            88.09, 88.84, 90.78, 90.54, 91.39, 90.65,
+        ];
+        let bollinger = batch(&mut Bollinger::default(), closes);
+        let column = |f: fn(&Bands) -> f64| -> Vec<Option<f64>> {
+            bollinger.iter().map(|b| b.as_ref().map(f)).collect()
+        };
+        published(
+            &column(|b| b.middle),
+            19,
+            &[
+                88.71, 89.05, 89.24, 89.39, 89.51, 89.69, 89.75, 89.91, 90.08, 90.38, 90.66, 90.86,
+                90.88, 90.90, 90.99, 91.15, 91.19, 91.12, 91.17, 91.25, 91.24, 91.17, 91.05,
+            ],
+        );
+        published(
+            &column(|b| b.upper),
+            19,
+            &[
+                91.29, 91.95, 92.61, 92.93, 93.31, 93.73, 93.90, 94.26, 94.56, 94.79, 95.04, 94.91,
+                94.90, 94.89, 94.86, 94.67, 94.55, 94.68, 94.57, 94.53, 94.53, 94.37, 94.15,
+            ],
+        );
+        published(
+            &column(|b| b.lower),
+            19,
+            &[
+                86.12, 86.14, 85.87, 85.85, 85.70, 85.65, 85.59, 85.56, 85.60, 85.98, 86.27, 86.82,
+                86.86, 86.91, 87.12, 87.63, 87.83, 87.56, 87.76, 87.97, 87.95, 87.96, 87.95,
+            ],
+        );
+        // Same spread far from zero keeps its deviation
+        let shifted = batch(&mut Bollinger::default(), closes.iter().map(|c| c + 1e9));
+        for (b, s) in bollinger.iter().zip(shifted.iter()) {
+            if let (Some(b), Some(s)) = (b, s) {
+                let width = |b: &Bands| b.upper - b.lower;
+                assert!((width(b) - width(s)).abs() < 1e-4);
+            }
+        }
+        let highs = [
+            48.70, 48.72, 48.90, 48.87, 48.82, 49.05, 49.20, 49.35, 49.92, 50.19, 50.12, 49.66,
+            49.88, 50.19, 50.36, 50.57, 50.65, 50.43, 49.63, 50.33, 50.29, 50.17, 49.32, 48.50,
+            48.32, 46.80, 47.80, 48.39, 48.66, 48.79,
+        ];
+        let lows = [
+            47.79, 48.14, 48.39, 48.37, 48.24, 48.64, 48.94, 48.86, 49.50, 49.87, 49.20, 48.90,
+            49.43, 49.73, 49.26, 50.09, 50.30, 49.21, 48.98, 49.61, 49.20, 49.43, 48.08, 47.64,
+            41.55, 44.28, 47.31, 47.20, 47.90, 47.73,
+        ];
+        let closes = [
+            48.16, 48.61, 48.75, 48.63, 48.74, 49.03, 49.07, 49.32, 49.91, 50.13, 49.53, 49.50,
+            49.75, 50.03, 50.31, 50.52, 50.41, 49.34, 49.37, 50.23, 49.24, 49.93, 48.43, 48.18,
+            46.57, 45.41, 47.77, 47.72, 48.62, 47.85,
+        ];
+        published(
+            &batch(&mut Atr::new(14), candles(&highs, &lows, &closes)),
+            13,
+            &[
+                0.55, 0.59, 0.59, 0.57, 0.61, 0.62, 0.64, 0.67, 0.69, 0.77, 0.78, 1.21, 1.30, 1.38,
+                1.37, 1.34, 1.32,
+            ],
+        );
+        let line = sample();
+        bands(
+            &line.donchian(20),
+            &[(19, 55.34, 52.38, 49.42), (39, 55.13, 52.285, 49.44)],
+        );
+        bands(
+            &line.keltner(20, 10, 2.0),
+            &[
+                (19, 56.03663231717542, 52.04400000000002, 48.05136768282462),
+                (
+                    39,
+                    56.371245364552365,
+                    52.161363327011436,
+                    47.95148128947051,
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn oscillators() {
+        let line = sample();
+        let stochastic = line.stochastic(14, 3);
+        assert!(stochastic[12].is_none());
+        let expected = [
+            (13, 84.56260720411656, None),
+            (15, 49.056603773584854, Some(69.01086335048593)),
+            (29, 58.31873905429068, Some(34.315713875899434)),
+            (39, 78.17531305903402, Some(56.29099582587958)),
+        ];
+        for &(i, k, d) in expected.iter() {
+            let v = stochastic[i].unwrap();
+            assert!(close(v.k, k));
+            assert_eq!(v.d.is_some(), d.is_some());
+            assert!(d.is_none_or(|d| close(v.d.unwrap(), d)));
+        }
+
+        // Steady climb, every bar moves up by 1 over a true range of 1.5. All movement
+        // is directional so ADX is 100 once it has its period of DX values
+        let highs: Vec<f64> = (0..40).map(|i| i as f64 + 1.0).collect();
+        let lows: Vec<f64> = (0..40).map(|i| i as f64).collect();
+        let closes: Vec<f64> = (0..40).map(|i| i as f64 + 0.5).collect();
+        let adx = batch(&mut Adx::new(14), candles(&highs, &lows, &closes));
+        assert!(adx[13].is_none());
+        assert!(adx[14..27].iter().all(|v| v.unwrap().adx.is_none()));
+        for v in adx[14..].iter().map(|v| v.unwrap()) {
+            assert!((v.plus_di - 200.0 / 3.0).abs() < 1e-9);
+            assert_eq!(v.minus_di, 0.0);
+        }
+        for v in adx[27..].iter() {
+            assert!((v.unwrap().adx.unwrap() - 100.0).abs() < 1e-9);
+        }
+        // Same fall mirrors the lines
+        let falling = batch(
+            &mut Adx::new(14),
+            candles(
+                &lows.iter().map(|l| -l).collect::<Vec<f64>>(),
+                &highs.iter().map(|h| -h).collect::<Vec<f64>>(),
+                &closes.iter().map(|c| -c).collect::<Vec<f64>>(),
+            ),
+        );
+        for (up, down) in adx.iter().zip(falling.iter()) {
+            assert_eq!(
+                up.map(|v| (v.plus_di, v.adx)),
+                down.map(|v| (v.minus_di, v.adx))
+            );
+        }
+    }
+
+    #[test]
+    fn volume() {
+        let line = sample();
+        let obv = line.obv();
+        assert_eq!(obv[0], 0.0);
+        assert_eq!((obv[19], obv[29], obv[39]), (219.0, -235.0, -13.0));
+        // Session restarts at midnight, which is candle 24
+        at(
+            &line.vwap(),
+            &[
+                (0, 50.23333333333333),
+                (23, 52.404923294307636),
+                (24, 52.873333333333335),
+                (39, 52.03288818987705),
+            ],
+        );
+    }
+
+    #[test]
+    fn ticks_match_closed_bars() {
+        // Replacing the last bar with ticks ends in the same state as closed bars only
+        fn check<I, O>(mut closed: I, mut live: I, inputs: Vec<(I::Input, I::Input)>)
+        where
+            I: Indicator<Output = O>,
+            I::Input: Copy,
+            O: std::fmt::Debug,
+        {
+            for (tick, bar) in inputs {
+                live.next(tick);
+                live.replace(tick);
+                // Rolling sums are updated differently, so tiny rounding differences are fine
+                assert_eq!(
+                    format!("{:.6?}", live.replace(bar)),
+                    format!("{:.6?}", closed.next(bar))
+                );
+            }
+        }
+        let line = sample();
+        let candles = line.all();
+        let pairs: Vec<(Candle, Candle)> = candles
+            .iter()
+            .map(|c| {
+                let tick = Candle::new(
+                    c.timestamp(),
+                    c.open(),
+                    c.open() + 3.0,
+                    c.open() - 3.0,
+                    c.low(),
+                    1.0,
+                );
+                (tick, *c)
+            })
+            .collect();
+        let values: Vec<(f64, f64)> = pairs.iter().map(|(t, c)| (t.close(), c.close())).collect();
+
+        check(Ema::new(5), Ema::new(5), values.clone());
+        check(Wma::new(5), Wma::new(5), values.clone());
+        check(Smma::new(5), Smma::new(5), values.clone());
+        check(Rsi::new(5), Rsi::new(5), values.clone());
+        check(Macd::new(3, 6, 4), Macd::new(3, 6, 4), values.clone());
+        check(
+            Bollinger::new(5, 2.0),
+            Bollinger::new(5, 2.0),
+            values.clone(),
+        );
+        check(Extremum::max(5), Extremum::max(5), values.clone());
+        check(Extremum::min(5), Extremum::min(5), values);
+        check(Atr::new(5), Atr::new(5), pairs.clone());
+        check(Stochastic::new(5, 3), Stochastic::new(5, 3), pairs.clone());
+        check(Adx::new(5), Adx::new(5), pairs.clone());
+        check(Obv::new(), Obv::new(), pairs.clone());
+        check(Vwap::new(), Vwap::new(), pairs.clone());
+        check(Donchian::new(5), Donchian::new(5), pairs.clone());
+        check(Keltner::new(5, 5, 1.5), Keltner::new(5, 5, 1.5), pairs);
+    }
+
+    #[test]
+    fn window() {
+        let mut window = Window::new(3);
+        assert_eq!(window.replace_last(1.0), None);
+        for v in [1.0, 2.0, 3.0] {
+            assert_eq!(window.push(v), None);
+        }
+        assert_eq!(window.push(4.0), Some(1.0));
+        assert_eq!(window.sum(), 9.0);
+        assert_eq!(window.replace_last(10.0), Some(4.0));
+        assert_eq!(window.mean(), Some(5.0));
+    }
+
+    #[test]
+    fn sma_replace() {
+        let mut sma = Sma::new(3);
+        assert_eq!(batch(&mut sma, vec![1.0, 2.0]), vec![None, None]);
+        assert_eq!(sma.next(6.0), Some(3.0));
+        // Tick of an unclosed bar does not move the window
+        assert_eq!(sma.replace(9.0), Some(4.0));
+        assert_eq!(sma.replace(3.0), Some(2.0));
+        assert_eq!(sma.next(4.0), Some(3.0));
+    }
+
+    #[test]
+    fn stream_replaces_same_bar() {
+        let candles: Vec<Candle> = (0..6)
+            .map(|i| {
+                let p = 10.0 + (i as f64 * 1.7).sin();
+                Candle::new(i * 60_000, p, p + 1.0, p - 1.5, p + 0.5, 1.0)
+            })
+            .collect();
+        let closed = batch(&mut HeikinAshi::new(), candles.clone());
+
+        let mut stream = Stream::new(HeikinAshi::new());
+        let mut live = Vec::new();
+        for candle in candles.iter() {
+            // Some ticks with different close before the final one
+            stream.update(candle.timestamp(), Candle::zeros());
+            live.push(stream.update(candle.timestamp(), *candle));
+        }
+        assert_eq!(stream.bars(), 6);
+        for (a, b) in closed.iter().zip(live.iter()) {
+            assert_eq!(a.open(), b.open());
+            assert_eq!(a.close(), b.close());
+        }
+    }
+}
@@ -1,2 +1,25 @@
+pub mod backtest;
+pub mod binance;
+pub mod builder;
+pub mod cache;
+pub mod candle_io;
+pub mod charts;
+pub mod clock;
+pub mod download;
+pub mod error;
+pub mod exchange;
+pub mod exchange_info;
+pub mod executor;
+pub mod fill;
+pub mod indicators;
+pub mod position;
+pub mod series;
+pub mod sizing;
 pub mod strategy;
+pub mod timeframe;
+pub mod trade;
 pub mod types;
+pub mod validation;
+
+#[cfg(test)]
+mod testing;
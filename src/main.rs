@@ -1,29 +1,55 @@
 //#![allow(dead_code, unused_doc_comments)]
-use reqwest::{self, Client};
-//use serde::{Deserialize, Serialize};
-use serde_json::{self, json, Value};
 //use std::time::{Duration, Instant};
-use tungstenite::{connect, Message};
 
-use tradeterm::strategy;
-use tradeterm::types::{Broker, Candle, Config, Event, Journal, Market, Order, Signal, Stats};
-use tradeterm::types::{OrderRespType, OrderSide, OrderType, TimeInForce};
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tradeterm::backtest::{BacktestResult, Backtester};
+use tradeterm::cache::CandleCache;
+use tradeterm::download::{Downloader, RateLimiter, MAX_PAGE_SIZE};
+use tradeterm::error::{Error, Result};
+use tradeterm::exchange::{Exchange, KlineUpdate};
+use tradeterm::exchange_info::SymbolInfo;
+use tradeterm::executor::Executor;
+use tradeterm::strategy::StrategyRegistry;
+use tradeterm::timeframe::{base_interval, parse_interval, Resampler};
+use tradeterm::types::OrderRespType;
+use tradeterm::types::{Broker, CandleLine, Config, Market, Order, Stats};
+
+// Backtests without a given range cover the last week
+const WEEK_MS: u64 = 7 * 86_400_000;
+
+fn range(end_ms: u64) -> Result<(u64, u64)> {
+    // Optional start and end in ms follow the mode
+    let args: Vec<String> = std::env::args().skip(2).collect();
+    let ms = |arg: &String| {
+        arg.parse::<u64>()
+            .map_err(|_| Error::Validation(format!("{} is not a time in ms", arg)))
+    };
+    match args.as_slice() {
+        [] => Ok((end_ms.saturating_sub(WEEK_MS), end_ms)),
+        [start, end] => Ok((ms(start)?, ms(end)?)),
+        _ => Err(Error::Validation("Expected START_MS END_MS".to_string())),
+    }
+}
 
 #[tokio::main]
-async fn main() -> Result<(), reqwest::Error> {
+async fn main() -> Result<()> {
     // get information abour exchange
 
     let binance = Broker::new(
         "BINANCE".to_string(),
         "api_key".to_string(),
+        "secret_key".to_string(),
         "https://api.binance.com/api/v3/".to_string(),
         "wss://stream.binance.com:9443/ws".to_string(),
     );
 
-    let binance_testnet = Broker::new(
+    let _binance_testnet = Broker::new(
         "BINANCE".to_string(),
         "api_key".to_string(),
-        "https://testnet.binance.vision/api".to_string(),
+        "secret_key".to_string(),
+        "https://testnet.binance.vision/api/v3/".to_string(),
         "wss://testnet.binance.vision/ws".to_string(),
     );
 
@@ -36,255 +62,313 @@ async fn main() -> Result<(), reqwest::Error> {
         "ExS".to_string(),
         binance,
     );
-    // let info = get_sym_info(&config).await.unwrap();
-    // println!(
-    //     "{:#?}",
-    //     info.get("symbols")
-    //         .unwrap()
-    //         .get(0)
-    //         .unwrap()
-    //         .get("filters")
-    //         .unwrap()
-    //         .get(5)
-    //         .unwrap()
-    //         .get("stepSize")
-    //         .unwrap()
-    // );
-    //let step = info.get("symbols").unwrap(); //.get("filters").unwrap().get(4);
-    //let step = info.get("symbols").unwrap().get("filters").unwrap().get(5).unwrap();
+    // Own strategies can be added with registry.register before starting a session
+    let registry = StrategyRegistry::default();
+    // Fail early on unknown strategy name or bad sizing
+    registry.create(&config.get_strategy())?;
+    config.get_sizing().validate()?;
+    // First argument picks the mode: backtest, offline for a backtest of cached candles
+    // only, or live. Without one the startup order is only tested
+    let mode = std::env::args().nth(1).unwrap_or_default();
+    if mode == "offline" {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64);
+        let (start, end) = range(now)?;
+        let market = Market::new(0.0, 10000.0, 1.0, 0.0, 0.0, 0.001);
+        backtest(&config, &registry, None, market, start, end).await?;
+        return Ok(());
+    }
+    let exchange = config.get_broker().exchange()?;
+    let info = exchange.get_symbol_info(&config.get_ticker()).await?;
+    println!("{:#?}", info.lot_size());
 
-    let time = get_server_time(&config).await;
-    let test_order = Order::new(
-        "BTCUSDT".to_string(),
-        OrderSide::BUY,
-        OrderType::MARKET,
-        None,
-        Some(0.0001),
-        None,
-        None,
-        None,
-        None,
-        None,
-        Some(OrderRespType::FULL),
-        None,
-        time.unwrap(),
-    );
-    let resp = place_order(&config, test_order).await;
+    // Checked and signed by the exchange, but never executed
+    let test_order = Order::market_buy("BTCUSDT")
+        .quantity(0.0001)
+        .resp_type(OrderRespType::FULL)
+        .build(&exchange.synced_clock().await?)?;
+    let resp = exchange
+        .test_order(&test_order.normalize(&info, None)?)
+        .await;
     println!("{:?}", resp);
     //println!("{:?}", time);
-    //let mut market = Market::new(0.0, 10000.0, 1.0, 0.0001, 0.0001, 0.001);
-    //backtrade(&config, &mut market).await;
-    //trade_live(&config).await;
+    let mut market = Market::from_symbol_info(&info, 0.0, 10000.0, 1.0, 0.001);
+    match mode.as_str() {
+        "backtest" => {
+            let (start, end) = range(exchange.get_server_time().await?)?;
+            backtest(
+                &config,
+                &registry,
+                Some(exchange.as_ref()),
+                market,
+                start,
+                end,
+            )
+            .await?;
+        }
+        "live" => trade_live(&config, &registry, exchange.as_ref(), &mut market).await?,
+        "" => (),
+        _ => {
+            return Err(Error::Validation(format!(
+                "Unknown mode {}, expected backtest, offline or live",
+                mode
+            )))
+        }
+    }
     Ok(())
 }
 
-async fn place_order(cfg: &Config, order: Order) -> Result<Value, reqwest::Error> {
-    let client = Client::new();
-    let payload = json!({
-            "symbol":cfg.get_ticker().to_uppercase()});
-    let res = client
-        .get(cfg.get_api_url() + "exchangeInfo")
-        .query(&payload)
-        .send()
-        .await?
-        .text()
-        .await?;
-    let data: Value = serde_json::from_str(&res).unwrap();
-    Ok(data)
-}
-
-async fn get_server_time(cfg: &Config) -> Result<u64, reqwest::Error> {
-    let client = Client::new();
-    //let payload = json!({
-    //"symbol":cfg.get_ticker().to_uppercase()});
-    let res = client
-        .get(cfg.get_api_url() + "time")
-        //.query(&payload)
-        .send()
-        .await?
-        .text()
-        .await?;
-    println!("{:?}", &res);
-    // let data: Value = serde_json::from_str(&res);
-    // println!("{:?}", data.as_str().unwrap().parse::<u64>().unwrap());
-    Ok(1)
-}
-
-async fn get_sym_info(cfg: &Config) -> Result<Value, reqwest::Error> {
-    let client = Client::new();
-    let payload = json!({
-            "symbol":cfg.get_ticker().to_uppercase()});
-    let res = client
-        .get(cfg.get_api_url() + "exchangeInfo")
-        .query(&payload)
-        .send()
-        .await?
-        .text()
-        .await?;
-    let data: Value = serde_json::from_str(&res).unwrap();
-    Ok(data)
-}
+async fn backtest(
+    cfg: &Config,
+    registry: &StrategyRegistry,
+    exchange: Option<&dyn Exchange>,
+    market: Market,
+    start_ms: u64,
+    end_ms: u64,
+) -> Result<BacktestResult> {
+    // Without an exchange the range has to be cached already and orders are not checked
+    // against symbol filters. Non-standard timeframes are built from the longest exchange
+    // interval fitting into them
+    let base = base_interval(&cfg.get_timeframe())?;
+    let cache = CandleCache::new("cache");
+    let ticker = cfg.get_ticker();
+    let candles = match exchange {
+        Some(exchange) => {
+            // Downloads keep to the request weight limits the exchange publishes
+            let limits = exchange.get_exchange_info().await?.rate_limits;
+            let mut downloader =
+                Downloader::new(exchange).with_limiter(RateLimiter::from_rate_limits(&limits));
+            cache
+                .fetch(&mut downloader, &ticker, base, start_ms, end_ms)
+                .await?
+        }
+        None => cache.load_offline(&ticker, base, start_ms, end_ms)?,
+    }
+    .resample(&cfg.get_timeframe())?;
+    let mut backtester = Backtester::new(registry.create(&cfg.get_strategy())?, market)
+        .with_sizing(cfg.get_sizing())
+        .with_fill_model(cfg.get_fill_model());
+    if let Some(exchange) = exchange {
+        backtester = backtester.with_symbol_info(exchange.get_symbol_info(&ticker).await?);
+    }
+    let result = backtester.run(&candles)?;
 
-async fn get_ex_info(cfg: &Config) -> Result<Value, reqwest::Error> {
-    let client = Client::new();
-    let res = client
-        .get(cfg.get_api_url() + "exchangeInfo")
-        //.query(&payload)
-        .send()
-        .await?
-        .text()
-        .await?;
-    let data: Value = serde_json::from_str(&res).unwrap();
-    Ok(data)
+    let mut stats = Stats::init();
+    stats.calculate(result.get_journal().clone());
+    println!("{:#?}", stats);
+    println!(
+        "Return: {:.3}%\nFirst C: {:#?}\nLast C: {:#?}",
+        result.get_return() * 100.0,
+        candles.all().first(),
+        candles.all().last()
+    );
+    Ok(result)
 }
-// Parse from Value object to matrix of floats
-async fn get_candles(cfg: &Config) -> Result<Vec<Candle>, reqwest::Error> {
-    let client = Client::new();
-    let payload = json!({
-        "symbol":cfg.get_ticker().to_uppercase(),"interval":cfg.get_timeframe(),"limit":50});
-    let res = client
-        .get(cfg.get_api_url() + "klines")
-        .query(&payload)
-        .send()
-        .await?
-        .text()
-        .await?;
 
-    // Parse from Value object to matrix of floats
-    let data: Vec<Vec<Value>> = serde_json::from_str(&res).unwrap();
-    let candle_vec = data
-        .iter()
-        .map(|row| {
-            Candle::new(
-                row[0].as_u64().unwrap_or(0),
-                row[1].as_str().unwrap().parse::<f64>().unwrap_or(0.0),
-                row[2].as_str().unwrap().parse::<f64>().unwrap_or(0.0),
-                row[3].as_str().unwrap().parse::<f64>().unwrap_or(0.0),
-                row[4].as_str().unwrap().parse::<f64>().unwrap_or(0.0),
-                row[5].as_str().unwrap().parse::<f64>().unwrap_or(0.0),
-            )
-        })
-        .collect::<Vec<Candle>>();
-    Ok(candle_vec)
+async fn send(
+    exchange: &dyn Exchange,
+    executor: &mut Executor,
+    order: &Order,
+    timestamp: u64,
+    order_ids: &mut HashMap<String, u64>,
+) {
+    // Rejected order is forgotten, so the signal may try again with fresh balances
+    let id = order.get_new_client_order_id().unwrap_or_default();
+    match exchange.place_order(order).await {
+        Ok(response) => {
+            order_ids.insert(id, response.get_order_id());
+            executor.report(&response, timestamp);
+        }
+        Err(e) => {
+            println!("Order {} failed: {}", id, e);
+            executor.forget(&id);
+        }
+    }
 }
 
-fn process(candles: &Vec<Candle>, strategy_name: String) -> Signal {
-    let signal = match strategy_name.to_lowercase().as_str() {
-        "exs" => strategy::exs(candles),
-        _ => Signal::Sleep,
+async fn cancel(
+    exchange: &dyn Exchange,
+    executor: &mut Executor,
+    symbol: &str,
+    id: &str,
+    order_id: u64,
+) {
+    // Order may have filled since it was last checked, exchange then does not know it
+    // anymore and its final state is queried instead
+    let info = match exchange.cancel_order(symbol, order_id).await {
+        Err(Error::Api(e)) => {
+            println!("Cancel of {} failed: {}", id, Error::Api(e));
+            exchange.query_order(symbol, order_id).await
+        }
+        result => result,
     };
-    //println!("Last candle:\n{:#?} \nSignal: \t{:#?}", &candles.last(),&signal);
-    signal
+    match info {
+        Ok(info) => executor.report_info(&info),
+        Err(e) => println!("State of {} is unknown: {}", id, e),
+    }
+    executor.forget(id);
 }
 
-async fn backtrade(cfg: &Config, market: &mut Market) {
-    let candles = get_candles(&cfg).await.unwrap();
-    let mut signals: Vec<Signal> = vec![];
-
-    let mut journal = Journal::new();
-
-    for index in 0..candles.len() {
-        market.update_ratio(candles.get(index).unwrap().close());
-        if &cfg.get_window() > &candles.len() {
-            signals.push(process(&candles.to_vec(), cfg.get_strategy()));
-        } else {
-            if index + &cfg.get_window() <= candles.len() {
-                signals.push(process(
-                    &candles[index..&cfg.get_window() + index].to_vec(),
-                    cfg.get_strategy(),
-                ));
-            }
+async fn check(
+    exchange: &dyn Exchange,
+    executor: &mut Executor,
+    symbol: &str,
+    id: &str,
+    order_id: u64,
+) -> bool {
+    // Returns false when the exchange does not know the order, it is forgotten then.
+    // Other failures leave it for the next check
+    match exchange.query_order(symbol, order_id).await {
+        Ok(info) => {
+            executor.report_info(&info);
+            true
         }
-        match signals.last().unwrap() {
-            Signal::Long => market.buy(market.b_in_a()),
-            Signal::Short => market.sell(market.a_in_b()),
-            Signal::Sleep => (),
+        Err(Error::Api(e)) => {
+            println!("Query of {} failed: {}", id, Error::Api(e));
+            executor.forget(id);
+            false
+        }
+        Err(e) => {
+            println!("Query of {} failed: {}", id, e);
+            true
         }
-
-        let e = Event::new(
-            candles[index].timestamp() as usize,
-            signals.last().unwrap().clone(),
-            market.clone(),
-            candles[index],
-        );
-        journal.put(e);
     }
-
-    let mut stats = Stats::init();
-    stats.calculate(journal);
-    println!("{:#?}", stats);
-    println!(
-        "Final result{:#?}\nFirst C: {:#?}\nLast C: {:#?}",
-        &market.a_in_b(),
-        &candles.first(),
-        &candles.last()
-    );
 }
 
-fn socket_sub_payload(cfg: &Config) -> String {
-    let payload = json!({"method":"SUBSCRIBE",
-    "params":[format!("{}@kline_{}",cfg.get_ticker().to_lowercase(),cfg.get_timeframe())],
-    "id":1});
-    serde_json::to_string(&payload).unwrap()
+async fn refresh_balances(
+    exchange: &dyn Exchange,
+    info: &SymbolInfo,
+    market: &mut Market,
+) -> Result<()> {
+    let balances = exchange.get_balances().await?;
+    let free = |asset: &str| {
+        balances
+            .iter()
+            .find(|b| b.asset == asset)
+            .map_or(0.0, |b| b.free)
+    };
+    market.set_amounts(free(&info.base_asset), free(&info.quote_asset));
+    Ok(())
 }
 
-async fn trade_live(cfg: &Config, market: &Market) {
-    let mut candles = get_candles(&cfg).await.unwrap();
-
-    let (mut socket, _response) = connect(cfg.get_socket_url()).expect("Cannot connect");
+async fn trade_live(
+    cfg: &Config,
+    registry: &StrategyRegistry,
+    exchange: &dyn Exchange,
+    market: &mut Market,
+) -> Result<()> {
+    let mut strategy = registry.create(&cfg.get_strategy())?;
+    let window = cfg.get_window().max(strategy.window());
+    let base = base_interval(&cfg.get_timeframe())?;
+    let ratio = (parse_interval(&cfg.get_timeframe())? / parse_interval(base)?) as usize;
+    // Enough history to warm up the strategy, plus the bar which is forming now
+    let history = exchange
+        .get_candles(
+            &cfg.get_ticker(),
+            base,
+            ((window + 1) * ratio).min(MAX_PAGE_SIZE),
+        )
+        .await?;
+    let mut resampler = Resampler::new(&cfg.get_timeframe(), base)?;
+    // Last candle of history is still open, resampler only needs the closed ones
+    for candle in history.iter().take(history.len().saturating_sub(1)) {
+        resampler.update(KlineUpdate {
+            candle: *candle,
+            closed: true,
+        });
+    }
+    for candle in CandleLine::new_from_vec(history)
+        .resample(&cfg.get_timeframe())?
+        .all()
+    {
+        strategy.on_candle(&candle);
+    }
 
-    let payload = socket_sub_payload(&cfg);
+    let info = exchange.get_symbol_info(&cfg.get_ticker()).await?;
+    let mut executor = Executor::new(info.clone()).with_sizing(cfg.get_sizing());
+    // Exchange ids of sent orders by client id, needed to query and cancel them
+    let mut order_ids: HashMap<String, u64> = HashMap::new();
+    refresh_balances(exchange, &info, market).await?;
 
-    socket.write_message(Message::Text(payload.into())).unwrap();
+    let mut stream = exchange.subscribe_klines(&cfg.get_ticker(), base)?;
 
     loop {
-        match socket.read_message().expect("Error reading message") {
-            Message::Text(t) => {
-                let msg: serde_json::Value = serde_json::from_str(&t).unwrap();
-                // debug message
-                // println!("{:?}",&msg);
-
-                let candle = &msg["k"];
-
-                // Only if Message is correct (no errors)
-                if msg.get("e") != None {
-                    // Measure time of processing
-                    // let t_new = Instant::now();
+        let update = match stream.next_kline() {
+            Ok(update) => update,
+            // Connection is gone, nothing more will arrive
+            Err(Error::WebSocket(e)) => return Err(Error::WebSocket(e)),
+            // Single bad message should not stop the bot
+            Err(e) => {
+                println!("Skipping message: {}", e);
+                continue;
+            }
+        };
+        // Measure time of processing
+        // let t_new = Instant::now();
 
-                    // Creating new candle from data acquired
-                    let new_candle = Candle::new(
-                        candle["t"].as_u64().unwrap(),
-                        candle["o"].as_str().unwrap().parse::<f64>().unwrap(),
-                        candle["h"].as_str().unwrap().parse::<f64>().unwrap(),
-                        candle["l"].as_str().unwrap().parse::<f64>().unwrap(),
-                        candle["c"].as_str().unwrap().parse::<f64>().unwrap(),
-                        candle["v"].as_str().unwrap().parse::<f64>().unwrap(),
-                    );
-                    if candle.get("x") == Some(&Value::Bool(true)) {
-                        // On full candle premanently add to candles vec
-                        candles.push(new_candle);
-                    } else {
-                        // Replace latest tick with new one
-                        candles.pop();
-                        candles.push(new_candle);
+        // Tick of the forming bar has the same open time and replaces the previous one
+        let update = resampler.update(update);
+        let timestamp = update.candle.timestamp();
+        market.update_ratio(update.candle.close());
+        // Failures of single requests skip the tick or the order, only a lost stream
+        // stops the bot
+        let clock = match exchange.synced_clock().await {
+            Ok(clock) => clock,
+            Err(e) => {
+                println!("Skipping tick, clock sync failed: {}", e);
+                continue;
+            }
+        };
+        executor.on_candle(&update.candle);
+        match executor.check_exit(&update.candle, market, &clock) {
+            Ok(Some((price, order))) => {
+                println!("Exit at {}", price);
+                send(exchange, &mut executor, &order, timestamp, &mut order_ids).await;
+                if let Err(e) = refresh_balances(exchange, &info, market).await {
+                    println!("Balances not refreshed: {}", e);
+                }
+            }
+            Ok(None) => (),
+            Err(e) => println!("Exit not placed: {}", e),
+        }
+        let signal = strategy.on_candle(&update.candle);
+        if !executor.is_repeated(&signal) {
+            // Resting orders of the previous signal are not wanted anymore
+            for id in executor.pending() {
+                match order_ids.remove(&id) {
+                    Some(order_id) => {
+                        cancel(exchange, &mut executor, &info.symbol, &id, order_id).await
                     }
-                    // Run processing function on range of candles
-                    let signal: Signal;
-                    if &cfg.get_window() > &candles.len() {
-                        signal = process(&candles.to_vec(), cfg.get_strategy());
-                    } else {
-                        signal = process(
-                            &candles[&candles.len() - cfg.get_window()..].to_vec(),
-                            cfg.get_strategy(),
-                        );
+                    None => executor.forget(&id),
+                }
+            }
+        } else if update.closed {
+            // Resting orders are checked once per closed candle
+            for id in executor.pending() {
+                if let Some(&order_id) = order_ids.get(&id) {
+                    if !check(exchange, &mut executor, &info.symbol, &id, order_id).await {
+                        order_ids.remove(&id);
                     }
-                    //println!("{:?}",&signal);
-                    //println!("Message processing took: {} microseconds",t_new.elapsed().as_micros());
-                } else {
-                    println!("{:?}", msg.get("e"));
                 }
             }
-            _ => (),
+        }
+        let orders = match executor.on_signal(&signal, market, update.candle.close(), &clock) {
+            Ok(orders) => orders,
+            Err(e) => {
+                println!("Signal {:?} not placed: {}", signal, e);
+                vec![]
+            }
         };
+        for order in orders.iter() {
+            println!("{:?} -> {:?}", signal, order);
+            send(exchange, &mut executor, order, timestamp, &mut order_ids).await;
+        }
+        if !orders.is_empty() {
+            if let Err(e) = refresh_balances(exchange, &info, market).await {
+                println!("Balances not refreshed: {}", e);
+            }
+        }
+        //println!("Message processing took: {} microseconds",t_new.elapsed().as_micros());
     }
 }
@@ -0,0 +1,215 @@
+use crate::types::{Candle, Intent, Signal};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Side {
+    #[default]
+    Flat,
+    Long,
+    Short,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Position {
+    side: Side,
+    entry_time: u64,
+    entry_price: f64,
+    stop_loss: Option<f64>,
+    take_profit: Option<f64>,
+    trailing_stop: Option<f64>,
+    // Best price since entry, highest for long and lowest for short
+    extreme: f64,
+    tag: Option<String>,
+}
+
+impl Position {
+    pub fn new() -> Position {
+        Position::default()
+    }
+    pub fn get_side(&self) -> Side {
+        self.side
+    }
+    pub fn get_entry_time(&self) -> u64 {
+        self.entry_time
+    }
+    pub fn get_entry_price(&self) -> f64 {
+        self.entry_price
+    }
+    pub fn get_take_profit(&self) -> Option<f64> {
+        self.take_profit
+    }
+    pub fn get_tag(&self) -> Option<String> {
+        self.tag.clone()
+    }
+    pub fn is_flat(&self) -> bool {
+        self.side == Side::Flat
+    }
+
+    pub fn target(&self, signal: &Signal) -> Option<Side> {
+        // Side the signal asks for, None when nothing should change
+        match signal {
+            Signal::Sleep => None,
+            Signal::Long(_) => Some(Side::Long),
+            Signal::Short(_) => Some(Side::Short),
+            Signal::ExitLong if self.side == Side::Long => Some(Side::Flat),
+            Signal::ExitShort if self.side == Side::Short => Some(Side::Flat),
+            Signal::ExitLong | Signal::ExitShort => None,
+            Signal::Flat if self.side != Side::Flat => Some(Side::Flat),
+            Signal::Flat => None,
+        }
+    }
+
+    pub fn open(&mut self, side: Side, timestamp: u64, price: f64, intent: &Intent) {
+        // Signal for the side already held only updates the exits it gives, so
+        // repeating it does not restart the trailing stop
+        if side == Side::Flat {
+            return self.close();
+        }
+        if side != self.side {
+            *self = Position {
+                side,
+                entry_time: timestamp,
+                entry_price: price,
+                extreme: price,
+                ..Position::default()
+            };
+        }
+        self.stop_loss = intent.get_stop_loss().or(self.stop_loss);
+        self.take_profit = intent.get_take_profit().or(self.take_profit);
+        self.trailing_stop = intent.get_trailing_stop().or(self.trailing_stop);
+        self.tag = intent.get_tag().or_else(|| self.tag.clone());
+    }
+
+    pub fn close(&mut self) {
+        *self = Position::default();
+    }
+
+    pub fn stop_price(&self) -> Option<f64> {
+        // Tighter of the fixed and the trailing stop
+        let trailing = self.trailing_stop.map(|d| match self.side {
+            Side::Short => self.extreme * (1.0 + d),
+            _ => self.extreme * (1.0 - d),
+        });
+        match (self.stop_loss, trailing, self.side) {
+            (Some(s), Some(t), Side::Short) => Some(s.min(t)),
+            (Some(s), Some(t), _) => Some(s.max(t)),
+            (s, t, _) => s.or(t),
+        }
+    }
+
+    pub fn check_exit(&mut self, candle: &Candle) -> Option<f64> {
+        // Price at which a stop or target closed the position during the candle. Path
+        // inside the candle is unknown, so the stop is assumed to be hit first and the
+        // trailing stop moves only after the candle. Gaps fill at the open
+        let stop = self.stop_price();
+        let exit = match self.side {
+            Side::Flat => return None,
+            Side::Long => match (stop, self.take_profit) {
+                (Some(s), _) if candle.low() <= s => Some(candle.open().min(s)),
+                (_, Some(t)) if candle.high() >= t => Some(candle.open().max(t)),
+                _ => None,
+            },
+            Side::Short => match (stop, self.take_profit) {
+                (Some(s), _) if candle.high() >= s => Some(candle.open().max(s)),
+                (_, Some(t)) if candle.low() <= t => Some(candle.open().min(t)),
+                _ => None,
+            },
+        };
+        match exit {
+            Some(_) => self.close(),
+            None if self.side == Side::Long => self.extreme = self.extreme.max(candle.high()),
+            None => self.extreme = self.extreme.min(candle.low()),
+        }
+        exit
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn intent(signal: Signal) -> Intent {
+        signal.get_intent().unwrap().clone()
+    }
+
+    #[test]
+    fn targets() {
+        let mut position = Position::new();
+        assert_eq!(position.target(&Signal::Sleep), None);
+        assert_eq!(position.target(&Signal::ExitLong), None);
+        assert_eq!(position.target(&Signal::Flat), None);
+        assert_eq!(position.target(&Signal::long()), Some(Side::Long));
+        position.open(Side::Long, 0, 100.0, &Intent::default());
+        assert_eq!(position.target(&Signal::ExitShort), None);
+        assert_eq!(position.target(&Signal::ExitLong), Some(Side::Flat));
+        assert_eq!(position.target(&Signal::Flat), Some(Side::Flat));
+        assert_eq!(position.target(&Signal::short()), Some(Side::Short));
+    }
+
+    #[test]
+    fn long_exits() {
+        let mut position = Position::new();
+        let signal = Signal::long()
+            .stop_loss(95.0)
+            .take_profit(110.0)
+            .tag("test");
+        position.open(Side::Long, 0, 100.0, &intent(signal));
+        assert_eq!(position.get_tag(), Some("test".to_string()));
+        assert_eq!(
+            position.check_exit(&Candle::new(1, 100.0, 105.0, 96.0, 104.0, 1.0)),
+            None
+        );
+        // Both levels inside one candle, stop wins
+        let wide = Candle::new(2, 100.0, 111.0, 94.0, 104.0, 1.0);
+        assert_eq!(position.clone().check_exit(&wide), Some(95.0));
+        // Gap over the target fills at the open
+        let gap = Candle::new(2, 112.0, 113.0, 111.0, 112.0, 1.0);
+        assert_eq!(position.check_exit(&gap), Some(112.0));
+        assert!(position.is_flat());
+    }
+
+    #[test]
+    fn trailing() {
+        let mut position = Position::new();
+        position.open(
+            Side::Long,
+            0,
+            100.0,
+            &intent(Signal::long().trailing_stop(0.1)),
+        );
+        assert_eq!(position.stop_price(), Some(90.0));
+        assert_eq!(
+            position.check_exit(&Candle::new(1, 100.0, 120.0, 99.0, 118.0, 1.0)),
+            None
+        );
+        assert_eq!(position.stop_price(), Some(108.0));
+        // Repeated signal keeps the best price
+        position.open(
+            Side::Long,
+            2,
+            118.0,
+            &intent(Signal::long().stop_loss(100.0)),
+        );
+        assert_eq!(position.stop_price(), Some(108.0));
+        assert_eq!(position.get_entry_price(), 100.0);
+        assert_eq!(
+            position.check_exit(&Candle::new(2, 118.0, 119.0, 105.0, 106.0, 1.0)),
+            Some(108.0)
+        );
+    }
+
+    #[test]
+    fn short_exits() {
+        let mut position = Position::new();
+        let signal = Signal::short().stop_loss(105.0).trailing_stop(0.02);
+        position.open(Side::Short, 0, 100.0, &intent(signal));
+        assert_eq!(position.stop_price(), Some(102.0));
+        assert_eq!(
+            position.check_exit(&Candle::new(1, 100.0, 101.0, 90.0, 91.0, 1.0)),
+            None
+        );
+        assert_eq!(
+            position.check_exit(&Candle::new(2, 91.0, 93.0, 90.5, 92.0, 1.0)),
+            Some(91.8)
+        );
+    }
+}
@@ -0,0 +1,291 @@
+use crate::types::{Candle, CandleLine};
+
+// Series of different length are aligned on their last values, so an indicator with
+// shorter history can be compared with full price series. Missing indicator values
+// can be passed as NaN, comparisons with NaN never count as a cross.
+
+fn last_pair(series: &[f64], back: usize) -> Option<(f64, f64)> {
+    // Value before and at the bar `back` bars from the end
+    let len = series.len();
+    if len < back + 2 {
+        return None;
+    }
+    Some((series[len - back - 2], series[len - back - 1]))
+}
+
+fn cross_above_at(a: &[f64], b: &[f64], back: usize) -> bool {
+    match (last_pair(a, back), last_pair(b, back)) {
+        (Some((a0, a1)), Some((b0, b1))) => a0 <= b0 && a1 > b1,
+        _ => false,
+    }
+}
+
+fn cross_below_at(a: &[f64], b: &[f64], back: usize) -> bool {
+    match (last_pair(a, back), last_pair(b, back)) {
+        (Some((a0, a1)), Some((b0, b1))) => a0 >= b0 && a1 < b1,
+        _ => false,
+    }
+}
+
+pub fn cross_above(a: &[f64], b: &[f64]) -> bool {
+    // True if a went above b on the last bar
+    cross_above_at(a, b, 0)
+}
+
+pub fn cross_below(a: &[f64], b: &[f64]) -> bool {
+    // True if a went below b on the last bar
+    cross_below_at(a, b, 0)
+}
+
+pub fn crossed_above_within(a: &[f64], b: &[f64], bars: usize) -> bool {
+    // True if a went above b on any of the last bars, one means the last bar only
+    (0..bars).any(|back| cross_above_at(a, b, back))
+}
+
+pub fn crossed_below_within(a: &[f64], b: &[f64], bars: usize) -> bool {
+    (0..bars).any(|back| cross_below_at(a, b, back))
+}
+
+pub fn bars_since_cross(a: &[f64], b: &[f64]) -> Option<usize> {
+    // Number of bars since a crossed b in any direction, zero for the last bar
+    let len = a.len().min(b.len());
+    (0..len.saturating_sub(1))
+        .find(|&back| cross_above_at(a, b, back) || cross_below_at(a, b, back))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Divergence {
+    // Price makes a lower low while the indicator makes a higher low
+    Bullish,
+    // Price makes a higher high while the indicator makes a lower high
+    Bearish,
+}
+
+fn pivots(series: &[f64], strength: usize, high: bool) -> Vec<usize> {
+    // Indexes of values more extreme than `strength` values on each side
+    let len = series.len();
+    if len < 2 * strength + 1 {
+        return vec![];
+    }
+    (strength..len - strength)
+        .filter(|&i| {
+            let v = series[i];
+            (i - strength..=i + strength).filter(|&j| j != i).all(|j| {
+                if high {
+                    v > series[j]
+                } else {
+                    v < series[j]
+                }
+            })
+        })
+        .collect()
+}
+
+pub fn divergence(price: &[f64], indicator: &[f64], strength: usize) -> Option<Divergence> {
+    // Regular divergence between the two most recent price pivots, series must be aligned
+    let len = price.len().min(indicator.len());
+    let price = &price[price.len() - len..];
+    let indicator = &indicator[indicator.len() - len..];
+    let compare = |points: Vec<usize>| -> Option<(usize, usize)> {
+        let n = points.len();
+        if n < 2 {
+            None
+        } else {
+            Some((points[n - 2], points[n - 1]))
+        }
+    };
+    let lows = compare(pivots(price, strength, false));
+    let highs = compare(pivots(price, strength, true));
+    // Only the pivot which is the most recent one decides
+    let bullish = lows.filter(|&(a, b)| price[b] < price[a] && indicator[b] > indicator[a]);
+    let bearish = highs.filter(|&(a, b)| price[b] > price[a] && indicator[b] < indicator[a]);
+    match (bullish, bearish) {
+        (Some((_, l)), Some((_, h))) if l > h => Some(Divergence::Bullish),
+        (Some(_), Some(_)) => Some(Divergence::Bearish),
+        (Some(_), None) => Some(Divergence::Bullish),
+        (None, Some(_)) => Some(Divergence::Bearish),
+        (None, None) => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pattern {
+    Doji,
+    Hammer,
+    BullishEngulfing,
+    BearishEngulfing,
+    MorningStar,
+}
+
+impl Candle {
+    pub fn body(&self) -> f64 {
+        (self.close() - self.open()).abs()
+    }
+    pub fn range(&self) -> f64 {
+        self.high() - self.low()
+    }
+    pub fn upper_shadow(&self) -> f64 {
+        self.high() - self.open().max(self.close())
+    }
+    pub fn lower_shadow(&self) -> f64 {
+        self.open().min(self.close()) - self.low()
+    }
+    pub fn is_bullish(&self) -> bool {
+        self.close() > self.open()
+    }
+    pub fn is_bearish(&self) -> bool {
+        self.close() < self.open()
+    }
+    pub fn is_doji(&self) -> bool {
+        // Body is at most a tenth of the range
+        self.range() > 0.0 && self.body() <= 0.1 * self.range()
+    }
+    pub fn is_hammer(&self) -> bool {
+        // Long lower shadow, small body at the top of the range. Trend before it is
+        // not checked, hanging man has the same shape
+        self.range() > 0.0
+            && self.lower_shadow() >= 2.0 * self.body()
+            && self.upper_shadow() <= self.body().max(0.1 * self.range())
+            && self.lower_shadow() >= 0.6 * self.range()
+    }
+}
+
+pub fn bullish_engulfing(prev: &Candle, candle: &Candle) -> bool {
+    prev.is_bearish()
+        && candle.is_bullish()
+        && candle.open() <= prev.close()
+        && candle.close() >= prev.open()
+        && candle.body() > prev.body()
+}
+
+pub fn bearish_engulfing(prev: &Candle, candle: &Candle) -> bool {
+    prev.is_bullish()
+        && candle.is_bearish()
+        && candle.open() >= prev.close()
+        && candle.close() <= prev.open()
+        && candle.body() > prev.body()
+}
+
+pub fn morning_star(first: &Candle, second: &Candle, third: &Candle) -> bool {
+    // Long falling candle, small one below its middle and rising one closing above it
+    let middle = (first.open() + first.close()) / 2.0;
+    first.is_bearish()
+        && first.body() >= 0.5 * first.range()
+        && second.body() <= 0.3 * first.body()
+        && second.open().max(second.close()) < middle
+        && third.is_bullish()
+        && third.close() > middle
+}
+
+impl CandleLine {
+    pub fn patterns_at(&self, index: usize) -> Vec<Pattern> {
+        // Patterns ending with the candle at index
+        let mut found = Vec::new();
+        if index >= self.len() {
+            return found;
+        }
+        let candle = self.get(index);
+        if candle.is_doji() {
+            found.push(Pattern::Doji);
+        }
+        if candle.is_hammer() {
+            found.push(Pattern::Hammer);
+        }
+        if index >= 1 {
+            let prev = self.get(index - 1);
+            if bullish_engulfing(&prev, &candle) {
+                found.push(Pattern::BullishEngulfing);
+            }
+            if bearish_engulfing(&prev, &candle) {
+                found.push(Pattern::BearishEngulfing);
+            }
+        }
+        if index >= 2 && morning_star(&self.get(index - 2), &self.get(index - 1), &candle) {
+            found.push(Pattern::MorningStar);
+        }
+        found
+    }
+    pub fn find_patterns(&self) -> Vec<(usize, Pattern)> {
+        (0..self.len())
+            .flat_map(|i| self.patterns_at(i).into_iter().map(move |p| (i, p)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn crosses() {
+        assert!(cross_above(&[2.0, 4.0], &[3.0, 1.0]));
+        // Longer series are aligned on the last values
+        assert!(cross_above(&[0.0, 10.0, 2.0, 4.0], &[3.0, 1.0]));
+        assert!(!cross_above(&[2.0, 4.0], &[3.0, 4.0]));
+        assert!(!cross_above(&[2.0, 4.0], &[0.0, 10.0, 2.0, 4.0]));
+        assert!(!cross_above(&[3.0, 1.0], &[0.0, 10.0, 2.0, 4.0]));
+        assert!(cross_below(&[3.0, 1.0], &[0.0, 10.0, 2.0, 4.0]));
+        assert!(!cross_above(&[1.0], &[0.0]));
+        assert!(!cross_above(&[f64::NAN, 2.0], &[1.0, 1.0]));
+    }
+
+    #[test]
+    fn crosses_within() {
+        let a = [1.0, 3.0, 4.0, 5.0, 2.0];
+        let b = [2.0, 2.0, 2.0, 2.0, 2.5];
+        assert!(!crossed_above_within(&a, &b, 3));
+        assert!(crossed_above_within(&a, &b, 4));
+        assert!(crossed_below_within(&a, &b, 1));
+        assert_eq!(bars_since_cross(&a, &b), Some(0));
+        assert_eq!(bars_since_cross(&a[..4], &b[..4]), Some(2));
+        assert_eq!(bars_since_cross(&[1.0, 2.0], &[0.0, 0.0]), None);
+    }
+
+    #[test]
+    fn divergences() {
+        // Lower low in price, higher low in indicator
+        let price = [10.0, 8.0, 9.0, 10.0, 7.0, 9.0, 9.5];
+        let rsi = [50.0, 30.0, 40.0, 45.0, 35.0, 45.0, 50.0];
+        assert_eq!(divergence(&price, &rsi, 1), Some(Divergence::Bullish));
+        // Higher high in price, lower high in indicator
+        let price = [1.0, 3.0, 2.0, 4.0, 3.0];
+        let rsi = [40.0, 70.0, 50.0, 60.0, 50.0];
+        assert_eq!(divergence(&price, &rsi, 1), Some(Divergence::Bearish));
+        let confirmed = [40.0, 60.0, 50.0, 70.0, 50.0];
+        assert_eq!(divergence(&price, &confirmed, 1), None);
+        assert_eq!(divergence(&price[..2], &rsi[..2], 1), None);
+    }
+
+    #[test]
+    fn single_candle_patterns() {
+        assert!(Candle::new(0, 10.0, 11.0, 9.0, 10.05, 1.0).is_doji());
+        assert!(!Candle::new(0, 10.0, 11.0, 9.0, 10.5, 1.0).is_doji());
+        assert!(!Candle::new(0, 10.0, 10.0, 10.0, 10.0, 1.0).is_doji());
+        let hammer = Candle::new(0, 10.0, 10.6, 8.0, 10.5, 1.0);
+        assert!(hammer.is_hammer());
+        // Inverted shape has the long shadow on top
+        assert!(!Candle::new(0, 8.1, 10.6, 8.0, 8.5, 1.0).is_hammer());
+    }
+
+    #[test]
+    fn line_patterns() {
+        let line = CandleLine::new_from_vec(vec![
+            Candle::new(0, 12.0, 12.2, 9.8, 10.0, 1.0),
+            Candle::new(1, 9.7, 9.9, 9.4, 9.6, 1.0),
+            Candle::new(2, 9.5, 11.8, 9.4, 11.5, 1.0),
+            Candle::new(3, 11.6, 11.7, 10.9, 11.0, 1.0),
+            Candle::new(4, 10.9, 12.0, 10.8, 11.9, 1.0),
+            Candle::new(5, 12.0, 12.1, 10.5, 10.6, 1.0),
+        ]);
+        assert_eq!(
+            line.find_patterns(),
+            vec![
+                (2, Pattern::BullishEngulfing),
+                (2, Pattern::MorningStar),
+                (4, Pattern::BullishEngulfing),
+                (5, Pattern::BearishEngulfing),
+            ]
+        );
+        assert!(line.patterns_at(10).is_empty());
+    }
+}
@@ -0,0 +1,201 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Sizing {
+    // Same amount of A for every position
+    FixedQuantity(f64),
+    // A worth the same amount of B for every position
+    FixedQuote(f64),
+    // Fraction of equity, free B plus value of held A
+    FractionOfBalance(f64),
+    // Hitting the stop loss costs this fraction of equity
+    FixedRisk(f64),
+    // Move of one ATR costs this fraction of equity
+    Volatility {
+        risk: f64,
+        period: usize,
+    },
+    // Fraction of the Kelly bet for the given win rate and average win to loss ratio
+    Kelly {
+        fraction: f64,
+        win_rate: f64,
+        payoff: f64,
+    },
+}
+
+impl Default for Sizing {
+    fn default() -> Sizing {
+        // Whole balance, as orders were sized before sizing policies existed
+        Sizing::FractionOfBalance(1.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SizingInput {
+    pub price: f64,
+    // Free B plus value of held A in B
+    pub equity: f64,
+    // Stop loss of the position, needed by FixedRisk
+    pub stop: Option<f64>,
+    // Average true range, needed by Volatility
+    pub atr: Option<f64>,
+    // Fraction given by the signal, scales the size of every policy
+    pub fraction: f64,
+}
+
+impl Sizing {
+    pub fn validate(&self) -> Result<()> {
+        let fraction = |name: &str, value: f64| {
+            if value > 0.0 && value <= 1.0 {
+                Ok(())
+            } else {
+                Err(Error::Validation(format!(
+                    "{} of {:?} has to be in (0, 1]",
+                    name, self
+                )))
+            }
+        };
+        match *self {
+            Sizing::FixedQuantity(amount) | Sizing::FixedQuote(amount) if amount <= 0.0 => Err(
+                Error::Validation(format!("Amount of {:?} has to be positive", self)),
+            ),
+            Sizing::FixedQuantity(_) | Sizing::FixedQuote(_) => Ok(()),
+            Sizing::FractionOfBalance(f) => fraction("Fraction", f),
+            Sizing::FixedRisk(risk) => fraction("Risk", risk),
+            Sizing::Volatility { period: 0, .. } => Err(Error::Validation(
+                "Volatility sizing needs ATR period of at least 1".to_string(),
+            )),
+            Sizing::Volatility { risk, .. } => fraction("Risk", risk),
+            Sizing::Kelly {
+                fraction: f,
+                win_rate,
+                payoff,
+            } => {
+                fraction("Fraction", f)?;
+                fraction("Win rate", win_rate)?;
+                if payoff > 0.0 {
+                    Ok(())
+                } else {
+                    Err(Error::Validation(format!(
+                        "Payoff of {:?} has to be positive",
+                        self
+                    )))
+                }
+            }
+        }
+    }
+
+    pub fn quantity(&self, input: &SizingInput) -> Option<f64> {
+        // Amount of A a long position should hold, None when the policy lacks data for
+        // it. Spot market can't borrow, so it is never worth more than the equity
+        if input.price <= 0.0 || input.equity <= 0.0 {
+            return None;
+        }
+        let quantity = match *self {
+            Sizing::FixedQuantity(amount) => amount,
+            Sizing::FixedQuote(amount) => amount / input.price,
+            Sizing::FractionOfBalance(f) => input.equity * f / input.price,
+            Sizing::FixedRisk(risk) => {
+                let distance = (input.price - input.stop?).abs();
+                if distance <= 0.0 {
+                    return None;
+                }
+                input.equity * risk / distance
+            }
+            Sizing::Volatility { risk, .. } => match input.atr? {
+                atr if atr > 0.0 => input.equity * risk / atr,
+                _ => return None,
+            },
+            Sizing::Kelly {
+                fraction,
+                win_rate,
+                payoff,
+            } => {
+                // Negative edge means no bet at all
+                let kelly = (win_rate - (1.0 - win_rate) / payoff).max(0.0);
+                input.equity * fraction * kelly / input.price
+            }
+        };
+        Some((quantity * input.fraction).min(input.equity / input.price))
+    }
+
+    pub fn atr_period(&self) -> Option<usize> {
+        match self {
+            Sizing::Volatility { period, .. } => Some(*period),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn input(stop: Option<f64>, atr: Option<f64>) -> SizingInput {
+        SizingInput {
+            price: 100.0,
+            equity: 10_000.0,
+            stop,
+            atr,
+            fraction: 1.0,
+        }
+    }
+
+    #[test]
+    fn quantities() {
+        let plain = input(None, None);
+        assert_eq!(Sizing::FixedQuantity(2.0).quantity(&plain), Some(2.0));
+        assert_eq!(Sizing::FixedQuote(500.0).quantity(&plain), Some(5.0));
+        assert_eq!(Sizing::FractionOfBalance(0.4).quantity(&plain), Some(40.0));
+        // 1% of equity over a stop 5 below the price
+        assert_eq!(Sizing::FixedRisk(0.01).quantity(&plain), None);
+        assert_eq!(
+            Sizing::FixedRisk(0.01).quantity(&input(Some(95.0), None)),
+            Some(20.0)
+        );
+        let volatility = Sizing::Volatility {
+            risk: 0.02,
+            period: 14,
+        };
+        assert_eq!(volatility.quantity(&plain), None);
+        assert_eq!(volatility.quantity(&input(None, Some(4.0))), Some(50.0));
+        // Full Kelly for 60% wins paying 1:1 is 20% of equity
+        let kelly = Sizing::Kelly {
+            fraction: 0.5,
+            win_rate: 0.6,
+            payoff: 1.0,
+        };
+        assert!((kelly.quantity(&plain).unwrap() - 10.0).abs() < 1e-9);
+        let losing = Sizing::Kelly {
+            fraction: 1.0,
+            win_rate: 0.3,
+            payoff: 1.0,
+        };
+        assert_eq!(losing.quantity(&plain), Some(0.0));
+    }
+
+    #[test]
+    fn limits() {
+        // Never more than the equity can buy, signal fraction scales the result
+        assert_eq!(
+            Sizing::FixedQuantity(500.0).quantity(&input(None, None)),
+            Some(100.0)
+        );
+        let half = SizingInput {
+            fraction: 0.5,
+            ..input(None, None)
+        };
+        assert_eq!(Sizing::FractionOfBalance(0.4).quantity(&half), Some(20.0));
+        assert!(Sizing::default().validate().is_ok());
+        assert!(Sizing::FractionOfBalance(1.5).validate().is_err());
+        assert!(Sizing::FixedQuote(0.0).validate().is_err());
+        assert!(Sizing::Volatility {
+            risk: 0.01,
+            period: 0
+        }
+        .validate()
+        .is_err());
+    }
+}
@@ -1,102 +1,284 @@
-use crate::types::{Candle, CandleLine, Signal};
-//use std::time::{Duration, Instant};
+use std::collections::HashMap;
 
-pub fn exs(candles: &Vec<Candle>) -> Signal {
-    //let t = Instant::now();
-    let cline = CandleLine::new_from_vec(candles.clone());
-    let mut ha_cline = cline.heikinashi();
+use crate::error::{Error, Result};
+use crate::indicators::{batch, Ema, HeikinAshi, Sma, Smma, Stream};
+use crate::types::{Candle, Signal};
 
-    //println!("Calculating {} bars of heikenashi took: {} microseconds",&candles.len(), t.elapsed().as_micros());
-//    println!(
-//        "{:#?}\n------",
-//        ha_cline.get_range(ha_cline.len(), ha_cline.len() - 4)
-//    );
+pub trait Strategy: Send {
+    fn name(&self) -> &str;
+    // Number of candles needed before signals are meaningful
+    fn window(&self) -> usize;
+    fn params(&self) -> HashMap<String, f64> {
+        HashMap::new()
+    }
+    fn set_param(&mut self, name: &str, _value: f64) -> Result<()> {
+        Err(Error::Validation(format!(
+            "Strategy {} has no parameter {}",
+            self.name(),
+            name
+        )))
+    }
+    // Called with every new candle and with every update of the last, unclosed one,
+    // which has the same open time as the previous call
+    fn on_candle(&mut self, candle: &Candle) -> Signal;
+    // Drops any state kept between candles
+    fn reset(&mut self) {}
+}
+
+type Factory = Box<dyn Fn() -> Box<dyn Strategy> + Send + Sync>;
 
-    if cline.last().close() > ha_cline.last().close(){
-        Signal::Long
+pub struct StrategyRegistry {
+    factories: HashMap<String, Factory>,
+}
+
+impl StrategyRegistry {
+    pub fn new() -> StrategyRegistry {
+        // Registry without any strategies, default one comes with the built-in ones
+        StrategyRegistry {
+            factories: HashMap::new(),
+        }
     }
-    else if cline.last().close() < ha_cline.get(ha_cline.len().wrapping_sub(1)).low(){
-        Signal::Short
+    pub fn register<F>(&mut self, name: &str, factory: F)
+    where
+        F: Fn() -> Box<dyn Strategy> + Send + Sync + 'static,
+    {
+        // Names are case insensitive, registering the same name again replaces the strategy
+        self.factories
+            .insert(name.to_lowercase(), Box::new(factory));
     }
-    else{
-        Signal::Sleep
+    pub fn create(&self, name: &str) -> Result<Box<dyn Strategy>> {
+        self.factories
+            .get(&name.to_lowercase())
+            .map(|factory| factory())
+            .ok_or_else(|| Error::Validation(format!("Unknown strategy {}", name)))
+    }
+    pub fn contains(&self, name: &str) -> bool {
+        self.factories.contains_key(&name.to_lowercase())
+    }
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.factories.keys().cloned().collect();
+        names.sort();
+        names
     }
 }
 
-impl CandleLine {
-    pub fn heikinashi(&self) -> CandleLine {
-        // Method generating Heikin Ashi candlesticks, best use with buffer of at least 10
-        // candles in past to decrease "synthetic" first candle
-        let data = self.all();
-        let mut ha = CandleLine::new();
-        // Initial candle
-        ha.push(Candle::new(
-            data[0].timestamp(),
-            (data[0].open() + data[0].close()) / 2.0,
-            data[0].high(),
-            data[0].low(),
-            (data[0].open() + data[0].high() + data[0].low() + data[0].close()) / 4.0,
-            data[0].volume(),
-        ));
-
-        // Add all remaining candles
-        for i in 1..data.len() {
-            let kline = Candle::new(
-                data[i].timestamp(),
-                (ha.get(i - 1).open() + ha.get(i - 1).close()) / 2.0, // This needs to be changed
-                data[i].high(),
-                data[i].low(),
-                (data[i].open() + data[i].high() + data[i].low() + data[i].close()) / 4.0,
-                data[i].volume(),
-            );
-            ha.push(kline);
-        }
-        ha
+impl Default for StrategyRegistry {
+    fn default() -> StrategyRegistry {
+        let mut registry = StrategyRegistry::new();
+        registry.register("exs", || Box::new(Exs::new()));
+        registry
     }
 }
 
-pub fn cross_over(a: Vec<f64>, b: Vec<f64>) -> bool {
-    if (a[0] <= b[0]) & (a[1] > b[1]) {
-        true
-    } else {
-        false
+pub struct Exs {
+    window: usize,
+    ha: Stream<HeikinAshi>,
+}
+
+impl Exs {
+    pub fn new() -> Exs {
+        Exs {
+            window: 32,
+            ha: Stream::new(HeikinAshi::new()),
+        }
     }
 }
 
-pub fn moving_average(data: Vec<f64>, window: usize) -> Vec<f64> {
-    let mut result: Vec<f64> = Vec::new();
+impl Default for Exs {
+    fn default() -> Exs {
+        Exs::new()
+    }
+}
 
-    for i in 0..data.len() {
-        if i < window - 1 {
-            result.push(data[i]);
+impl Strategy for Exs {
+    fn name(&self) -> &str {
+        "exs"
+    }
+    fn window(&self) -> usize {
+        self.window
+    }
+    fn params(&self) -> HashMap<String, f64> {
+        let mut params = HashMap::new();
+        params.insert("window".to_string(), self.window as f64);
+        params
+    }
+    fn set_param(&mut self, name: &str, value: f64) -> Result<()> {
+        match name {
+            "window" if value >= 1.0 => self.window = value as usize,
+            "window" => return Err(Error::Validation("Window must be at least 1".to_string())),
+            _ => {
+                return Err(Error::Validation(format!(
+                    "Strategy exs has no parameter {}",
+                    name
+                )))
+            }
+        }
+        Ok(())
+    }
+    fn on_candle(&mut self, candle: &Candle) -> Signal {
+        let ha = self.ha.update(candle.timestamp(), *candle);
+        // Synthetic first Heikin Ashi candle needs a few bars to fade out
+        if self.ha.bars() < self.window {
+            Signal::Sleep
+        } else if candle.close() > ha.close() {
+            Signal::long().tag("above ha close")
+        } else if candle.close() < ha.low() {
+            Signal::Flat
         } else {
-            let mut temp = result[i + 1 - window..i].to_vec();
-            temp.push(data[i]);
-            result.push(avg(&temp));
+            Signal::Sleep
         }
     }
-    result
+    fn reset(&mut self) {
+        self.ha.reset();
+    }
+}
+
+pub fn moving_average(data: &[f64], window: usize) -> Vec<Option<f64>> {
+    // Simple moving average, None until the window is full
+    batch(&mut Sma::new(window), data.iter().copied())
 }
-pub fn avg(data: &Vec<f64>) -> f64 {
-    data.clone().iter().sum::<f64>() / data.len() as f64
+pub fn exponential_moving_average(data: &[f64], window: usize) -> Vec<Option<f64>> {
+    // Seeded with simple average of the first window values
+    batch(&mut Ema::new(window), data.iter().copied())
+}
+pub fn smoothed_moving_average(data: &[f64], window: usize) -> Vec<Option<f64>> {
+    // Wilder's smoothing, each value is (previous * (window - 1) + new) / window
+    batch(&mut Smma::new(window), data.iter().copied())
+}
+pub fn avg(data: &[f64]) -> f64 {
+    data.iter().sum::<f64>() / data.len() as f64
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    fn assert_close(calculated: Vec<Option<f64>>, expected: Vec<Option<f64>>) {
+        assert_eq!(calculated.len(), expected.len());
+        for (c, e) in calculated.iter().zip(expected.iter()) {
+            assert_eq!(c.is_some(), e.is_some());
+            assert!((c.unwrap_or(0.0) - e.unwrap_or(0.0)).abs() < 1e-9);
+        }
+    }
+
     #[test]
     fn ma_test() {
-        let base = vec![
-            10.0,
-            20.0,
-            11.833333333333334,
-            20.644444444444446,
-            44.159259259259265,
-        ];
-        let calculated = moving_average(vec![10.0, 20.0, 5.5, 30.1, 100.0], 3);
-
-        assert_eq!(base, calculated);
+        let calculated = moving_average(&[10.0, 20.0, 5.5, 30.1, 100.0], 3);
+        assert_close(
+            calculated,
+            vec![
+                None,
+                None,
+                Some(11.833333333333334),
+                Some(18.533333333333335),
+                Some(45.2),
+            ],
+        );
+        assert_close(
+            moving_average(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0], 2),
+            vec![None, Some(1.5), Some(2.5), Some(3.5), Some(4.5), Some(5.5)],
+        );
+        assert_close(
+            moving_average(&[2.0, 4.0, 6.0, 8.0, 10.0, 0.0, 2.0], 3),
+            vec![
+                None,
+                None,
+                Some(4.0),
+                Some(6.0),
+                Some(8.0),
+                Some(6.0),
+                Some(4.0),
+            ],
+        );
+        // Window longer than data gives no values at all
+        assert_close(moving_average(&[1.0, 2.0], 3), vec![None, None]);
+    }
+
+    #[test]
+    fn ema_test() {
+        // Alpha is 0.5 for window of 3
+        assert_close(
+            exponential_moving_average(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0], 3),
+            vec![None, None, Some(2.0), Some(3.0), Some(4.0), Some(5.0)],
+        );
+    }
+
+    #[test]
+    fn smma_test() {
+        assert_close(
+            smoothed_moving_average(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0], 3),
+            vec![
+                None,
+                None,
+                Some(2.0),
+                Some(8.0 / 3.0),
+                Some(31.0 / 9.0),
+                Some(116.0 / 27.0),
+            ],
+        );
+    }
+
+    struct Always(Signal);
+
+    impl Strategy for Always {
+        fn name(&self) -> &str {
+            "always"
+        }
+        fn window(&self) -> usize {
+            1
+        }
+        fn on_candle(&mut self, _candle: &Candle) -> Signal {
+            self.0.clone()
+        }
+    }
+
+    #[test]
+    fn registry() {
+        let mut registry = StrategyRegistry::default();
+        assert!(registry.contains("ExS"));
+        assert!(matches!(registry.create("nope"), Err(Error::Validation(_))));
+
+        registry.register("Always", || Box::new(Always(Signal::long())));
+        assert_eq!(registry.names(), vec!["always", "exs"]);
+        let mut strategy = registry.create("always").unwrap();
+        assert_eq!(strategy.on_candle(&Candle::zeros()), Signal::long());
+        assert!(strategy.set_param("window", 3.0).is_err());
+    }
+
+    #[test]
+    fn exs_params() {
+        let mut strategy = StrategyRegistry::default().create("exs").unwrap();
+        strategy.set_param("window", 10.0).unwrap();
+        assert_eq!(strategy.window(), 10);
+        assert_eq!(strategy.params()["window"], 10.0);
+        assert!(strategy.set_param("window", 0.0).is_err());
+        assert!(strategy.set_param("length", 5.0).is_err());
+    }
+
+    #[test]
+    fn exs_ticks() {
+        // Feeding unclosed ticks before each closed candle ends with the same signals
+        let candles: Vec<Candle> = (0..60)
+            .map(|i| {
+                let p = 100.0 + (i as f64 / 3.0).sin() * 5.0;
+                Candle::new(
+                    i * 60_000,
+                    p,
+                    p + 2.0,
+                    p - 2.0,
+                    p + (i % 3) as f64 - 1.0,
+                    1.0,
+                )
+            })
+            .collect();
+        let mut closed = Exs::new();
+        let mut live = Exs::new();
+        for candle in candles.iter() {
+            live.on_candle(&Candle::new(candle.timestamp(), 1.0, 1.0, 1.0, 1.0, 1.0));
+            assert_eq!(live.on_candle(candle), closed.on_candle(candle));
+        }
+        live.reset();
+        assert_eq!(live.on_candle(&candles[59]), Signal::Sleep);
     }
 
     #[test]
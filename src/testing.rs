@@ -0,0 +1,107 @@
+// Helpers shared by unit tests
+use async_trait::async_trait;
+use std::sync::Mutex;
+
+use crate::builder::{OcoOrder, OcoResponse};
+use crate::clock::Clock;
+use crate::error::{Error, Result};
+use crate::exchange::{Exchange, KlineStream};
+use crate::exchange_info::{ExchangeInfo, SymbolInfo};
+use crate::types::{Balance, Candle, Order, OrderInfo, OrderResponse};
+
+pub fn minute_candles(minutes: &[u64]) -> Vec<Candle> {
+    minutes
+        .iter()
+        .map(|m| Candle::new(m * 60_000, 1.0, 1.0, 1.0, 1.0, 1.0))
+        .collect()
+}
+
+fn unsupported<T>() -> Result<T> {
+    Err(Error::Validation("not supported by FakeExchange".into()))
+}
+
+// Serves candles from memory, repeating the candle before start to imitate overlapping pages
+pub struct FakeExchange {
+    candles: Vec<Candle>,
+    requests: Mutex<usize>,
+}
+
+impl FakeExchange {
+    pub fn new(candles: Vec<Candle>) -> FakeExchange {
+        FakeExchange {
+            candles,
+            requests: Mutex::new(0),
+        }
+    }
+    pub fn requests(&self) -> usize {
+        *self.requests.lock().unwrap()
+    }
+}
+
+#[async_trait]
+impl Exchange for FakeExchange {
+    fn name(&self) -> String {
+        "FAKE".to_string()
+    }
+    fn clock(&self) -> Clock {
+        Clock::new()
+    }
+    async fn sync_clock(&self) -> Result<Clock> {
+        unsupported()
+    }
+    async fn get_candles(&self, _: &str, _: &str, _: usize) -> Result<Vec<Candle>> {
+        unsupported()
+    }
+    async fn get_candles_range(
+        &self,
+        _: &str,
+        _: &str,
+        start_ms: u64,
+        end_ms: u64,
+        limit: usize,
+    ) -> Result<Vec<Candle>> {
+        *self.requests.lock().unwrap() += 1;
+        let first = self
+            .candles
+            .iter()
+            .position(|c| c.timestamp() >= start_ms)
+            .unwrap_or(self.candles.len())
+            .saturating_sub(1);
+        Ok(self.candles[first..]
+            .iter()
+            .filter(|c| c.timestamp() <= end_ms)
+            .take(limit)
+            .copied()
+            .collect())
+    }
+    async fn get_server_time(&self) -> Result<u64> {
+        unsupported()
+    }
+    async fn get_exchange_info(&self) -> Result<ExchangeInfo> {
+        unsupported()
+    }
+    async fn get_symbol_info(&self, _: &str) -> Result<SymbolInfo> {
+        unsupported()
+    }
+    async fn place_order(&self, _: &Order) -> Result<OrderResponse> {
+        unsupported()
+    }
+    async fn test_order(&self, _: &Order) -> Result<()> {
+        unsupported()
+    }
+    async fn place_oco(&self, _: &OcoOrder) -> Result<OcoResponse> {
+        unsupported()
+    }
+    async fn cancel_order(&self, _: &str, _: u64) -> Result<OrderInfo> {
+        unsupported()
+    }
+    async fn query_order(&self, _: &str, _: u64) -> Result<OrderInfo> {
+        unsupported()
+    }
+    async fn get_balances(&self) -> Result<Vec<Balance>> {
+        unsupported()
+    }
+    fn subscribe_klines(&self, _: &str, _: &str) -> Result<Box<dyn KlineStream>> {
+        unsupported()
+    }
+}
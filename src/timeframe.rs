@@ -0,0 +1,239 @@
+use crate::download::interval_ms;
+use crate::error::{Error, Result};
+use crate::exchange::KlineUpdate;
+use crate::types::{Candle, CandleLine};
+
+// Intervals served directly by the exchange, shortest first
+pub const EXCHANGE_INTERVALS: [&str; 14] = [
+    "1m", "3m", "5m", "15m", "30m", "1h", "2h", "4h", "6h", "8h", "12h", "1d", "3d", "1w",
+];
+
+const DAY_MS: u64 = 86_400_000;
+const WEEK_MS: u64 = 7 * DAY_MS;
+// Unix epoch was a Thursday, exchange weeks start on Monday
+const WEEK_OFFSET_MS: u64 = 4 * DAY_MS;
+
+pub fn is_exchange_interval(interval: &str) -> bool {
+    EXCHANGE_INTERVALS.contains(&interval)
+}
+
+pub fn parse_interval(interval: &str) -> Result<u64> {
+    interval_ms(interval)
+        .filter(|ms| *ms > 0)
+        .ok_or_else(|| Error::Validation(format!("Unsupported interval {}", interval)))
+}
+
+pub fn base_interval(interval: &str) -> Result<&'static str> {
+    // Longest exchange interval whose candles add up exactly to the requested one
+    let target = parse_interval(interval)?;
+    EXCHANGE_INTERVALS
+        .iter()
+        .rev()
+        .find(|base| {
+            let base_ms = interval_ms(base).unwrap();
+            target.is_multiple_of(base_ms)
+                && (!base_ms.is_multiple_of(WEEK_MS) || target.is_multiple_of(WEEK_MS))
+        })
+        .copied()
+        .ok_or_else(|| Error::Validation(format!("Interval {} can not be built from 1m", interval)))
+}
+
+pub fn align(timestamp: u64, interval_ms: u64) -> u64 {
+    // Open time of the bar of given length containing timestamp, bars start at UTC midnight
+    if interval_ms.is_multiple_of(WEEK_MS) {
+        // Times before the first Monday fall into a bar opened at epoch
+        let shifted = timestamp + interval_ms - WEEK_OFFSET_MS;
+        (shifted - shifted % interval_ms + WEEK_OFFSET_MS).saturating_sub(interval_ms)
+    } else if interval_ms < DAY_MS && !DAY_MS.is_multiple_of(interval_ms) {
+        // Intraday bars which do not divide a day restart at midnight
+        let day = timestamp - timestamp % DAY_MS;
+        day + (timestamp - day) / interval_ms * interval_ms
+    } else {
+        timestamp - timestamp % interval_ms
+    }
+}
+
+pub fn bar_close(open_time: u64, interval_ms: u64) -> u64 {
+    // End of the bar, the last bar of a day may be cut short by midnight
+    let end = open_time + interval_ms;
+    if interval_ms < DAY_MS {
+        end.min(open_time - open_time % DAY_MS + DAY_MS)
+    } else {
+        end
+    }
+}
+
+fn merge(bar: Candle, next: Candle) -> Candle {
+    Candle::new(
+        bar.timestamp(),
+        bar.open(),
+        bar.high().max(next.high()),
+        bar.low().min(next.low()),
+        next.close(),
+        bar.volume() + next.volume(),
+    )
+}
+
+fn start_bar(candle: Candle, interval_ms: u64) -> Candle {
+    Candle::new(
+        align(candle.timestamp(), interval_ms),
+        candle.open(),
+        candle.high(),
+        candle.low(),
+        candle.close(),
+        candle.volume(),
+    )
+}
+
+impl CandleLine {
+    pub fn resample(&self, interval: &str) -> Result<CandleLine> {
+        Ok(self.resample_ms(parse_interval(interval)?))
+    }
+    pub fn resample_ms(&self, interval_ms: u64) -> CandleLine {
+        // Candles must be sorted, bars without any candle are left out and the
+        // last bar may be built from fewer candles than a full one
+        let mut bars: Vec<Candle> = Vec::new();
+        for candle in self.all() {
+            match bars.last_mut() {
+                Some(bar) if bar.timestamp() == align(candle.timestamp(), interval_ms) => {
+                    *bar = merge(*bar, candle)
+                }
+                _ => bars.push(start_bar(candle, interval_ms)),
+            }
+        }
+        CandleLine::new_from_vec(bars)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Resampler {
+    interval_ms: u64,
+    base_ms: u64,
+    // Closed base candles of the bar being built
+    done: Option<Candle>,
+}
+
+impl Resampler {
+    pub fn new(interval: &str, base: &str) -> Result<Resampler> {
+        let interval_ms = parse_interval(interval)?;
+        let base_ms = parse_interval(base)?;
+        if !interval_ms.is_multiple_of(base_ms) {
+            return Err(Error::Validation(format!(
+                "Interval {} is not a multiple of {}",
+                interval, base
+            )));
+        }
+        Ok(Resampler {
+            interval_ms,
+            base_ms,
+            done: None,
+        })
+    }
+    pub fn update(&mut self, update: KlineUpdate) -> KlineUpdate {
+        // Turns updates of base candles into updates of the resampled bar,
+        // which is closed together with its last base candle
+        let ts = update.candle.timestamp();
+        let open_time = align(ts, self.interval_ms);
+        let bar = match self.done {
+            Some(done) if done.timestamp() == open_time => merge(done, update.candle),
+            _ => {
+                self.done = None;
+                start_bar(update.candle, self.interval_ms)
+            }
+        };
+        if update.closed {
+            self.done = Some(bar);
+        }
+        KlineUpdate {
+            candle: bar,
+            closed: update.closed && ts + self.base_ms >= bar_close(open_time, self.interval_ms),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::minute_candles;
+
+    #[test]
+    fn bases() {
+        assert_eq!(base_interval("7m").unwrap(), "1m");
+        assert_eq!(base_interval("45m").unwrap(), "15m");
+        assert_eq!(base_interval("2h").unwrap(), "2h");
+        assert_eq!(base_interval("5d").unwrap(), "1d");
+        assert_eq!(base_interval("2w").unwrap(), "1w");
+        assert!(base_interval("30s").is_err());
+        assert!(base_interval("0m").is_err());
+        assert!(is_exchange_interval("4h") && !is_exchange_interval("7m"));
+    }
+
+    #[test]
+    fn alignment() {
+        // 2021-06-01 12:34:56 UTC, a Tuesday
+        let ts = 1_622_550_896_000;
+        assert_eq!(align(ts, 3_600_000), 1_622_548_800_000);
+        assert_eq!(align(ts, DAY_MS), 1_622_505_600_000);
+        // Monday 2021-05-31 00:00 UTC
+        assert_eq!(align(ts, WEEK_MS), 1_622_419_200_000);
+        assert_eq!(align(1_622_419_200_000, WEEK_MS), 1_622_419_200_000);
+        assert_eq!(align(0, WEEK_MS), 0);
+        // 7m bars start again at midnight, the last one of a day lasts 5m
+        assert_eq!(align(DAY_MS + 8 * 60_000, 420_000), DAY_MS + 420_000);
+        assert_eq!(align(DAY_MS - 60_000, 420_000), DAY_MS - 300_000);
+        assert_eq!(bar_close(DAY_MS - 300_000, 420_000), DAY_MS);
+        assert_eq!(bar_close(0, 5 * DAY_MS), 5 * DAY_MS);
+    }
+
+    #[test]
+    fn resample_ohlcv() {
+        let line = CandleLine::new_from_vec(vec![
+            Candle::new(0, 10.0, 12.0, 9.0, 11.0, 1.0),
+            Candle::new(60_000, 11.0, 15.0, 10.0, 14.0, 2.0),
+            Candle::new(120_000, 14.0, 14.5, 8.0, 9.0, 3.0),
+            Candle::new(180_000, 9.0, 10.0, 8.5, 9.5, 4.0),
+            // Gap at 4m and 5m, partial last bar
+            Candle::new(360_000, 9.5, 11.0, 9.0, 10.5, 5.0),
+        ]);
+        let bars = line.resample("3m").unwrap();
+        assert_eq!(bars.timestamps(), vec![0, 180_000, 360_000]);
+        assert_eq!(bars.opens(), vec![10.0, 9.0, 9.5]);
+        assert_eq!(bars.highs(), vec![15.0, 10.0, 11.0]);
+        assert_eq!(bars.lows(), vec![8.0, 8.5, 9.0]);
+        assert_eq!(bars.closes(), vec![9.0, 9.5, 10.5]);
+        assert_eq!(bars.volumes(), vec![6.0, 4.0, 5.0]);
+        assert!(line.resample("1M").is_err());
+    }
+
+    #[test]
+    fn resample_stream() {
+        let minutes: Vec<Candle> = minute_candles(&(0..8).collect::<Vec<u64>>())
+            .iter()
+            .enumerate()
+            .map(|(i, c)| Candle::new(c.timestamp(), 1.0, 1.0 + i as f64, 0.5, 1.0, 2.0))
+            .collect();
+        let mut resampler = Resampler::new("7m", "1m").unwrap();
+        let mut closed = Vec::new();
+        for candle in minutes.iter() {
+            // Every minute is first seen unclosed, then closed
+            let partial = resampler.update(KlineUpdate {
+                candle: *candle,
+                closed: false,
+            });
+            assert!(!partial.closed);
+            let update = resampler.update(KlineUpdate {
+                candle: *candle,
+                closed: true,
+            });
+            if update.closed {
+                closed.push(update.candle);
+            }
+        }
+        let batch = CandleLine::new_from_vec(minutes).resample("7m").unwrap();
+        assert_eq!(closed.len(), 1);
+        assert_eq!(closed[0].timestamp(), batch.first().timestamp());
+        assert_eq!(closed[0].volume(), batch.first().volume());
+        assert_eq!(closed[0].high(), batch.first().high());
+        assert!(Resampler::new("7m", "3m").is_err());
+    }
+}
@@ -0,0 +1,257 @@
+use crate::position::Side;
+use crate::types::{Candle, Execution, OrderSide};
+
+// Remainder under this fraction of the largest amount held is dust left by lot step
+// rounding, it does not keep the trade open
+const DUST: f64 = 0.01;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trade {
+    // Spot trades are long only, they open with a buy and close once sold
+    side: Side,
+    entry_time: u64,
+    exit_time: Option<u64>,
+    bought: f64,
+    cost: f64,
+    sold: f64,
+    proceeds: f64,
+    // All fees of the trade in B
+    fees: f64,
+    // Candles from the entry candle up to the one before the exit
+    bars: usize,
+    // Price extremes while the trade was held, path inside the entry and exit candles
+    // is unknown so only their fill prices count
+    high: f64,
+    low: f64,
+}
+
+impl Trade {
+    fn open(execution: &Execution) -> Trade {
+        Trade {
+            side: Side::Long,
+            entry_time: execution.get_timestamp(),
+            exit_time: None,
+            bought: 0.0,
+            cost: 0.0,
+            sold: 0.0,
+            proceeds: 0.0,
+            fees: 0.0,
+            bars: 0,
+            high: execution.get_price(),
+            low: execution.get_price(),
+        }
+    }
+
+    fn add(&mut self, execution: &Execution) {
+        let (price, quantity) = (execution.get_price(), execution.get_quantity());
+        match execution.get_side() {
+            OrderSide::BUY => {
+                self.bought += quantity;
+                self.cost += quantity * price;
+            }
+            OrderSide::SELL => {
+                self.sold += quantity;
+                self.proceeds += quantity * price;
+            }
+        }
+        self.fees += execution.get_fee();
+        self.high = self.high.max(price);
+        self.low = self.low.min(price);
+    }
+
+    fn hold(&mut self, candle: &Candle) {
+        // Candle of the entry itself counts only as a bar
+        self.bars += 1;
+        if candle.timestamp() > self.entry_time {
+            self.high = self.high.max(candle.high());
+            self.low = self.low.min(candle.low());
+        }
+    }
+
+    pub fn get_side(&self) -> Side {
+        self.side
+    }
+    pub fn get_entry_time(&self) -> u64 {
+        self.entry_time
+    }
+    pub fn get_exit_time(&self) -> Option<u64> {
+        self.exit_time
+    }
+    pub fn get_entry_price(&self) -> f64 {
+        // Average of all buys
+        if self.bought > 0.0 {
+            self.cost / self.bought
+        } else {
+            0.0
+        }
+    }
+    pub fn get_exit_price(&self) -> f64 {
+        // Average of all sells
+        if self.sold > 0.0 {
+            self.proceeds / self.sold
+        } else {
+            0.0
+        }
+    }
+    pub fn get_quantity(&self) -> f64 {
+        self.bought
+    }
+    pub fn get_fees(&self) -> f64 {
+        self.fees
+    }
+    pub fn get_bars(&self) -> usize {
+        self.bars
+    }
+    pub fn is_closed(&self) -> bool {
+        self.exit_time.is_some()
+    }
+    pub fn get_duration(&self) -> u64 {
+        self.exit_time.unwrap_or(self.entry_time) - self.entry_time
+    }
+    pub fn get_pnl(&self) -> f64 {
+        // Profit in B of what was sold so far, after all fees. Fees paid in A on buys
+        // are what is missing from the amount sold, so they count once
+        (self.get_exit_price() - self.get_entry_price()) * self.sold - self.fees
+    }
+    pub fn get_pnl_pct(&self) -> f64 {
+        if self.cost > 0.0 {
+            self.get_pnl() / self.cost
+        } else {
+            0.0
+        }
+    }
+    pub fn get_mae(&self) -> f64 {
+        // Maximum adverse excursion, largest move against the trade as fraction of entry
+        let entry = self.get_entry_price();
+        if entry > 0.0 {
+            (entry - self.low).max(0.0) / entry
+        } else {
+            0.0
+        }
+    }
+    pub fn get_mfe(&self) -> f64 {
+        // Maximum favorable excursion, largest move for the trade as fraction of entry
+        let entry = self.get_entry_price();
+        if entry > 0.0 {
+            (self.high - entry).max(0.0) / entry
+        } else {
+            0.0
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Ledger {
+    trades: Vec<Trade>,
+    open: Option<Trade>,
+    // Amount of A the open trade holds, buys pay their fee in A
+    held: f64,
+    peak: f64,
+}
+
+impl Ledger {
+    pub fn new() -> Ledger {
+        Ledger::default()
+    }
+
+    pub fn on_execution(&mut self, execution: &Execution) {
+        // Sells without an open trade get rid of A held from before, they are no trade
+        let price = execution.get_price();
+        match (execution.get_side(), self.open.as_mut()) {
+            (OrderSide::SELL, None) => return,
+            (OrderSide::BUY, None) => self.open = Some(Trade::open(execution)),
+            _ => (),
+        }
+        let trade = self.open.as_mut().expect("Trade was just opened");
+        trade.add(execution);
+        match execution.get_side() {
+            OrderSide::BUY => {
+                self.held += execution.get_quantity() - execution.get_fee() / price;
+                self.peak = self.peak.max(self.held);
+            }
+            OrderSide::SELL => self.held -= execution.get_quantity(),
+        }
+        if self.held <= self.peak * DUST {
+            trade.exit_time = Some(execution.get_timestamp());
+            self.trades.extend(self.open.take());
+            self.held = 0.0;
+            self.peak = 0.0;
+        }
+    }
+
+    pub fn on_candle(&mut self, candle: &Candle) {
+        if let Some(trade) = self.open.as_mut() {
+            trade.hold(candle);
+        }
+    }
+
+    pub fn get_trades(&self) -> Vec<Trade> {
+        // Closed trades only
+        self.trades.clone()
+    }
+    pub fn get_open(&self) -> Option<Trade> {
+        self.open.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn candle(timestamp: u64, high: f64, low: f64) -> Candle {
+        Candle::new(timestamp, low, high, low, high, 1.0)
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut ledger = Ledger::new();
+        ledger.on_execution(&Execution::new(0, OrderSide::BUY, 100.0, 10.0, 1.0));
+        // Entry candle does not move the extremes
+        ledger.on_candle(&candle(0, 130.0, 80.0));
+        ledger.on_candle(&candle(60_000, 105.0, 95.0));
+        ledger.on_candle(&candle(120_000, 112.0, 99.0));
+        assert!(ledger.get_trades().is_empty());
+        assert_eq!(ledger.get_open().unwrap().get_bars(), 3);
+        // Fee of the buy was paid in A, 9.99 is all there is to sell
+        ledger.on_execution(&Execution::new(
+            180_000,
+            OrderSide::SELL,
+            110.0,
+            9.99,
+            1.0989,
+        ));
+        ledger.on_candle(&candle(180_000, 150.0, 50.0));
+        assert!(ledger.get_open().is_none());
+        let trade = &ledger.get_trades()[0];
+        assert_eq!(trade.get_side(), Side::Long);
+        assert_eq!(trade.get_exit_time(), Some(180_000));
+        assert_eq!(trade.get_duration(), 180_000);
+        assert_eq!(trade.get_bars(), 3);
+        assert!((trade.get_fees() - 2.0989).abs() < 1e-9);
+        assert!((trade.get_pnl() - 97.8011).abs() < 1e-9);
+        assert!((trade.get_pnl_pct() - 0.0978011).abs() < 1e-9);
+        assert!((trade.get_mae() - 0.05).abs() < 1e-9);
+        assert!((trade.get_mfe() - 0.12).abs() < 1e-9);
+    }
+
+    #[test]
+    fn scaling_and_dust() {
+        let mut ledger = Ledger::new();
+        // Selling A held from before is no trade
+        ledger.on_execution(&Execution::new(0, OrderSide::SELL, 100.0, 1.0, 0.0));
+        assert!(ledger.get_open().is_none());
+        ledger.on_execution(&Execution::new(1, OrderSide::BUY, 100.0, 1.0, 0.0));
+        ledger.on_execution(&Execution::new(2, OrderSide::BUY, 130.0, 2.0, 0.0));
+        ledger.on_execution(&Execution::new(3, OrderSide::SELL, 120.0, 1.5, 0.0));
+        let open = ledger.get_open().unwrap();
+        assert_eq!(open.get_entry_price(), 120.0);
+        assert_eq!(open.get_pnl(), 0.0);
+        // Remainder left by step rounding closes it
+        ledger.on_execution(&Execution::new(4, OrderSide::SELL, 140.0, 1.499, 0.0));
+        let trades = ledger.get_trades();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].get_quantity(), 3.0);
+        assert!((trades[0].get_exit_price() - (180.0 + 209.86) / 2.999).abs() < 1e-9);
+        assert!((trades[0].get_mae() - 1.0 / 6.0).abs() < 1e-9);
+    }
+}
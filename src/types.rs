@@ -1,18 +1,34 @@
-use serde::{Deserialize, Serialize};
-use serde_json::{self, json, Value};
+use rust_decimal::prelude::FromPrimitive;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::fill::FillModel;
+use crate::sizing::Sizing;
+use crate::trade::{Ledger, Trade};
+use crate::validation::round_down_to_step;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Broker {
     name: String,
     api_key: String,
+    // Secret is never written out together with the rest of the config
+    #[serde(skip_serializing, default)]
+    secret_key: String,
     rest_api_url: String,
     ws_api_url: String,
 }
 impl Broker {
-    pub fn new(name: String, api_key: String, rest_api_url: String, ws_api_url: String) -> Broker {
+    pub fn new(
+        name: String,
+        api_key: String,
+        secret_key: String,
+        rest_api_url: String,
+        ws_api_url: String,
+    ) -> Broker {
         Broker {
             name: name.to_uppercase(),
             api_key,
+            secret_key,
             rest_api_url,
             ws_api_url,
         }
@@ -23,6 +39,9 @@ impl Broker {
     pub fn get_api_key(&self) -> String {
         self.api_key.clone()
     }
+    pub fn get_secret_key(&self) -> String {
+        self.secret_key.clone()
+    }
     pub fn get_rest_api_url(&self) -> String {
         self.rest_api_url.clone()
     }
@@ -40,6 +59,10 @@ pub struct Config {
     window: usize,
     strategy: String,
     broker: Broker,
+    #[serde(default)]
+    sizing: Sizing,
+    #[serde(default)]
+    fill_model: FillModel,
 }
 impl Config {
     pub fn new(
@@ -59,8 +82,20 @@ impl Config {
             window,
             strategy: strategy.to_lowercase(),
             broker,
+            sizing: Sizing::default(),
+            fill_model: FillModel::new(),
         }
     }
+    pub fn with_sizing(mut self, sizing: Sizing) -> Config {
+        // Position sizing used by backtests and live trading
+        self.sizing = sizing;
+        self
+    }
+    pub fn with_fill_model(mut self, fill_model: FillModel) -> Config {
+        // How backtests fill orders, live trading is filled by the exchange
+        self.fill_model = fill_model;
+        self
+    }
     pub fn get_name(&self) -> String {
         self.name.clone()
     }
@@ -85,6 +120,15 @@ impl Config {
     pub fn get_api_url(&self) -> String {
         self.broker.clone().get_rest_api_url()
     }
+    pub fn get_broker(&self) -> &Broker {
+        &self.broker
+    }
+    pub fn get_sizing(&self) -> Sizing {
+        self.sizing
+    }
+    pub fn get_fill_model(&self) -> FillModel {
+        self.fill_model
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Copy, Clone)]
@@ -140,7 +184,7 @@ impl Candle {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct CandleLine {
     data: Vec<Candle>,
 }
@@ -178,6 +222,10 @@ impl CandleLine {
         // Return number of elements in candleline / length of candleline
         self.data.len()
     }
+    pub fn is_empty(&self) -> bool {
+        // Return true if candleline holds no candles
+        self.data.is_empty()
+    }
     pub fn push(&mut self, kline: Candle) {
         // Add new candle to candleline
         self.data.push(kline);
@@ -215,27 +263,143 @@ impl CandleLine {
     }
 }
 
-#[derive(Debug, std::cmp::PartialEq, Copy, Clone)]
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Intent {
+    // Target position as a fraction of equity, whole equity when not given
+    fraction: Option<f64>,
+    // Entry only at this price or better
+    limit: Option<f64>,
+    stop_loss: Option<f64>,
+    take_profit: Option<f64>,
+    // Distance from the best price since entry, as a fraction of that price
+    trailing_stop: Option<f64>,
+    tag: Option<String>,
+}
+
+impl Intent {
+    pub fn get_fraction(&self) -> f64 {
+        self.fraction.unwrap_or(1.0)
+    }
+    pub fn get_limit(&self) -> Option<f64> {
+        self.limit
+    }
+    pub fn get_stop_loss(&self) -> Option<f64> {
+        self.stop_loss
+    }
+    pub fn get_take_profit(&self) -> Option<f64> {
+        self.take_profit
+    }
+    pub fn get_trailing_stop(&self) -> Option<f64> {
+        self.trailing_stop
+    }
+    pub fn get_tag(&self) -> Option<String> {
+        self.tag.clone()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Signal {
+    // Keep whatever is held now
     Sleep,
-    Long,
-    Short,
+    // Open or resize a long position
+    Long(Intent),
+    // Open or resize a short position
+    Short(Intent),
+    // Close the long position, if there is one
+    ExitLong,
+    // Close the short position, if there is one
+    ExitShort,
+    // Close any position
+    Flat,
 }
 
-#[derive(Debug, Clone)]
+impl Signal {
+    pub fn long() -> Signal {
+        // Whole equity at market price, details are added with the methods below,
+        // e.g. Signal::long().fraction(0.5).stop_loss(95.0)
+        Signal::Long(Intent::default())
+    }
+    pub fn short() -> Signal {
+        Signal::Short(Intent::default())
+    }
+    pub fn get_intent(&self) -> Option<&Intent> {
+        match self {
+            Signal::Long(intent) | Signal::Short(intent) => Some(intent),
+            _ => None,
+        }
+    }
+    fn with_intent<F: FnOnce(&mut Intent)>(mut self, f: F) -> Signal {
+        // Exits carry no details, setting them is a no-op
+        if let Signal::Long(intent) | Signal::Short(intent) = &mut self {
+            f(intent);
+        }
+        self
+    }
+    pub fn fraction(self, fraction: f64) -> Signal {
+        self.with_intent(|i| i.fraction = Some(fraction.clamp(0.0, 1.0)))
+    }
+    pub fn limit(self, price: f64) -> Signal {
+        self.with_intent(|i| i.limit = Some(price))
+    }
+    pub fn stop_loss(self, price: f64) -> Signal {
+        self.with_intent(|i| i.stop_loss = Some(price))
+    }
+    pub fn take_profit(self, price: f64) -> Signal {
+        self.with_intent(|i| i.take_profit = Some(price))
+    }
+    pub fn trailing_stop(self, distance: f64) -> Signal {
+        self.with_intent(|i| i.trailing_stop = Some(distance.clamp(0.0, 1.0)))
+    }
+    pub fn tag(self, tag: &str) -> Signal {
+        self.with_intent(|i| i.tag = Some(tag.to_string()))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct Journal {
     entries: Vec<Event>,
+    executions: Vec<Execution>,
+    // Trades made of the executions, kept up to date as they come
+    ledger: Ledger,
+    // Equity before the first candle, its trades and fees are already in the first event
+    start_equity: Option<f64>,
 }
 
 impl Journal {
     pub fn new() -> Journal {
         Journal {
             entries: Vec::new(),
+            executions: Vec::new(),
+            ledger: Ledger::new(),
+            start_equity: None,
         }
     }
+    pub fn with_start_equity(mut self, equity: f64) -> Journal {
+        self.start_equity = Some(equity);
+        self
+    }
+    pub fn get_start_equity(&self) -> Option<f64> {
+        self.start_equity
+    }
     pub fn put(&mut self, event: Event) {
+        self.ledger.on_candle(&event.get_candle());
         self.entries.push(event);
     }
+    pub fn put_execution(&mut self, execution: Execution) {
+        // Executions of a candle go in before its event
+        self.ledger.on_execution(&execution);
+        self.executions.push(execution);
+    }
+    pub fn get_executions(&self) -> Vec<Execution> {
+        self.executions.clone()
+    }
+    pub fn trades(&self) -> Vec<Trade> {
+        // Closed trades, in order of closing
+        self.ledger.get_trades()
+    }
+    pub fn open_trade(&self) -> Option<Trade> {
+        self.ledger.get_open()
+    }
     pub fn get(&self, index: usize) -> Event {
         self.entries[index].clone()
     }
@@ -272,13 +436,13 @@ impl Journal {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
 pub enum OrderSide {
     BUY,
     SELL,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
 pub enum TimeInForce {
     GTC,
     IOC,
@@ -286,7 +450,9 @@ pub enum TimeInForce {
     GTX,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+// Variant names mirror the exchange API
+#[allow(non_camel_case_types)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
 pub enum OrderType {
     LIMIT,
     MARKET,
@@ -297,13 +463,25 @@ pub enum OrderType {
     LIMIT_MAKER,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
 pub enum OrderRespType {
     ACK,
     RESULT,
     FULL,
 }
 
+#[allow(non_camel_case_types)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum OrderStatus {
+    NEW,
+    PARTIALLY_FILLED,
+    FILLED,
+    CANCELED,
+    PENDING_CANCEL,
+    REJECTED,
+    EXPIRED,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Order {
@@ -312,38 +490,54 @@ pub struct Order {
     r#type: OrderType,
     #[serde(skip_serializing_if = "Option::is_none")]
     time_in_force: Option<TimeInForce>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "ser_opt_decimal"
+    )]
     quantity: Option<f64>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    quoteOrderQty: Option<f64>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "ser_opt_decimal"
+    )]
+    quote_order_qty: Option<f64>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "ser_opt_decimal"
+    )]
     price: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    newClientOrderId: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    stopPrice: Option<f64>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    icebergQty: Option<f64>,
+    new_client_order_id: Option<String>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "ser_opt_decimal"
+    )]
+    stop_price: Option<f64>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "ser_opt_decimal"
+    )]
+    iceberg_qty: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     new_order_resp_type: Option<OrderRespType>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    recvWindow: Option<f64>,
+    recv_window: Option<u64>,
     timestamp: u64,
 }
 impl Order {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         symbol: String,
         side: OrderSide,
         r#type: OrderType,
         time_in_force: Option<TimeInForce>,
         quantity: Option<f64>,
-        quoteOrderQty: Option<f64>,
+        quote_order_qty: Option<f64>,
         price: Option<f64>,
-        newClientOrderId: Option<String>,
-        stopPrice: Option<f64>,
-        icebergQty: Option<f64>,
+        new_client_order_id: Option<String>,
+        stop_price: Option<f64>,
+        iceberg_qty: Option<f64>,
         new_order_resp_type: Option<OrderRespType>,
-        recvWindow: Option<f64>,
+        recv_window: Option<u64>,
         timestamp: u64,
     ) -> Order {
         Order {
@@ -352,16 +546,234 @@ impl Order {
             r#type,
             time_in_force,
             quantity,
-            quoteOrderQty,
+            quote_order_qty,
             price,
-            newClientOrderId,
-            stopPrice,
-            icebergQty,
+            new_client_order_id,
+            stop_price,
+            iceberg_qty,
             new_order_resp_type,
-            recvWindow,
+            recv_window,
+            timestamp,
+        }
+    }
+    pub fn get_symbol(&self) -> String {
+        self.symbol.clone()
+    }
+    pub fn get_side(&self) -> OrderSide {
+        self.side
+    }
+    pub fn get_type(&self) -> OrderType {
+        self.r#type
+    }
+    pub fn get_time_in_force(&self) -> Option<TimeInForce> {
+        self.time_in_force
+    }
+    pub fn get_quantity(&self) -> Option<f64> {
+        self.quantity
+    }
+    pub fn get_quote_order_qty(&self) -> Option<f64> {
+        self.quote_order_qty
+    }
+    pub fn get_price(&self) -> Option<f64> {
+        self.price
+    }
+    pub fn get_new_client_order_id(&self) -> Option<String> {
+        self.new_client_order_id.clone()
+    }
+    pub fn get_stop_price(&self) -> Option<f64> {
+        self.stop_price
+    }
+    pub fn get_iceberg_qty(&self) -> Option<f64> {
+        self.iceberg_qty
+    }
+    pub fn get_recv_window(&self) -> Option<u64> {
+        self.recv_window
+    }
+    pub fn get_timestamp(&self) -> u64 {
+        self.timestamp
+    }
+    pub fn get_resp_type(&self) -> OrderRespType {
+        // Exchange defaults to FULL for MARKET and LIMIT orders and to ACK for everything else
+        match (self.new_order_resp_type, self.r#type) {
+            (Some(resp_type), _) => resp_type,
+            (None, OrderType::MARKET) | (None, OrderType::LIMIT) => OrderRespType::FULL,
+            (None, _) => OrderRespType::ACK,
+        }
+    }
+}
+
+// Exchange sends all decimal values as strings to avoid precision loss
+pub(crate) fn de_str_f64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    s.parse::<f64>().map_err(serde::de::Error::custom)
+}
+
+// Exchange rejects scientific notation, which small f64 values would be written in
+pub(crate) fn ser_decimal<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+    match Decimal::from_f64(*value) {
+        Some(decimal) => serializer.serialize_str(&decimal.normalize().to_string()),
+        None => Err(serde::ser::Error::custom(format!(
+            "{} can't be sent as a decimal",
+            value
+        ))),
+    }
+}
+
+pub(crate) fn ser_opt_decimal<S: Serializer>(
+    value: &Option<f64>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(value) => ser_decimal(value, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderAck {
+    pub symbol: String,
+    pub order_id: u64,
+    #[serde(default)]
+    pub order_list_id: i64,
+    pub client_order_id: String,
+    pub transact_time: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderResult {
+    pub symbol: String,
+    pub order_id: u64,
+    #[serde(default)]
+    pub order_list_id: i64,
+    pub client_order_id: String,
+    pub transact_time: u64,
+    #[serde(deserialize_with = "de_str_f64")]
+    pub price: f64,
+    #[serde(deserialize_with = "de_str_f64")]
+    pub orig_qty: f64,
+    #[serde(deserialize_with = "de_str_f64")]
+    pub executed_qty: f64,
+    #[serde(deserialize_with = "de_str_f64")]
+    pub cummulative_quote_qty: f64,
+    pub status: OrderStatus,
+    pub time_in_force: TimeInForce,
+    pub r#type: OrderType,
+    pub side: OrderSide,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Fill {
+    #[serde(deserialize_with = "de_str_f64")]
+    pub price: f64,
+    #[serde(deserialize_with = "de_str_f64")]
+    pub qty: f64,
+    #[serde(deserialize_with = "de_str_f64")]
+    pub commission: f64,
+    pub commission_asset: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OrderFull {
+    #[serde(flatten)]
+    pub result: OrderResult,
+    pub fills: Vec<Fill>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderInfo {
+    // State of an existing order as returned by query and cancel requests
+    pub symbol: String,
+    pub order_id: u64,
+    pub client_order_id: String,
+    #[serde(deserialize_with = "de_str_f64")]
+    pub price: f64,
+    #[serde(deserialize_with = "de_str_f64")]
+    pub orig_qty: f64,
+    #[serde(deserialize_with = "de_str_f64")]
+    pub executed_qty: f64,
+    #[serde(deserialize_with = "de_str_f64")]
+    pub cummulative_quote_qty: f64,
+    pub status: OrderStatus,
+    pub time_in_force: TimeInForce,
+    pub r#type: OrderType,
+    pub side: OrderSide,
+    #[serde(default)]
+    pub time: u64,
+    #[serde(default)]
+    pub update_time: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Balance {
+    pub asset: String,
+    #[serde(deserialize_with = "de_str_f64")]
+    pub free: f64,
+    #[serde(deserialize_with = "de_str_f64")]
+    pub locked: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OrderResponse {
+    Ack(OrderAck),
+    Result(OrderResult),
+    Full(OrderFull),
+}
+impl OrderResponse {
+    pub fn get_order_id(&self) -> u64 {
+        match self {
+            OrderResponse::Ack(ack) => ack.order_id,
+            OrderResponse::Result(res) => res.order_id,
+            OrderResponse::Full(full) => full.result.order_id,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Execution {
+    // Single fill of an order, fee is always in B
+    timestamp: u64,
+    side: OrderSide,
+    price: f64,
+    quantity: f64,
+    fee: f64,
+}
+impl Execution {
+    pub fn new(timestamp: u64, side: OrderSide, price: f64, quantity: f64, fee: f64) -> Execution {
+        Execution {
             timestamp,
+            side,
+            price,
+            quantity,
+            fee,
         }
     }
+    pub fn from_fill(timestamp: u64, side: OrderSide, fill: &Fill) -> Execution {
+        // Buys pay commission in A, it is converted at the fill price
+        let fee = match side {
+            OrderSide::BUY => fill.commission * fill.price,
+            OrderSide::SELL => fill.commission,
+        };
+        Execution::new(timestamp, side, fill.price, fill.qty, fee)
+    }
+    pub fn get_timestamp(&self) -> u64 {
+        self.timestamp
+    }
+    pub fn get_side(&self) -> OrderSide {
+        self.side
+    }
+    pub fn get_price(&self) -> f64 {
+        self.price
+    }
+    pub fn get_quantity(&self) -> f64 {
+        self.quantity
+    }
+    pub fn get_fee(&self) -> f64 {
+        self.fee
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -375,9 +787,9 @@ impl Event {
     pub fn new(timestamp: usize, signal: Signal, market: Market, candle: Candle) -> Event {
         Event {
             timestamp,
-            signal: signal.clone(),
-            market: market.clone(),
-            candle: candle.clone(),
+            signal,
+            market,
+            candle,
         }
     }
     pub fn get_timestamp(&self) -> usize {
@@ -393,103 +805,298 @@ impl Event {
         self.candle
     }
 }
-#[derive(Debug)]
+// Length of a year in milliseconds, ratios are annualized with it
+const YEAR_MS: f64 = 365.0 * 24.0 * 3600.0 * 1000.0;
+
+#[derive(Debug, Clone, Default)]
 pub struct Stats {
+    // Changes are fractions, from the first to the last candle
     chg_passive: f64,
     chg_active: f64,
+    // Average number of candles a trade was held
     avg_in_pos: f64,
+    // Profits and losses of closed trades in B, losses are positive amounts
     avg_gain: f64,
     avg_loss: f64,
+    // Same as fraction of the cost of the trade
+    avg_gain_pct: f64,
+    avg_loss_pct: f64,
     cum_gain: f64,
     cum_loss: f64,
+    // Same as fraction of the equity on the first candle
+    cum_gain_pct: f64,
+    cum_loss_pct: f64,
+    // Fees of all executions in B, open trade included
     cum_fees: f64,
+    trades: usize,
+    win_rate: f64,
+    // Gross profit over gross loss, infinite when nothing was lost
+    profit_factor: f64,
+    // Average profit of a trade in B
+    expectancy: f64,
+    // Largest fall of equity from its peak as fraction, and milliseconds from that peak
+    // until equity got back to it or the journal ended
+    max_drawdown: f64,
+    max_drawdown_duration: u64,
+    // Annualized from returns between candles, risk free rate is taken as zero
+    sharpe: f64,
+    sortino: f64,
+    calmar: f64,
+    // Fraction of candles spent in a trade
+    exposure: f64,
 }
 impl Stats {
     pub fn init() -> Stats {
-        Stats {
-            chg_passive: 0.0,
-            chg_active: 0.0,
-            avg_in_pos: 0.0,
-            avg_gain: 0.0,
-            avg_loss: 0.0,
-            cum_gain: 0.0,
-            cum_loss: 0.0,
-            cum_fees: 0.0,
-        }
-    }
-
-    fn calc_chg_p(&mut self, journal: Journal) {
-        let data = journal.get_all();
-        let f = data.first();
-        let l = data.last();
-        //println!("\n========={:#?} {:#?}\n=========", f, l);
-        self.chg_passive = (l.unwrap().get_candle().close() - f.unwrap().get_candle().open())
-            / l.unwrap().get_candle().close()
-    }
-    fn calc_chg_a(&mut self, journal: Journal) {
-        let f = *journal.get_markets().first().unwrap();
-        let l = *journal.get_markets().last().unwrap();
-        if &f.get_a_amount() != &0.0 && &l.get_a_amount() != &0.0 {
-            println!("1-> {0} - {1} / {1}", l.a_in_b(), f.a_in_b());
-            self.chg_active = (l.a_in_b() - f.a_in_b()) / f.a_in_b();
-        } else if &f.get_b_amount() != &0.0 && &l.get_b_amount() != &0.0 {
-            println!("2-> {0} - {1} / {1}", l.get_b_amount(), f.get_b_amount());
-            self.chg_active = (l.get_b_amount() - f.get_b_amount()) / f.get_b_amount();
-        } else if &f.get_a_amount() == &0.0 && &l.get_a_amount() != &0.0 {
-            println!("3-> {0} - {1} / {1}", l.a_in_b(), f.get_b_amount());
-            self.chg_active = (l.a_in_b() - f.get_b_amount()) / f.get_b_amount();
-        } else if &f.get_b_amount() == &0.0 && &l.get_b_amount() != &0.0 {
-            println!("4-> {0} - {1} / {1}", l.get_b_amount(), f.a_in_b());
-            self.chg_active = (l.get_b_amount() - f.a_in_b()) / f.a_in_b();
-        }
-    }
-    //something's wrong I can feel it or more like I see it spews BS
-    fn calc_avg_in_pos(&mut self, journal: Journal) {
-        let mut temp: Vec<usize> = Vec::new();
-        let mut counter: usize = 0;
-        let mut temp_sig = journal.get_signals().first().unwrap().to_owned();
-        println!("{:?}", &journal.get_signals());
-        for sig in journal.get_signals() {
-            if sig == Signal::Sleep || sig == temp_sig {
-                counter += 1;
-            } else {
-                temp_sig = sig;
-                temp.push(counter);
-                counter = 1;
-            }
-            //println!("counter:{:?}\ntemps:{:?}", &counter, &temp);
+        Stats::default()
+    }
+
+    fn calc_chg_p(&mut self, candles: &[Candle]) {
+        let first = candles.first().unwrap().open();
+        if first > 0.0 {
+            self.chg_passive = (candles.last().unwrap().close() - first) / first;
+        }
+    }
+    fn calc_chg_a(&mut self, equity: &[f64], start: f64) {
+        if start > 0.0 {
+            self.chg_active = (equity.last().unwrap() - start) / start;
         }
+    }
+    fn calc_avg_in_pos(&mut self, trades: &[Trade]) {
+        if !trades.is_empty() {
+            let bars = trades.iter().map(|t| t.get_bars()).sum::<usize>();
+            self.avg_in_pos = bars as f64 / trades.len() as f64;
+        }
+    }
+    fn calc_exposure(&mut self, journal: &Journal, trades: &[Trade], candles: usize) {
+        let open = journal.open_trade().map_or(0, |t| t.get_bars());
+        let bars = trades.iter().map(|t| t.get_bars()).sum::<usize>() + open;
+        self.exposure = bars as f64 / candles as f64;
+    }
+
+    fn calc_avg_gain(&mut self, trades: &[Trade]) {
+        let gains: Vec<&Trade> = trades.iter().filter(|t| t.get_pnl() > 0.0).collect();
+        if !gains.is_empty() {
+            let n = gains.len() as f64;
+            self.avg_gain = gains.iter().map(|t| t.get_pnl()).sum::<f64>() / n;
+            self.avg_gain_pct = gains.iter().map(|t| t.get_pnl_pct()).sum::<f64>() / n;
+        }
+    }
+    fn calc_avg_loss(&mut self, trades: &[Trade]) {
+        let losses: Vec<&Trade> = trades.iter().filter(|t| t.get_pnl() < 0.0).collect();
+        if !losses.is_empty() {
+            let n = losses.len() as f64;
+            self.avg_loss = -losses.iter().map(|t| t.get_pnl()).sum::<f64>() / n;
+            self.avg_loss_pct = -losses.iter().map(|t| t.get_pnl_pct()).sum::<f64>() / n;
+        }
+    }
+    fn calc_cum_gain(&mut self, trades: &[Trade], start: f64) {
+        self.cum_gain = trades.iter().map(|t| t.get_pnl().max(0.0)).sum();
+        if start > 0.0 {
+            self.cum_gain_pct = self.cum_gain / start;
+        }
+    }
+    fn calc_cum_loss(&mut self, trades: &[Trade], start: f64) {
+        self.cum_loss = -trades.iter().map(|t| t.get_pnl().min(0.0)).sum::<f64>();
+        if start > 0.0 {
+            self.cum_loss_pct = self.cum_loss / start;
+        }
+    }
+    fn calc_cum_fees(&mut self, journal: &Journal) {
+        self.cum_fees = journal.get_executions().iter().map(|e| e.get_fee()).sum();
+    }
+
+    fn calc_trades(&mut self, trades: &[Trade]) {
+        // Needs gains and losses calculated first
+        self.trades = trades.len();
+        if trades.is_empty() {
+            return;
+        }
+        let wins = trades.iter().filter(|t| t.get_pnl() > 0.0).count();
+        self.win_rate = wins as f64 / trades.len() as f64;
+        self.profit_factor = match (self.cum_gain, self.cum_loss) {
+            (_, loss) if loss > 0.0 => self.cum_gain / loss,
+            (gain, _) if gain > 0.0 => f64::INFINITY,
+            _ => 0.0,
+        };
+        self.expectancy = (self.cum_gain - self.cum_loss) / trades.len() as f64;
+    }
 
-        self.avg_in_pos = temp.iter().sum::<usize>() as f64 / temp.len() as f64;
+    fn calc_drawdown(&mut self, timestamps: &[u64], equity: &[f64]) {
+        let (mut peak, mut peak_time) = (equity[0], timestamps[0]);
+        // Whether the deepest drawdown so far is the one in progress
+        let mut deepest = false;
+        for (&time, &value) in timestamps.iter().zip(equity.iter()) {
+            if value >= peak {
+                if deepest {
+                    self.max_drawdown_duration = time - peak_time;
+                    deepest = false;
+                }
+                peak = value;
+                peak_time = time;
+            } else if peak > 0.0 && (peak - value) / peak > self.max_drawdown {
+                self.max_drawdown = (peak - value) / peak;
+                deepest = true;
+            }
+        }
+        if deepest {
+            self.max_drawdown_duration = timestamps.last().unwrap() - peak_time;
+        }
     }
 
-    fn calc_avg_gain(&mut self, journal: Journal) {
-        let mut temp: Vec<f64> = Vec::new();
-        let (sigs, candles) = (journal.get_signals(), journal.get_candles());
-        let tmp_sig = sigs.first().unwrap();
-        for sig in sigs.iter() {
-            if sig != tmp_sig && sig != &Signal::Sleep {}
+    fn calc_ratios(&mut self, timestamps: &[u64], equity: &[f64], start: f64) {
+        // Needs drawdown calculated first
+        let span = (timestamps.last().unwrap() - timestamps[0]) as f64;
+        let returns: Vec<f64> = equity
+            .windows(2)
+            .filter(|w| w[0] > 0.0)
+            .map(|w| w[1] / w[0] - 1.0)
+            .collect();
+        if returns.is_empty() || span <= 0.0 {
+            return;
+        }
+        let n = returns.len() as f64;
+        let periods_per_year = YEAR_MS / (span / (equity.len() - 1) as f64);
+        let mean = returns.iter().sum::<f64>() / n;
+        let deviation = (returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / n).sqrt();
+        let downside = (returns.iter().map(|r| r.min(0.0).powi(2)).sum::<f64>() / n).sqrt();
+        if deviation > 0.0 {
+            self.sharpe = mean / deviation * periods_per_year.sqrt();
+        }
+        if downside > 0.0 {
+            self.sortino = mean / downside * periods_per_year.sqrt();
+        }
+        let last = *equity.last().unwrap();
+        if self.max_drawdown > 0.0 && start > 0.0 {
+            let annual = (last / start).powf(YEAR_MS / span) - 1.0;
+            self.calmar = annual / self.max_drawdown;
         }
     }
-// TODO!!
-    fn calc_avg_loss(&mut self, journal: Journal) {}
-    fn calc_cum_gain(&mut self, journal: Journal) {}
-    fn calc_cum_loss(&mut self, journal: Journal) {}
-    fn calc_cum_fees(&mut self, journal: Journal) {}
+
+    pub fn get_chg_passive(&self) -> f64 {
+        self.chg_passive
+    }
+    pub fn get_chg_active(&self) -> f64 {
+        self.chg_active
+    }
+    pub fn get_avg_in_pos(&self) -> f64 {
+        self.avg_in_pos
+    }
+    pub fn get_avg_gain(&self) -> f64 {
+        self.avg_gain
+    }
+    pub fn get_avg_loss(&self) -> f64 {
+        self.avg_loss
+    }
+    pub fn get_avg_gain_pct(&self) -> f64 {
+        self.avg_gain_pct
+    }
+    pub fn get_avg_loss_pct(&self) -> f64 {
+        self.avg_loss_pct
+    }
+    pub fn get_cum_gain(&self) -> f64 {
+        self.cum_gain
+    }
+    pub fn get_cum_loss(&self) -> f64 {
+        self.cum_loss
+    }
+    pub fn get_cum_gain_pct(&self) -> f64 {
+        self.cum_gain_pct
+    }
+    pub fn get_cum_loss_pct(&self) -> f64 {
+        self.cum_loss_pct
+    }
+    pub fn get_cum_fees(&self) -> f64 {
+        self.cum_fees
+    }
+    pub fn get_trades(&self) -> usize {
+        self.trades
+    }
+    pub fn get_win_rate(&self) -> f64 {
+        self.win_rate
+    }
+    pub fn get_profit_factor(&self) -> f64 {
+        self.profit_factor
+    }
+    pub fn get_expectancy(&self) -> f64 {
+        self.expectancy
+    }
+    pub fn get_max_drawdown(&self) -> f64 {
+        self.max_drawdown
+    }
+    pub fn get_max_drawdown_duration(&self) -> u64 {
+        self.max_drawdown_duration
+    }
+    pub fn get_sharpe(&self) -> f64 {
+        self.sharpe
+    }
+    pub fn get_sortino(&self) -> f64 {
+        self.sortino
+    }
+    pub fn get_calmar(&self) -> f64 {
+        self.calmar
+    }
+    pub fn get_exposure(&self) -> f64 {
+        self.exposure
+    }
 
     pub fn calculate(&mut self, journal: Journal) {
-        self.calc_chg_a(journal.clone());
-        self.calc_chg_p(journal.clone());
-        self.calc_avg_in_pos(journal.clone());
-        self.calc_avg_gain(journal.clone());
-        self.calc_avg_loss(journal.clone());
-        self.calc_cum_gain(journal.clone());
-        self.calc_cum_loss(journal.clone());
-        self.calc_cum_fees(journal.clone());
+        // Empty journal has nothing to say, everything stays zero
+        *self = Stats::init();
+        let candles = journal.get_candles();
+        if candles.is_empty() {
+            return;
+        }
+        let timestamps: Vec<u64> = candles.iter().map(|c| c.timestamp()).collect();
+        let equity: Vec<f64> = journal.get_markets().iter().map(|m| m.equity()).collect();
+        let trades = journal.trades();
+        // Journal without the equity before the run starts from the first candle
+        let start = journal.get_start_equity().unwrap_or(equity[0]);
+        self.calc_chg_p(&candles);
+        self.calc_chg_a(&equity, start);
+        self.calc_avg_in_pos(&trades);
+        self.calc_exposure(&journal, &trades, candles.len());
+        self.calc_avg_gain(&trades);
+        self.calc_avg_loss(&trades);
+        self.calc_cum_gain(&trades, start);
+        self.calc_cum_loss(&trades, start);
+        self.calc_cum_fees(&journal);
+        self.calc_trades(&trades);
+        self.calc_drawdown(&timestamps, &equity);
+        self.calc_ratios(&timestamps, &equity, start);
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
+struct Resting {
+    order: Order,
+    // Stop price of a stop limit order was reached, it waits as a plain limit now
+    triggered: bool,
+}
+
+fn rises_to_trigger(order: &Order) -> bool {
+    // Stop loss buys and take profit sells wait for the price to rise to the stop price
+    matches!(
+        (order.get_type(), order.get_side()),
+        (OrderType::STOP_LOSS, OrderSide::BUY)
+            | (OrderType::STOP_LOSS_LIMIT, OrderSide::BUY)
+            | (OrderType::TAKE_PROFIT, OrderSide::SELL)
+            | (OrderType::TAKE_PROFIT_LIMIT, OrderSide::SELL)
+    )
+}
+
+fn trigger_price(order: &Order, open: f64, high: f64, low: f64) -> Option<f64> {
+    // Price at which the stop price is reached, gap over it triggers at the open
+    let stop = order.get_stop_price()?;
+    match rises_to_trigger(order) {
+        true if open >= stop => Some(open),
+        true if high >= stop => Some(stop),
+        false if open <= stop => Some(open),
+        false if low <= stop => Some(stop),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Market {
     currency_a_amount: f64,
     currency_b_amount: f64,
@@ -498,6 +1105,8 @@ pub struct Market {
     min_a_transaction: f64,
     step_size: f64,
     transaction_fee: f64,
+    // Orders waiting for their price, in order of placement
+    orders: Vec<Resting>,
 }
 impl Market {
     pub fn new(
@@ -508,7 +1117,7 @@ impl Market {
         step_size: f64,
         transaction_fee: f64,
     ) -> Market {
-        let ratio_b_to_a = 1.0 / &ratio_a_to_b;
+        let ratio_b_to_a = 1.0 / ratio_a_to_b;
         Market {
             currency_a_amount,
             currency_b_amount,
@@ -517,6 +1126,7 @@ impl Market {
             min_a_transaction,
             step_size,
             transaction_fee,
+            orders: vec![],
         }
     }
     pub fn get_a_amount(&self) -> f64 {
@@ -532,6 +1142,15 @@ impl Market {
         self.currency_b_amount * self.ratio_b_to_a
     }
 
+    pub fn get_ratio(&self) -> f64 {
+        // Price of A in B
+        self.ratio_a_to_b
+    }
+    pub fn set_amounts(&mut self, currency_a_amount: f64, currency_b_amount: f64) {
+        // Balances taken from the exchange
+        self.currency_a_amount = currency_a_amount;
+        self.currency_b_amount = currency_b_amount;
+    }
     pub fn update_ratio(&mut self, ratio: f64) {
         self.ratio_a_to_b = ratio;
         self.ratio_b_to_a = 1.0 / ratio;
@@ -545,58 +1164,572 @@ impl Market {
     fn get_step_size(&self) -> f64 {
         self.step_size
     }
-    pub fn buy(&mut self, amount: f64) {
-        if &amount >= &0.0 && &amount * self.ratio_a_to_b <= self.currency_b_amount {
+    pub fn buy(&mut self, amount: f64) -> bool {
+        // Returns whether the amount was bought, balances stay untouched otherwise.
+        // Amount computed from B itself may cost a rounding error more than there is
+        let cost = amount * self.ratio_a_to_b;
+        if amount >= 0.0 && cost <= self.currency_b_amount * (1.0 + 1e-12) {
             self.currency_a_amount += amount * (1.0 - self.transaction_fee);
-            self.currency_b_amount -= amount * self.ratio_a_to_b;
+            self.currency_b_amount = (self.currency_b_amount - cost).max(0.0);
+            true
         } else {
-            println!("Given value exceeds allowed range");
+            false
         }
     }
-    pub fn sell(&mut self, amount: f64) {
-        if &amount >= &0.0 && &amount <= &self.currency_a_amount {
+    pub fn sell(&mut self, amount: f64) -> bool {
+        // Returns whether the amount was sold, balances stay untouched otherwise
+        if amount >= 0.0 && amount <= self.currency_a_amount {
             self.currency_a_amount -= amount;
             self.currency_b_amount += amount * self.ratio_a_to_b * (1.0 - self.transaction_fee);
+            true
         } else {
-            println!("Given value exceeds allowed range");
+            false
         }
     }
-    pub fn buy_max(&mut self) {
-        let t =
-            self.get_min_a_transaction() * (self.b_in_a() / self.get_min_a_transaction()).floor();
+    pub fn buy_max(&mut self) -> f64 {
+        // Largest amount of A affordable with current B, rounded down to the step size.
+        // Returns amount bought, zero when it is below the minimal transaction
+        let t = self.get_step_size() * (self.b_in_a() / self.get_step_size()).floor();
+        if t >= self.get_min_a_transaction() && self.buy(t) {
+            t
+        } else {
+            0.0
+        }
     }
-    pub fn sell_max(&mut self) {
-        if self.get_a_amount() >= self.get_min_a_transaction() {
-            self.sell(self.get_a_amount());
+    pub fn sell_max(&mut self) -> f64 {
+        let t = self.get_a_amount();
+        if t >= self.get_min_a_transaction() && self.sell(t) {
+            t
+        } else {
+            0.0
+        }
+    }
+    fn ordered(&self, order: &Order, price: f64) -> f64 {
+        match (order.get_quantity(), order.get_quote_order_qty()) {
+            (Some(quantity), _) => quantity,
+            (None, Some(quote)) => quote / price,
+            (None, None) => 0.0,
+        }
+    }
+
+    fn response(
+        &self,
+        order: &Order,
+        status: OrderStatus,
+        ordered: f64,
+        fills: Vec<Fill>,
+        timestamp: u64,
+    ) -> OrderResponse {
+        // Simulated exchange answers everything with FULL response
+        let executed = fills.iter().map(|fill| fill.qty).sum::<f64>();
+        let quote = fills.iter().map(|fill| fill.qty * fill.price).sum::<f64>();
+        OrderResponse::Full(OrderFull {
+            result: OrderResult {
+                symbol: order.get_symbol(),
+                order_id: 0,
+                order_list_id: -1,
+                client_order_id: order.get_new_client_order_id().unwrap_or_default(),
+                transact_time: timestamp,
+                price: order.get_price().unwrap_or(0.0),
+                orig_qty: ordered,
+                executed_qty: executed,
+                cummulative_quote_qty: quote,
+                status,
+                time_in_force: order.get_time_in_force().unwrap_or(TimeInForce::GTC),
+                r#type: order.get_type(),
+                side: order.get_side(),
+            },
+            fills,
+        })
+    }
+
+    fn trade(&mut self, order: &Order, price: f64, timestamp: u64) -> OrderResponse {
+        // Fills the order at the price right away. Only what balances allow is filled and
+        // the rest of the order expires, fill or kill order expires whole instead
+        let mid = self.ratio_a_to_b;
+        self.update_ratio(price);
+        let ordered = self.ordered(order, price);
+        let available = match order.get_side() {
+            OrderSide::BUY => round_down_to_step(self.b_in_a(), self.step_size),
+            OrderSide::SELL => self.currency_a_amount,
+        };
+        let killed = order.get_time_in_force() == Some(TimeInForce::FOK) && available < ordered;
+        let quantity = ordered.min(available);
+        let filled = !killed
+            && quantity > 0.0
+            && match order.get_side() {
+                OrderSide::BUY => self.buy(quantity),
+                OrderSide::SELL => self.sell(quantity),
+            };
+        let status = match filled {
+            true if quantity < ordered => OrderStatus::EXPIRED,
+            true => OrderStatus::FILLED,
+            false if killed => OrderStatus::EXPIRED,
+            false => OrderStatus::REJECTED,
+        };
+        // Simulated market has no asset names, buys pay fee in A and sells in B
+        let fills = if filled {
+            let (commission, commission_asset) = match order.get_side() {
+                OrderSide::BUY => (quantity * self.transaction_fee, "A"),
+                OrderSide::SELL => (quantity * price * self.transaction_fee, "B"),
+            };
+            vec![Fill {
+                price,
+                qty: quantity,
+                commission,
+                commission_asset: commission_asset.to_string(),
+            }]
+        } else {
+            vec![]
+        };
+        self.update_ratio(mid);
+        self.response(order, status, ordered, fills, timestamp)
+    }
+
+    fn rest(
+        &mut self,
+        order: Order,
+        triggered: bool,
+        ordered: f64,
+        timestamp: u64,
+    ) -> OrderResponse {
+        // Immediate or cancel and fill or kill orders expire instead of waiting, stop
+        // limit orders apply them only once triggered
+        let waiting = order.get_stop_price().is_some() && !triggered;
+        let immediate = matches!(
+            order.get_time_in_force(),
+            Some(TimeInForce::IOC) | Some(TimeInForce::FOK)
+        );
+        if immediate && !waiting {
+            return self.response(&order, OrderStatus::EXPIRED, ordered, vec![], timestamp);
+        }
+        let response = self.response(&order, OrderStatus::NEW, ordered, vec![], timestamp);
+        self.orders.push(Resting { order, triggered });
+        response
+    }
+
+    pub fn place(&mut self, order: Order, model: &FillModel, candle: &Candle) -> OrderResponse {
+        // Places the order on the simulated exchange with the current ratio as mid price.
+        // Market orders fill right away, limit orders when marketable and otherwise rest
+        // as their time in force allows, stop orders rest until the stop price is reached.
+        // Resting orders do not lock balances, they fill with whatever is there then
+        let mid = self.ratio_a_to_b;
+        let timestamp = candle.timestamp();
+        let ordered = self.ordered(&order, mid);
+        let side = order.get_side();
+        let post_only = order.get_type() == OrderType::LIMIT_MAKER
+            || order.get_time_in_force() == Some(TimeInForce::GTX);
+        let stop = order.get_stop_price().is_some();
+        match (order.get_type(), order.get_price()) {
+            _ if order.check_fields().is_err() => {
+                self.response(&order, OrderStatus::REJECTED, ordered, vec![], timestamp)
+            }
+            (OrderType::MARKET, _) => {
+                let price = model.market_price(side, mid, ordered, candle);
+                self.trade(&order, price, timestamp)
+            }
+            // Exchange refuses stops which would trigger immediately
+            _ if stop && trigger_price(&order, mid, mid, mid).is_some() => {
+                self.response(&order, OrderStatus::REJECTED, ordered, vec![], timestamp)
+            }
+            _ if stop => self.rest(order, false, ordered, timestamp),
+            (_, Some(limit)) => match model.marketable(side, limit, mid) {
+                Some(_) if post_only => {
+                    self.response(&order, OrderStatus::REJECTED, ordered, vec![], timestamp)
+                }
+                Some(price) => self.trade(&order, price, timestamp),
+                None => self.rest(order, false, ordered, timestamp),
+            },
+            (_, None) => self.response(&order, OrderStatus::REJECTED, ordered, vec![], timestamp),
+        }
+    }
+
+    pub fn match_orders(&mut self, model: &FillModel, candle: &Candle) -> Vec<OrderResponse> {
+        // Runs resting orders against the candle and answers for those which changed, in
+        // order of placement. Limits fill as the fill model says, stops trigger at their
+        // stop price and stop limits become limits marketable at it or resting
+        let timestamp = candle.timestamp();
+        let mut responses = vec![];
+        for Resting { order, triggered } in std::mem::take(&mut self.orders) {
+            let side = order.get_side();
+            let ordered = self.ordered(&order, self.ratio_a_to_b);
+            let waiting = order.get_stop_price().is_some() && !triggered;
+            let trigger = if waiting {
+                trigger_price(&order, candle.open(), candle.high(), candle.low())
+            } else {
+                None
+            };
+            let response = match (waiting, trigger, order.get_price()) {
+                (false, _, Some(limit)) => match model.limit_price(side, limit, candle) {
+                    Some(price) => self.trade(&order, price, timestamp),
+                    None => {
+                        self.orders.push(Resting { order, triggered });
+                        continue;
+                    }
+                },
+                (true, Some(stop), None) => {
+                    let price = model.market_price(side, stop, ordered, candle);
+                    self.trade(&order, price, timestamp)
+                }
+                (true, Some(stop), Some(limit)) => match model.marketable(side, limit, stop) {
+                    Some(price) => self.trade(&order, price, timestamp),
+                    None => self.rest(order, true, ordered, timestamp),
+                },
+                _ => {
+                    self.orders.push(Resting { order, triggered });
+                    continue;
+                }
+            };
+            responses.push(response);
+        }
+        responses
+    }
+
+    pub fn get_open_orders(&self) -> Vec<Order> {
+        self.orders
+            .iter()
+            .map(|resting| resting.order.clone())
+            .collect()
+    }
+    pub fn cancel(&mut self, client_order_id: &str) -> Option<Order> {
+        let index = self.orders.iter().position(|resting| {
+            resting.order.get_new_client_order_id().as_deref() == Some(client_order_id)
+        })?;
+        Some(self.orders.remove(index).order)
+    }
+    pub fn cancel_all(&mut self) -> Vec<Order> {
+        self.orders.drain(..).map(|resting| resting.order).collect()
+    }
+    pub fn equity(&self) -> f64 {
+        // Value of both currencies in B
+        self.currency_b_amount + self.a_in_b()
+    }
+    pub fn set_exposure(&mut self, fraction: f64) {
+        // Buys or sells A until it is the given fraction of equity. Spot market can't
+        // borrow, so the fraction is kept between 0 and 1
+        let target = self.equity() * fraction.clamp(0.0, 1.0) * self.ratio_b_to_a;
+        let step = |amount: f64| {
+            if self.step_size > 0.0 {
+                self.step_size * (amount / self.step_size).floor()
+            } else {
+                amount
+            }
+        };
+        if target > self.currency_a_amount {
+            let amount = step(target - self.currency_a_amount);
+            if amount > 0.0 && amount >= self.min_a_transaction {
+                self.buy(amount);
+            }
+        } else if target <= 0.0 {
+            self.sell_max();
+        } else {
+            let amount = step(self.currency_a_amount - target);
+            if amount > 0.0 && amount >= self.min_a_transaction {
+                self.sell(amount);
+            }
         }
     }
 }
 #[cfg(test)]
 mod test {
-    // use super::*;
+    use super::*;
+    use crate::clock::Clock;
+
+    #[test]
+    fn signal_details() {
+        let signal = Signal::long()
+            .fraction(1.5)
+            .limit(99.0)
+            .stop_loss(95.0)
+            .take_profit(110.0)
+            .trailing_stop(0.05)
+            .tag("breakout");
+        let intent = signal.get_intent().unwrap();
+        assert_eq!(intent.get_fraction(), 1.0);
+        assert_eq!(intent.get_limit(), Some(99.0));
+        assert_eq!(intent.get_stop_loss(), Some(95.0));
+        assert_eq!(intent.get_take_profit(), Some(110.0));
+        assert_eq!(intent.get_trailing_stop(), Some(0.05));
+        assert_eq!(intent.get_tag(), Some("breakout".to_string()));
+        assert_eq!(Signal::short().get_intent().unwrap().get_fraction(), 1.0);
+        // Exits have nothing to set
+        assert_eq!(Signal::ExitLong.fraction(0.5), Signal::ExitLong);
+        assert!(Signal::Flat.get_intent().is_none());
+    }
+
+    #[test]
+    fn exposure() {
+        let mut market = Market::new(0.0, 1000.0, 10.0, 0.1, 0.1, 0.0);
+        market.set_exposure(0.5);
+        assert_eq!(market.get_a_amount(), 50.0);
+        assert_eq!(market.get_b_amount(), 500.0);
+        // Amounts are rounded down to the step size
+        market.set_exposure(0.257);
+        assert!((market.get_a_amount() - 25.8).abs() < 1e-9);
+        market.set_exposure(2.0);
+        assert!((market.get_a_amount() - 100.0).abs() < 1e-9);
+        market.set_exposure(0.0);
+        assert_eq!(market.get_a_amount(), 0.0);
+        assert!((market.equity() - 1000.0).abs() < 1e-9);
+    }
+
+    fn result(response: &OrderResponse) -> (OrderStatus, f64, Option<f64>) {
+        match response {
+            OrderResponse::Full(full) => (
+                full.result.status,
+                full.result.executed_qty,
+                full.fills.first().map(|fill| fill.price),
+            ),
+            _ => panic!("Simulated market answers with FULL response"),
+        }
+    }
+
+    fn candle(timestamp: u64, open: f64, high: f64, low: f64) -> Candle {
+        Candle::new(timestamp, open, high, low, open, 1.0)
+    }
+
+    #[test]
+    fn resting_orders() {
+        let clock = Clock::new();
+        let model = FillModel::new();
+        let now = candle(0, 100.0, 100.0, 100.0);
+        let mut market = Market::new(10.0, 1000.0, 100.0, 0.0, 0.0, 0.0);
+        // Limit waits until the price trades through it
+        let limit = Order::limit("X", OrderSide::BUY, 95.0, 2.0)
+            .build(&clock)
+            .unwrap();
+        let placed = market.place(limit, &model, &now);
+        assert_eq!(result(&placed), (OrderStatus::NEW, 0.0, None));
+        assert!(market
+            .match_orders(&model, &candle(1, 100.0, 101.0, 95.0))
+            .is_empty());
+        let filled = market.match_orders(&model, &candle(2, 99.0, 99.0, 94.0));
+        assert_eq!(result(&filled[0]), (OrderStatus::FILLED, 2.0, Some(95.0)));
+        assert_eq!(market.get_a_amount(), 12.0);
+        // Stop triggers at its price, or at the open when the price gaps over it
+        let stop = Order::stop_loss("X", OrderSide::SELL, 90.0, 1.0);
+        market.place(stop.clone().build(&clock).unwrap(), &model, &now);
+        let filled = market.match_orders(&model, &candle(3, 95.0, 96.0, 89.0));
+        assert_eq!(result(&filled[0]), (OrderStatus::FILLED, 1.0, Some(90.0)));
+        market.place(stop.build(&clock).unwrap(), &model, &now);
+        let filled = market.match_orders(&model, &candle(4, 85.0, 86.0, 84.0));
+        assert_eq!(result(&filled[0]).2, Some(85.0));
+        // Take profit sell above the price would trigger right away
+        let target = Order::take_profit("X", OrderSide::SELL, 99.0, 1.0)
+            .build(&clock)
+            .unwrap();
+        assert_eq!(
+            result(&market.place(target, &model, &now)).0,
+            OrderStatus::REJECTED
+        );
+        // Stop limit which can't fill at the stop price waits as a limit
+        let stop_limit = Order::stop_loss_limit("X", OrderSide::SELL, 90.0, 91.0, 1.0)
+            .build(&clock)
+            .unwrap();
+        market.place(stop_limit, &model, &now);
+        let triggered = market.match_orders(&model, &candle(5, 95.0, 95.0, 89.0));
+        assert_eq!(result(&triggered[0]).0, OrderStatus::NEW);
+        assert_eq!(market.get_open_orders().len(), 1);
+        let filled = market.match_orders(&model, &candle(6, 90.0, 92.0, 88.0));
+        assert_eq!(result(&filled[0]), (OrderStatus::FILLED, 1.0, Some(91.0)));
+        // Cancelled order is gone
+        let limit = Order::limit("X", OrderSide::SELL, 120.0, 1.0)
+            .client_order_id("take")
+            .build(&clock)
+            .unwrap();
+        market.place(limit, &model, &now);
+        assert!(market.cancel("take").is_some());
+        assert!(market.get_open_orders().is_empty());
+        assert!(market
+            .match_orders(&model, &candle(7, 120.0, 130.0, 120.0))
+            .is_empty());
+    }
+
+    #[test]
+    fn time_in_force() {
+        let clock = Clock::new();
+        let model = FillModel::new();
+        let now = candle(0, 100.0, 100.0, 100.0);
+        let mut market = Market::new(0.0, 1000.0, 100.0, 0.0, 0.0, 0.0);
+        let order = |price: f64, quantity: f64, tif: TimeInForce| {
+            Order::limit("X", OrderSide::BUY, price, quantity)
+                .tif(tif)
+                .build(&clock)
+                .unwrap()
+        };
+        // Immediate orders don't rest
+        let placed = market.place(order(95.0, 1.0, TimeInForce::IOC), &model, &now);
+        assert_eq!(result(&placed), (OrderStatus::EXPIRED, 0.0, None));
+        assert!(market.get_open_orders().is_empty());
+        // Fill or kill fills whole or not at all, immediate or cancel takes what it can
+        let placed = market.place(order(105.0, 20.0, TimeInForce::FOK), &model, &now);
+        assert_eq!(result(&placed), (OrderStatus::EXPIRED, 0.0, None));
+        assert_eq!(market.get_b_amount(), 1000.0);
+        let placed = market.place(order(105.0, 20.0, TimeInForce::IOC), &model, &now);
+        assert_eq!(result(&placed), (OrderStatus::EXPIRED, 10.0, Some(100.0)));
+        // Maker only order is refused when it would take
+        let maker = Order::limit_maker("X", OrderSide::SELL, 99.0, 1.0)
+            .build(&clock)
+            .unwrap();
+        assert_eq!(
+            result(&market.place(maker, &model, &now)).0,
+            OrderStatus::REJECTED
+        );
+        let maker = Order::limit_maker("X", OrderSide::SELL, 101.0, 1.0)
+            .build(&clock)
+            .unwrap();
+        assert_eq!(
+            result(&market.place(maker, &model, &now)).0,
+            OrderStatus::NEW
+        );
+    }
+
+    fn journal(
+        start: f64,
+        equity: &[f64],
+        executions: &[(usize, OrderSide, f64, f64, f64)],
+    ) -> Journal {
+        // One candle a year, closing at a tenth of the equity
+        let mut journal = Journal::new().with_start_equity(start);
+        for (i, value) in equity.iter().enumerate() {
+            let timestamp = i as u64 * YEAR_MS as u64;
+            for &(_, side, price, quantity, fee) in executions.iter().filter(|e| e.0 == i) {
+                journal.put_execution(Execution::new(timestamp, side, price, quantity, fee));
+            }
+            let price = value / 10.0;
+            journal.put(Event::new(
+                timestamp as usize,
+                Signal::Sleep,
+                Market::new(0.0, *value, price, 0.0, 0.0, 0.0),
+                Candle::new(timestamp, price, price, price, price, 1.0),
+            ));
+        }
+        journal
+    }
+
+    #[test]
+    fn stats() {
+        let journal = journal(
+            1000.0,
+            &[1000.0, 1100.0, 990.0, 1210.0],
+            &[
+                (0, OrderSide::BUY, 100.0, 10.0, 1.0),
+                (1, OrderSide::SELL, 110.0, 9.99, 0.0),
+                (2, OrderSide::BUY, 110.0, 10.0, 0.0),
+                (3, OrderSide::SELL, 99.0, 10.0, 0.0),
+            ],
+        );
+        let mut stats = Stats::init();
+        stats.calculate(journal);
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        assert!(close(stats.get_chg_passive(), 0.21));
+        assert!(close(stats.get_chg_active(), 0.21));
+        assert_eq!(stats.get_trades(), 2);
+        assert!(close(stats.get_avg_in_pos(), 1.0));
+        assert!(close(stats.get_exposure(), 0.5));
+        // Fee paid in A on the buy is part of the gain already
+        assert!(close(stats.get_avg_gain(), 98.9));
+        assert!(close(stats.get_avg_gain_pct(), 0.0989));
+        assert!(close(stats.get_avg_loss(), 110.0));
+        assert!(close(stats.get_avg_loss_pct(), 0.1));
+        assert!(close(stats.get_cum_gain(), 98.9));
+        assert!(close(stats.get_cum_loss_pct(), 0.11));
+        assert!(close(stats.get_cum_fees(), 1.0));
+        assert!(close(stats.get_win_rate(), 0.5));
+        assert!(close(stats.get_profit_factor(), 98.9 / 110.0));
+        assert!(close(stats.get_expectancy(), -5.55));
+        // From 1100 down to 990, recovered two candles after the peak
+        assert!(close(stats.get_max_drawdown(), 0.1));
+        assert_eq!(stats.get_max_drawdown_duration(), 2 * YEAR_MS as u64);
+        assert!((stats.get_sharpe() - 0.557711).abs() < 1e-6);
+        assert!((stats.get_sortino() - 1.283001).abs() < 1e-6);
+        assert!((stats.get_calmar() - 0.656022).abs() < 1e-6);
+    }
+
+    #[test]
+    fn stats_start_equity() {
+        // Fee of the buy on the first candle is already out of its equity
+        let executions = [(0, OrderSide::BUY, 100.0, 10.0, 1.0)];
+        let mut stats = Stats::init();
+        stats.calculate(journal(1001.0, &[1000.0, 1001.0], &executions));
+        assert_eq!(stats.get_chg_active(), 0.0);
+        let executions = [
+            (0, OrderSide::BUY, 100.0, 10.0, 0.0),
+            (1, OrderSide::SELL, 90.0, 10.0, 0.0),
+        ];
+        stats.calculate(journal(1000.0, &[800.0, 900.0], &executions));
+        assert!((stats.get_chg_active() + 0.1).abs() < 1e-9);
+        assert!((stats.get_cum_loss_pct() - 0.1).abs() < 1e-9);
+        // Without it the first candle is the start
+        let mut journal = journal(1000.0, &[800.0, 900.0], &executions);
+        journal.start_equity = None;
+        stats.calculate(journal);
+        assert!((stats.get_chg_active() - 0.125).abs() < 1e-9);
+    }
+
+    #[test]
+    fn stats_edges() {
+        // Nothing to calculate from
+        let mut stats = Stats::init();
+        stats.calculate(Journal::new());
+        assert_eq!(stats.get_trades(), 0);
+        // Only winners, drawdown never recovered
+        let journal = journal(
+            1000.0,
+            &[1000.0, 1200.0, 900.0, 1000.0],
+            &[
+                (0, OrderSide::BUY, 100.0, 1.0, 0.0),
+                (1, OrderSide::SELL, 120.0, 1.0, 0.0),
+                (2, OrderSide::BUY, 90.0, 1.0, 0.0),
+            ],
+        );
+        stats.calculate(journal);
+        assert_eq!(stats.get_trades(), 1);
+        assert_eq!(stats.get_profit_factor(), f64::INFINITY);
+        assert_eq!(stats.get_avg_loss(), 0.0);
+        assert!((stats.get_max_drawdown() - 0.25).abs() < 1e-9);
+        assert_eq!(stats.get_max_drawdown_duration(), 2 * YEAR_MS as u64);
+        // Open trade counts in the exposure
+        assert!((stats.get_exposure() - 0.75).abs() < 1e-9);
+        assert_eq!(stats.get_calmar(), 0.0);
+    }
+
+    #[test]
+    fn buy_sell_results() {
+        let mut market = Market::new(0.0, 100.0, 4.0, 0.0, 0.0, 0.0);
+        assert!(market.buy(10.0));
+        assert!(!market.buy(99999.999));
+        assert!(!market.buy(-123.0));
+        assert!(!market.sell(99999.999));
+        assert!(!market.sell(-123.0));
+        assert_eq!(market.get_a_amount(), 10.0);
+        assert_eq!(market.get_b_amount(), 60.0);
+        assert!(market.sell(10.0));
+        assert_eq!(market.get_b_amount(), 100.0);
+    }
 
     // #[test]
     // fn market_buy_sell() {
-        //        let mut market = Market::new(0.0, 100.0, 4.0, 0.001);
-        //        println!("{:#?}", &market);
-        //        market.buy(10.0);
-        //        market.sell(1.0);
-        //        market.sell(9.0);
-        //        market.buy(99999.999);
-        //        market.buy(-123.0);
-        //        market.sell(99999.999);
-        //        market.sell(-123.0);
-        //        println!("{:#?}", &market);
+    //        let mut market = Market::new(0.0, 100.0, 4.0, 0.001);
+    //        println!("{:#?}", &market);
+    //        market.buy(10.0);
+    //        market.sell(1.0);
+    //        market.sell(9.0);
+    //        market.buy(99999.999);
+    //        market.buy(-123.0);
+    //        market.sell(99999.999);
+    //        market.sell(-123.0);
+    //        println!("{:#?}", &market);
     // }
 
     // #[test]
     // fn conversion() {
-        /*
-        let mut market = Market::new(0.0, 100.0, 4.0, 0.001);
+    /*
+    let mut market = Market::new(0.0, 100.0, 4.0, 0.001);
 
-        println!("\nA={}, A in B={}", market.get_a_amount(), market.a_in_b());
+    println!("\nA={}, A in B={}", market.get_a_amount(), market.a_in_b());
 
-        println!("B={}, B in A={}", market.get_b_amount(), market.b_in_a());
-        */
+    println!("B={}, B in A={}", market.get_b_amount(), market.b_in_a());
+    */
     // }
 }
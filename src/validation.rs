@@ -0,0 +1,387 @@
+use rust_decimal::prelude::*;
+
+use crate::error::{Error, Result};
+use crate::exchange_info::{LotSize, PriceFilter, SymbolInfo};
+use crate::types::{Order, OrderType, TimeInForce};
+
+fn to_decimal(value: f64) -> Decimal {
+    Decimal::from_f64(value).unwrap_or_default()
+}
+
+pub fn round_down_to_step(value: f64, step: f64) -> f64 {
+    // Rounding is done on decimals, so 0.3 / 0.1 does not end up as 2.9999
+    if step <= 0.0 {
+        return value;
+    }
+    let step = to_decimal(step);
+    ((to_decimal(value) / step).floor() * step)
+        .to_f64()
+        .unwrap_or(0.0)
+}
+
+pub fn round_to_tick(value: f64, tick: f64) -> f64 {
+    // Nearest multiple of tick size
+    if tick <= 0.0 {
+        return value;
+    }
+    let tick = to_decimal(tick);
+    ((to_decimal(value) / tick).round() * tick)
+        .to_f64()
+        .unwrap_or(0.0)
+}
+
+fn invalid<T>(msg: String) -> Result<T> {
+    Err(Error::Validation(msg))
+}
+
+fn check_qty(name: &str, qty: f64, lot: Option<&LotSize>) -> Result<f64> {
+    let lot = match lot {
+        Some(lot) => lot,
+        None => return Ok(qty),
+    };
+    let rounded = round_down_to_step(qty, lot.step_size);
+    if rounded < lot.min_qty || rounded <= 0.0 {
+        return invalid(format!(
+            "{} {} is below minimal quantity {}",
+            name, rounded, lot.min_qty
+        ));
+    }
+    // max_qty of zero means there is no upper limit
+    if lot.max_qty > 0.0 && rounded > lot.max_qty {
+        return invalid(format!(
+            "{} {} is above maximal quantity {}",
+            name, rounded, lot.max_qty
+        ));
+    }
+    Ok(rounded)
+}
+
+fn check_price(name: &str, price: f64, filter: Option<&PriceFilter>) -> Result<f64> {
+    let filter = match filter {
+        Some(filter) => filter,
+        None => return Ok(price),
+    };
+    let rounded = round_to_tick(price, filter.tick_size);
+    // Limits set to zero are disabled
+    if rounded <= 0.0 || (filter.min_price > 0.0 && rounded < filter.min_price) {
+        return invalid(format!(
+            "{} {} is below minimal price {}",
+            name, rounded, filter.min_price
+        ));
+    }
+    if filter.max_price > 0.0 && rounded > filter.max_price {
+        return invalid(format!(
+            "{} {} is above maximal price {}",
+            name, rounded, filter.max_price
+        ));
+    }
+    Ok(rounded)
+}
+
+impl Order {
+    pub fn check_fields(&self) -> Result<()> {
+        // Which parameters are mandatory and which are not allowed depends on the order type
+        let order_type = self.get_type();
+        let (needs_tif, needs_price, needs_stop) = match order_type {
+            OrderType::LIMIT => (true, true, false),
+            OrderType::MARKET => (false, false, false),
+            OrderType::STOP_LOSS => (false, false, true),
+            OrderType::STOP_LOSS_LIMIT => (true, true, true),
+            OrderType::TAKE_PROFIT => (false, false, true),
+            OrderType::TAKE_PROFIT_LIMIT => (true, true, true),
+            OrderType::LIMIT_MAKER => (false, true, false),
+        };
+        let check = |present: bool, needed: bool, name: &str| {
+            if present && !needed {
+                invalid(format!("{:?} order does not accept {}", order_type, name))
+            } else if !present && needed {
+                invalid(format!("{:?} order requires {}", order_type, name))
+            } else {
+                Ok(())
+            }
+        };
+        check(self.get_time_in_force().is_some(), needs_tif, "timeInForce")?;
+        check(self.get_price().is_some(), needs_price, "price")?;
+        check(self.get_stop_price().is_some(), needs_stop, "stopPrice")?;
+
+        match (self.get_quantity(), self.get_quote_order_qty()) {
+            (Some(_), Some(_)) => {
+                invalid("Order can't have both quantity and quoteOrderQty".into())
+            }
+            (None, Some(_)) if order_type != OrderType::MARKET => invalid(format!(
+                "{:?} order does not accept quoteOrderQty",
+                order_type
+            )),
+            (None, None) => invalid(format!("{:?} order requires quantity", order_type)),
+            _ => Ok(()),
+        }?;
+
+        if self.get_iceberg_qty().is_some() && self.get_time_in_force() != Some(TimeInForce::GTC) {
+            return invalid("Iceberg orders require GTC timeInForce".into());
+        }
+        Ok(())
+    }
+
+    pub fn normalize(&self, info: &SymbolInfo, market_price: Option<f64>) -> Result<Order> {
+        // Returns copy of the order rounded to symbol filters, or reason why exchange would reject it
+        // market_price is used for MIN_NOTIONAL of orders which don't carry any price
+        self.check_fields()?;
+        let order_type = self.get_type();
+        if !info.order_types.is_empty() && !info.order_types.contains(&order_type) {
+            return invalid(format!(
+                "{:?} orders are not allowed on {}",
+                order_type, info.symbol
+            ));
+        }
+
+        let quantity = match self.get_quantity() {
+            Some(qty) => {
+                let mut qty = check_qty("quantity", qty, info.lot_size())?;
+                if order_type == OrderType::MARKET {
+                    // Market lot filter with zero step only limits the size
+                    qty = check_qty("quantity", qty, info.market_lot_size())?;
+                }
+                Some(qty)
+            }
+            None => None,
+        };
+        let iceberg_qty = match self.get_iceberg_qty() {
+            Some(qty) => Some(check_qty("icebergQty", qty, info.lot_size())?),
+            None => None,
+        };
+        let price = match self.get_price() {
+            Some(price) => Some(check_price("price", price, info.price_filter())?),
+            None => None,
+        };
+        let stop_price = match self.get_stop_price() {
+            Some(price) => Some(check_price("stopPrice", price, info.price_filter())?),
+            None => None,
+        };
+
+        if let Some(min) = info.min_notional() {
+            let is_market = price.is_none();
+            let reference = price.or(stop_price).or(market_price);
+            let notional = match (quantity, self.get_quote_order_qty(), reference) {
+                (_, Some(quote), _) => Some(quote),
+                (Some(qty), None, Some(price)) => Some(qty * price),
+                _ => None,
+            };
+            if let Some(notional) = notional {
+                if (!is_market || min.apply_to_market) && notional < min.min_notional {
+                    return invalid(format!(
+                        "Notional {} is below minimal notional {}",
+                        notional, min.min_notional
+                    ));
+                }
+            }
+        }
+
+        Ok(Order::new(
+            self.get_symbol(),
+            self.get_side(),
+            order_type,
+            self.get_time_in_force(),
+            quantity,
+            self.get_quote_order_qty(),
+            price,
+            self.get_new_client_order_id(),
+            stop_price,
+            iceberg_qty,
+            Some(self.get_resp_type()),
+            self.get_recv_window(),
+            self.get_timestamp(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::exchange_info::{Filter, MinNotional};
+    use crate::types::OrderSide;
+
+    fn btcusdt() -> SymbolInfo {
+        SymbolInfo {
+            symbol: "BTCUSDT".to_string(),
+            status: "TRADING".to_string(),
+            base_asset: "BTC".to_string(),
+            base_asset_precision: 8,
+            quote_asset: "USDT".to_string(),
+            quote_asset_precision: 8,
+            order_types: vec![
+                OrderType::LIMIT,
+                OrderType::MARKET,
+                OrderType::STOP_LOSS_LIMIT,
+            ],
+            iceberg_allowed: true,
+            oco_allowed: true,
+            is_spot_trading_allowed: true,
+            filters: vec![
+                Filter::Price(PriceFilter {
+                    min_price: 0.01,
+                    max_price: 1000000.0,
+                    tick_size: 0.01,
+                }),
+                Filter::LotSize(LotSize {
+                    min_qty: 0.000001,
+                    max_qty: 9000.0,
+                    step_size: 0.000001,
+                }),
+                Filter::MinNotional(MinNotional {
+                    min_notional: 10.0,
+                    apply_to_market: true,
+                    avg_price_mins: 5,
+                }),
+            ],
+        }
+    }
+
+    fn order(
+        r#type: OrderType,
+        tif: Option<TimeInForce>,
+        qty: Option<f64>,
+        quote: Option<f64>,
+        price: Option<f64>,
+        stop: Option<f64>,
+    ) -> Order {
+        Order::new(
+            "BTCUSDT".to_string(),
+            OrderSide::BUY,
+            r#type,
+            tif,
+            qty,
+            quote,
+            price,
+            None,
+            stop,
+            None,
+            None,
+            None,
+            1,
+        )
+    }
+
+    #[test]
+    fn rounding() {
+        assert_eq!(round_down_to_step(0.0033333, 0.000001), 0.003333);
+        assert_eq!(round_down_to_step(0.3, 0.1), 0.3);
+        assert_eq!(round_down_to_step(5.0, 0.0), 5.0);
+        assert_eq!(round_to_tick(30000.126, 0.01), 30000.13);
+        assert_eq!(round_to_tick(30000.124, 0.01), 30000.12);
+    }
+
+    #[test]
+    fn required_fields() {
+        let gtc = Some(TimeInForce::GTC);
+        assert!(
+            order(OrderType::LIMIT, gtc, Some(1.0), None, Some(1.0), None)
+                .check_fields()
+                .is_ok()
+        );
+        assert!(
+            order(OrderType::LIMIT, None, Some(1.0), None, Some(1.0), None)
+                .check_fields()
+                .is_err()
+        );
+        assert!(order(OrderType::LIMIT, gtc, Some(1.0), None, None, None)
+            .check_fields()
+            .is_err());
+        assert!(order(OrderType::MARKET, None, None, Some(20.0), None, None)
+            .check_fields()
+            .is_ok());
+        assert!(order(OrderType::MARKET, gtc, Some(1.0), None, None, None)
+            .check_fields()
+            .is_err());
+        assert!(order(OrderType::MARKET, None, None, None, None, None)
+            .check_fields()
+            .is_err());
+        assert!(order(
+            OrderType::STOP_LOSS_LIMIT,
+            gtc,
+            Some(1.0),
+            None,
+            Some(1.0),
+            None
+        )
+        .check_fields()
+        .is_err());
+        assert!(
+            order(OrderType::STOP_LOSS, None, Some(1.0), None, None, Some(1.0))
+                .check_fields()
+                .is_ok()
+        );
+        assert!(order(
+            OrderType::LIMIT_MAKER,
+            gtc,
+            Some(1.0),
+            None,
+            Some(1.0),
+            None
+        )
+        .check_fields()
+        .is_err());
+    }
+
+    #[test]
+    fn normalize_rounds_to_filters() {
+        let o = order(
+            OrderType::STOP_LOSS_LIMIT,
+            Some(TimeInForce::GTC),
+            Some(0.0012345678),
+            None,
+            Some(29999.999),
+            Some(30100.004),
+        )
+        .normalize(&btcusdt(), None)
+        .unwrap();
+        assert_eq!(o.get_quantity(), Some(0.001234));
+        assert_eq!(o.get_price(), Some(30000.0));
+        assert_eq!(o.get_stop_price(), Some(30100.0));
+    }
+
+    #[test]
+    fn normalize_rejects() {
+        let info = btcusdt();
+        // Below min notional
+        assert!(order(
+            OrderType::LIMIT,
+            Some(TimeInForce::GTC),
+            Some(0.0001),
+            None,
+            Some(30000.0),
+            None
+        )
+        .normalize(&info, None)
+        .is_err());
+        assert!(
+            order(OrderType::MARKET, None, Some(0.0001), None, None, None)
+                .normalize(&info, Some(30000.0))
+                .is_err()
+        );
+        assert!(order(OrderType::MARKET, None, None, Some(5.0), None, None)
+            .normalize(&info, None)
+            .is_err());
+        // Quantity rounded down to zero
+        assert!(
+            order(OrderType::MARKET, None, Some(0.0000001), None, None, None)
+                .normalize(&info, None)
+                .is_err()
+        );
+        // Order type not allowed on symbol
+        assert!(order(
+            OrderType::TAKE_PROFIT,
+            None,
+            Some(1.0),
+            None,
+            None,
+            Some(1.0)
+        )
+        .normalize(&info, None)
+        .is_err());
+        assert!(
+            order(OrderType::MARKET, None, Some(0.001), None, None, None)
+                .normalize(&info, Some(30000.0))
+                .is_ok()
+        );
+    }
+}